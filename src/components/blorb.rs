@@ -0,0 +1,153 @@
+use log::debug;
+
+use super::InfocomError;
+
+/// The 32-bit value at `offset`, or an error if the file ends first
+fn read_long(data: &[u8], offset: usize) -> Result<u32, InfocomError> {
+    match data.get(offset..offset + 4) {
+        Some(b) => Ok(((b[0] as u32) << 24) | ((b[1] as u32) << 16) | ((b[2] as u32) << 8) | b[3] as u32),
+        None => Err(InfocomError::API(format!("Blorb file is truncated at offset {}", offset)))
+    }
+}
+
+/// An entry in the resource index
+struct Resource {
+    usage: [u8; 4],
+    number: u32,
+    start: usize
+}
+
+/// A Blorb resource file.  Only the resource index is parsed; resources are read from
+/// the file data when they're requested.
+pub struct Blorb {
+    data: Vec<u8>,
+    index: Vec<Resource>
+}
+
+impl Blorb {
+    /// True if `data` starts with the Blorb `FORM`/`IFRS` magic
+    pub fn is_blorb(data: &[u8]) -> bool {
+        data.len() >= 12 && &data[0..4] == b"FORM" && &data[8..12] == b"IFRS"
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Blorb, InfocomError> {
+        if !Blorb::is_blorb(&data) {
+            return Err(InfocomError::API(format!("Not a Blorb resource file")))
+        }
+
+        let end = std::cmp::min(data.len(), 8 + read_long(&data, 4)? as usize);
+        let mut offset = 12;
+        while offset + 8 <= end {
+            let length = read_long(&data, offset + 4)? as usize;
+            let start = offset + 8;
+            if start + length > end {
+                return Err(InfocomError::API(format!("Blorb chunk {} extends beyond end of file", String::from_utf8_lossy(&data[offset..offset + 4]))))
+            }
+
+            // The resource index is the first chunk, but don't insist on it
+            if &data[offset..offset + 4] == b"RIdx" {
+                // A chunk too short for the count reads it from the following bytes, and
+                // fails the length check
+                let count = read_long(&data, start)? as usize;
+                if 4 + (count * 12) > length {
+                    return Err(InfocomError::API(format!("Blorb resource index has {} entries but is only {} bytes", count, length)))
+                }
+
+                let mut index = Vec::new();
+                for i in 0..count {
+                    let entry = start + 4 + (i * 12);
+                    let mut usage = [0; 4];
+                    usage.copy_from_slice(&data[entry..entry + 4]);
+                    index.push(Resource { usage, number: read_long(&data, entry + 4)?, start: read_long(&data, entry + 8)? as usize });
+                }
+                return Ok(Blorb { data, index })
+            }
+
+            debug!("Skipping Blorb chunk {}", String::from_utf8_lossy(&data[offset..offset + 4]));
+            offset = start + length + (length % 2);
+        }
+
+        Err(InfocomError::API(format!("Blorb file has no resource index")))
+    }
+
+    /// The chunk type and data of a resource.  Resources stored as IFF files, such as
+    /// AIFF sounds, are returned whole, including the `FORM` header.
+    pub fn resource(&self, usage: &[u8; 4], number: u32) -> Option<(&[u8], &[u8])> {
+        let r = self.index.iter().find(|r| &r.usage == usage && r.number == number)?;
+        if r.start + 8 > self.data.len() {
+            return None
+        }
+
+        let id = &self.data[r.start..r.start + 4];
+        let length = read_long(&self.data, r.start + 4).ok()? as usize;
+        if r.start + 8 + length > self.data.len() {
+            return None
+        }
+
+        if id == b"FORM" {
+            // The form type is the first 4 bytes of the chunk data
+            if length < 4 {
+                return None
+            }
+            Some((&self.data[r.start + 8..r.start + 12], &self.data[r.start..r.start + 8 + length]))
+        } else {
+            Some((id, &self.data[r.start + 8..r.start + 8 + length]))
+        }
+    }
+
+    /// The Z-code story image, if the executable resource is a `ZCOD` chunk
+    pub fn executable(&self) -> Option<&[u8]> {
+        match self.resource(b"Exec", 0) {
+            Some((id, data)) if id == b"ZCOD" => Some(data),
+            _ => None
+        }
+    }
+
+    /// Sound resource `number` as an AIFF or Ogg file
+    pub fn sound(&self, number: u32) -> Option<&[u8]> {
+        self.resource(b"Snd ", number).map(|(_, data)| data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_util::blorb;
+
+    #[test]
+    fn resources_are_found_through_the_index() {
+        let sound = b"FORM\0\0\0\x04AIFF";
+        let data = blorb(&[(b"Exec", 0, 0), (b"Snd ", 3, 1)], &[(b"ZCOD", &[5, 0, 1]), (b"FORM", &sound[8..])]);
+        let b = Blorb::from_bytes(data).unwrap();
+        assert_eq!(b.executable(), Some(&[5, 0, 1][..]));
+        assert_eq!(b.resource(b"Snd ", 3).map(|(id, _)| id), Some(&b"AIFF"[..]));
+        assert_eq!(b.sound(3), Some(&sound[..]));
+        assert_eq!(b.sound(4), None);
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let data = blorb(&[(b"Exec", 0, 0)], &[(b"ZCOD", &[5; 16])]);
+        for length in 0..data.len() {
+            if let Ok(b) = Blorb::from_bytes(data[..length].to_vec()) {
+                assert_eq!(b.executable(), None, "{} bytes", length);
+            }
+        }
+
+        // A resource index too short to hold its own entry count, at the end of the file
+        // and followed by another chunk
+        let mut data = b"FORM\0\0\0\x0EIFRSRIdx\0\0\0\x02\0\0".to_vec();
+        assert!(Blorb::from_bytes(data.clone()).is_err());
+        data.extend_from_slice(b"Snd \0\0\0\0");
+        data[7] = 0x16;
+        assert!(Blorb::from_bytes(data).is_err());
+
+        // Resources that start past the end of the file or are too short for a form type
+        let mut data = blorb(&[(b"Snd ", 1, 0), (b"Snd ", 2, 0)], &[(b"FORM", &[0, 0])]);
+        // The index entries start at byte 24, so the second entry's start is at 44
+        data[44..48].copy_from_slice(&1000u32.to_be_bytes());
+        let b = Blorb::from_bytes(data).unwrap();
+        assert_eq!(b.sound(1), None);
+        assert_eq!(b.sound(2), None);
+    }
+}
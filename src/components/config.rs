@@ -0,0 +1,113 @@
+use std::env;
+
+use log::{warn, Level};
+
+const DEFAULT_REDIS_URL: &str = "redis://localhost";
+const DEFAULT_SESSION_TTL: usize = 3600;
+const DEFAULT_LOOP_GUARD: usize = 10_000_000;
+const DEFAULT_MAX_STORY_SIZE: usize = 1024 * 1024;
+const DEFAULT_LOG_LEVEL: Level = Level::Warn;
+
+/// Runtime configuration for the REST service
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub redis_url: String,
+    pub session_ttl: usize,
+    pub loop_guard: Option<usize>,
+    pub max_story_size: usize,
+}
+
+/// The loop guard is on by default in debug builds only
+fn default_loop_guard() -> Option<usize> {
+    if cfg!(debug_assertions) {
+        Some(DEFAULT_LOOP_GUARD)
+    } else {
+        None
+    }
+}
+
+/// Map a level name such as "info" or "DEBUG" to a log level
+pub fn parse_log_level(name: &str) -> Option<Level> {
+    match name.trim().to_lowercase().as_str() {
+        "error" => Some(Level::Error),
+        "warn" | "warning" => Some(Level::Warn),
+        "info" => Some(Level::Info),
+        "debug" => Some(Level::Debug),
+        "trace" => Some(Level::Trace),
+        _ => None
+    }
+}
+
+/// The log level from `RUST_LOG`, defaulting to warnings.  This is read separately from
+/// `Config` because the logger must be running before configuration problems are reported.
+pub fn log_level() -> Level {
+    match env::var("RUST_LOG") {
+        Ok(v) => parse_log_level(&v).unwrap_or(DEFAULT_LOG_LEVEL),
+        Err(_) => DEFAULT_LOG_LEVEL
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config { redis_url: String::from(DEFAULT_REDIS_URL), session_ttl: DEFAULT_SESSION_TTL, loop_guard: default_loop_guard(), max_story_size: DEFAULT_MAX_STORY_SIZE }
+    }
+}
+
+impl Config {
+    /// Read configuration from the `REDIS_URL`, `SESSION_TTL`, `LOOP_GUARD`, and `MAX_STORY_SIZE` environment
+    /// variables, falling back to the defaults for anything unset or invalid.
+    pub fn from_env() -> Config {
+        let redis_url = env::var("REDIS_URL").unwrap_or(String::from(DEFAULT_REDIS_URL));
+        let session_ttl = match env::var("SESSION_TTL") {
+            Ok(v) => match v.parse() {
+                Ok(ttl) => ttl,
+                Err(_) => {
+                    warn!("Invalid SESSION_TTL '{}', using {}", v, DEFAULT_SESSION_TTL);
+                    DEFAULT_SESSION_TTL
+                }
+            },
+            Err(_) => DEFAULT_SESSION_TTL
+        };
+
+        let loop_guard = match env::var("LOOP_GUARD") {
+            Ok(v) => match v.parse() {
+                Ok(0) => None,
+                Ok(n) => Some(n),
+                Err(_) => {
+                    warn!("Invalid LOOP_GUARD '{}', using default", v);
+                    default_loop_guard()
+                }
+            },
+            Err(_) => default_loop_guard()
+        };
+
+        let max_story_size = match env::var("MAX_STORY_SIZE") {
+            Ok(v) => match v.parse() {
+                Ok(size) => size,
+                Err(_) => {
+                    warn!("Invalid MAX_STORY_SIZE '{}', using {}", v, DEFAULT_MAX_STORY_SIZE);
+                    DEFAULT_MAX_STORY_SIZE
+                }
+            },
+            Err(_) => DEFAULT_MAX_STORY_SIZE
+        };
+
+        Config { redis_url, session_ttl, loop_guard, max_story_size }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_names_map_to_log_levels() {
+        assert_eq!(parse_log_level("info"), Some(Level::Info));
+        assert_eq!(parse_log_level("debug"), Some(Level::Debug));
+        assert_eq!(parse_log_level(" WARNING\n"), Some(Level::Warn));
+        assert_eq!(parse_log_level("Error"), Some(Level::Error));
+        assert_eq!(parse_log_level("trace"), Some(Level::Trace));
+        assert_eq!(parse_log_level("verbose"), None);
+        assert_eq!(parse_log_level(""), None);
+    }
+}
@@ -2,7 +2,8 @@ use std::collections::HashSet;
 use log::debug;
 
 use super::InfocomError;
-use super::memory::MemoryMap;
+use super::header::Header;
+use super::memory::{ MemoryMap, Version };
 use super::text::{ Decoder, Encoder };
 use super::state::FrameStack;
 
@@ -23,7 +24,11 @@ pub struct Word {
 
 impl Dictionary {
     pub fn new(mem: &MemoryMap) -> Result<Dictionary,InfocomError> {
-        let address = mem.get_word(0x08)? as usize;
+        Dictionary::at(mem, Header::new(mem)?.dictionary())
+    }
+
+    /// Read the dictionary at `address`, such as a game-supplied dictionary for `tokenise`
+    pub fn at(mem: &MemoryMap, address: usize) -> Result<Dictionary,InfocomError> {
         let decoder = Decoder::new(mem)?;
         let encoder = Encoder::new(mem)?;
 
@@ -34,24 +39,67 @@ impl Dictionary {
         }
 
         let entry_length = mem.get_byte(address + 1 + separator_count)? as usize;
-        let entry_count = mem.get_word(address + 2 + separator_count)? as usize;
+        // User dictionaries may have a negative count, meaning the entries are unsorted
+        let entry_count = (mem.get_signed_word(address + 2 + separator_count)? as i32).abs() as usize;
         let entries_address = address + 4 + separator_count;
         
         Ok(Dictionary { address, separators, entry_length, entry_count, entries_address, encoder })
     }
 
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    pub fn separators(&self) -> &HashSet<char> {
+        &self.separators
+    }
+
+    pub fn entry_length(&self) -> usize {
+        self.entry_length
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entry_count
+    }
+
+    pub fn entries_address(&self) -> usize {
+        self.entries_address
+    }
+
+    /// Decode every dictionary entry.  The `position` of each `Word` is the entry address.
+    pub fn entries(&self, mem: &MemoryMap) -> Result<Vec<Word>,InfocomError> {
+        let decoder = Decoder::new(mem)?;
+        let text_length = match mem.version {
+            Version::V(1) | Version::V(2) | Version::V(3) => 4,
+            _ => 6
+        };
+        let mut words = Vec::new();
+        for i in 0..self.entry_count {
+            let entry_address = self.entries_address + (i * self.entry_length);
+            let mut entry = Vec::new();
+            for j in 0..text_length {
+                entry.push(mem.get_byte(entry_address + j)?);
+            }
+            words.push(Word { text: decoder.decode_dictionary_entry(&entry)?, position: entry_address });
+        }
+
+        Ok(words)
+    }
+
     fn lookup_word(&self, mem: &MemoryMap, word: &str) -> Result<Option<u16>,InfocomError> {
-        // TODO: Version 5 support
+        // Entry text is 2 words before V4 and 3 words after, matching the encoded length
         let encoded_text = self.encoder.encode(word)?;
-        let entry = ((encoded_text[0] as u64) << 16) | encoded_text[1] as u64;
+        let entry = encoded_text.iter().fold(0, |e, w| (e << 16) | *w as u64);
 
         debug!("{:?} -> ${:012x}", encoded_text, entry);
 
         // TODO: Binary search this mother.InfocomError
         for i in 0..self.entry_count {
             let entry_address = self.entries_address + (i * self.entry_length);
-            let e = ((mem.get_word(entry_address)? as u64) << 16) |
-                    mem.get_word(entry_address + 2)? as u64;
+            let mut e:u64 = 0;
+            for j in 0..encoded_text.len() {
+                e = (e << 16) | mem.get_word(entry_address + (2 * j))? as u64;
+            }
             if entry == e {
                 return Ok(Some(entry_address as u16));
             }                    
@@ -60,7 +108,10 @@ impl Dictionary {
         Ok(None)
     }
         
-    pub fn analyze_text(&self, f: &mut FrameStack, text: &String, parse_table_address: usize) -> Result<(),InfocomError> {
+    /// Split `text` into words and write them to the parse table.  When `skip_unknown` is
+    /// set, the entries for words not in the dictionary are left untouched.  Word positions
+    /// are offsets into the text buffer, whose text starts at byte 1 before V5 and byte 2 after.
+    pub fn analyze_text(&self, f: &mut FrameStack, text: &String, parse_table_address: usize, skip_unknown: bool) -> Result<(),InfocomError> {
         let mut slice = text.as_str();
         let mut words:Vec<Word> = Vec::new();
         let mut offset = 0;
@@ -85,19 +136,67 @@ impl Dictionary {
 
         f.set_byte(parse_table_address + 1, words.len() as u8)?;
 
+        // Text starts at byte 1 of the text buffer before V5, after the count byte from V5
+        let text_start = if f.get_memory().is_v5_or_later() { 2 } else { 1 };
+
         for i in 0..words.len() {
             let addr = parse_table_address + 2 + (4 * i);
             if let Some(entry_address) = self.lookup_word(f.get_memory(), &words[i].text)? {
                 debug!("Found {} @ ${:04x}", words[i].text, entry_address);
                 f.set_word(addr, entry_address)?;
+            } else if skip_unknown {
+                debug!("{} not in dictionary, leaving parse entry", words[i].text);
+                continue;
             } else {
                 debug!("{} not in dictionary", words[i].text);
                 f.set_word(addr, 0)?;
             }
             f.set_byte(addr + 2, words[i].text.len() as u8)?;
-            f.set_byte(addr + 3, words[i].position as u8 + 2)?;
+            f.set_byte(addr + 3, words[i].position as u8 + text_start)?;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_util::{StoryBuilder, DICTIONARY};
+
+    #[test]
+    fn entries_decode_the_dictionary_words() {
+        let mem = StoryBuilder::new(3).dictionary(&['.', ','], &["north", "lamp", "take"]).build();
+        let dictionary = Dictionary::new(&mem).unwrap();
+        assert_eq!(dictionary.address(), DICTIONARY);
+        assert_eq!(dictionary.separators(), &['.', ','].iter().cloned().collect());
+        assert_eq!(dictionary.entry_length(), 7);
+        assert_eq!(dictionary.entry_count(), 3);
+        assert_eq!(dictionary.entries_address(), DICTIONARY + 6);
+
+        let entries = dictionary.entries(&mem).unwrap();
+        let mut words: Vec<&str> = entries.iter().map(|w| w.text.as_str()).collect();
+        words.sort();
+        assert_eq!(words, vec!["lamp", "north", "take"]);
+        for (i, w) in entries.iter().enumerate() {
+            assert_eq!(w.position, DICTIONARY + 6 + (7 * i));
+        }
+    }
+
+    #[test]
+    fn word_positions_allow_for_the_text_buffer_layout() {
+        let mut positions = Vec::new();
+        for version in &[3, 5] {
+            let mut mem = StoryBuilder::new(*version).dictionary(&[], &["north"]).byte(0x780, 5).build();
+            let dictionary = Dictionary::new(&mem).unwrap();
+            let mut state = FrameStack::new(&mut mem).unwrap();
+            dictionary.analyze_text(&mut state, &String::from("go north"), 0x780, false).unwrap();
+            let memory = state.get_memory();
+            assert_eq!(memory.get_byte(0x781).unwrap(), 2);
+            positions.push((memory.get_byte(0x785).unwrap(), memory.get_byte(0x789).unwrap()));
+        }
+
+        // Text starts at byte 1 of the text buffer in V3 and byte 2 in V5
+        assert_eq!(positions, vec![(1, 4), (2, 5)]);
+    }
+}
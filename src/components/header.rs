@@ -0,0 +1,326 @@
+use super::InfocomError;
+use super::memory::{MemoryMap, Version};
+
+// Flags 1, versions 1-3
+pub const FLAGS1_STATUS_LINE_TIMED:u8 = 0x02;
+pub const FLAGS1_STORY_SPLIT:u8 = 0x04;
+pub const FLAGS1_STATUS_LINE_UNAVAILABLE:u8 = 0x10;
+pub const FLAGS1_SCREEN_SPLIT_AVAILABLE:u8 = 0x20;
+pub const FLAGS1_VARIABLE_PITCH_DEFAULT:u8 = 0x40;
+
+// Flags 1, versions 4+
+pub const FLAGS1_COLOURS_AVAILABLE:u8 = 0x01;
+pub const FLAGS1_PICTURES_AVAILABLE:u8 = 0x02;
+pub const FLAGS1_BOLD_AVAILABLE:u8 = 0x04;
+pub const FLAGS1_ITALIC_AVAILABLE:u8 = 0x08;
+pub const FLAGS1_FIXED_AVAILABLE:u8 = 0x10;
+pub const FLAGS1_SOUND_AVAILABLE:u8 = 0x20;
+pub const FLAGS1_TIMED_INPUT_AVAILABLE:u8 = 0x80;
+
+// Flags 2
+pub const FLAGS2_TRANSCRIPTING:u16 = 0x0001;
+pub const FLAGS2_FORCE_FIXED:u16 = 0x0002;
+pub const FLAGS2_REDRAW:u16 = 0x0004;
+pub const FLAGS2_PICTURES:u16 = 0x0008;
+pub const FLAGS2_UNDO:u16 = 0x0010;
+pub const FLAGS2_MOUSE:u16 = 0x0020;
+pub const FLAGS2_COLOURS:u16 = 0x0040;
+pub const FLAGS2_SOUND:u16 = 0x0080;
+pub const FLAGS2_MENUS:u16 = 0x0100;
+
+// Header extension table words, V5+
+pub const EXTENSION_MOUSE_X:usize = 1;
+pub const EXTENSION_MOUSE_Y:usize = 2;
+pub const EXTENSION_UNICODE_TABLE:usize = 3;
+pub const EXTENSION_FLAGS3:usize = 4;
+pub const EXTENSION_TRUE_FOREGROUND:usize = 5;
+pub const EXTENSION_TRUE_BACKGROUND:usize = 6;
+
+// Flags 3
+pub const FLAGS3_TRANSPARENCY:u16 = 0x0001;
+
+/// Parsed fields from the story file header
+#[derive(Clone, Debug)]
+pub struct Header {
+    version: Version,
+    flags1: u8,
+    release: u16,
+    high_memory_base: usize,
+    initial_pc: usize,
+    dictionary: usize,
+    object_table: usize,
+    global_variables: usize,
+    static_memory_base: usize,
+    flags2: u16,
+    serial: String,
+    abbreviations: usize,
+    file_length: usize,
+    extension_table: usize,
+    checksum: u16,
+}
+
+impl Header {
+    pub fn new(mem: &MemoryMap) -> Result<Header, InfocomError> {
+        let mut serial = String::new();
+        for i in 0..6 {
+            serial.push(mem.get_byte(0x12 + i)? as char);
+        }
+
+        // File length is stored divided by a version dependent constant
+        let length = mem.get_word(0x1A)? as usize;
+        let file_length = match mem.version {
+            Version::V(1) | Version::V(2) | Version::V(3) => length * 2,
+            Version::V(4) | Version::V(5) => length * 4,
+            Version::V(6) | Version::V(7) | Version::V(8) => length * 8,
+            _ => return Err(InfocomError::Version(mem.version))
+        };
+
+        Ok(Header { version: mem.version,
+                    flags1: mem.get_byte(0x01)?,
+                    release: mem.get_word(0x02)?,
+                    high_memory_base: mem.get_word(0x04)? as usize,
+                    initial_pc: mem.get_word(0x06)? as usize,
+                    dictionary: mem.get_word(0x08)? as usize,
+                    object_table: mem.get_word(0x0A)? as usize,
+                    global_variables: mem.get_word(0x0C)? as usize,
+                    static_memory_base: mem.get_word(0x0E)? as usize,
+                    flags2: mem.get_word(0x10)?,
+                    serial,
+                    abbreviations: mem.get_word(0x18)? as usize,
+                    file_length,
+                    // The header extension table was added in V5
+                    extension_table: if mem.is_v5_or_later() { mem.get_word(0x36)? as usize } else { 0 },
+                    checksum: mem.get_word(0x1C)? })
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn flags1(&self) -> u8 {
+        self.flags1
+    }
+
+    pub fn set_flags1(&mut self, mem: &mut MemoryMap, flags: u8) -> Result<(), InfocomError> {
+        mem.set_byte(0x01, flags)?;
+        self.flags1 = flags;
+        Ok(())
+    }
+
+    pub fn release(&self) -> u16 {
+        self.release
+    }
+
+    pub fn high_memory_base(&self) -> usize {
+        self.high_memory_base
+    }
+
+    pub fn initial_pc(&self) -> usize {
+        self.initial_pc
+    }
+
+    pub fn dictionary(&self) -> usize {
+        self.dictionary
+    }
+
+    pub fn object_table(&self) -> usize {
+        self.object_table
+    }
+
+    pub fn global_variables(&self) -> usize {
+        self.global_variables
+    }
+
+    pub fn static_memory_base(&self) -> usize {
+        self.static_memory_base
+    }
+
+    pub fn flags2(&self) -> u16 {
+        self.flags2
+    }
+
+    pub fn set_flags2(&mut self, mem: &mut MemoryMap, flags: u16) -> Result<(), InfocomError> {
+        mem.set_word(0x10, flags)?;
+        self.flags2 = flags;
+        Ok(())
+    }
+
+    pub fn serial(&self) -> &str {
+        &self.serial
+    }
+
+    pub fn abbreviations(&self) -> usize {
+        self.abbreviations
+    }
+
+    pub fn file_length(&self) -> usize {
+        self.file_length
+    }
+
+    /// Address of the header extension table, or 0 if there isn't one
+    pub fn extension_table(&self) -> usize {
+        self.extension_table
+    }
+
+    /// Word `n` (counting from 1) of the header extension table, or None if the table
+    /// is missing or too short
+    pub fn extension_word(&self, mem: &MemoryMap, n: usize) -> Result<Option<u16>, InfocomError> {
+        if self.extension_table == 0 || n == 0 || n > mem.get_word(self.extension_table)? as usize {
+            Ok(None)
+        } else {
+            Ok(Some(mem.get_word(self.extension_table + (2 * n))?))
+        }
+    }
+
+    /// Set word `n` of the header extension table, returning false if the table is missing or too short
+    pub fn set_extension_word(&self, mem: &mut MemoryMap, n: usize, value: u16) -> Result<bool, InfocomError> {
+        if self.extension_table == 0 || n == 0 || n > mem.get_word(self.extension_table)? as usize {
+            Ok(false)
+        } else {
+            mem.set_word(self.extension_table + (2 * n), value)?;
+            Ok(true)
+        }
+    }
+
+    /// Flags 3 from the header extension table, as set by the game
+    pub fn flags3(&self, mem: &MemoryMap) -> Result<u16, InfocomError> {
+        Ok(self.extension_word(mem, EXTENSION_FLAGS3)?.unwrap_or(0))
+    }
+
+    pub fn checksum(&self) -> u16 {
+        self.checksum
+    }
+
+    /// Set the screen size in the header, requesting a redraw in V6
+    pub fn set_screen_size(&mut self, mem: &mut MemoryMap, rows: u16, columns: u16) -> Result<(), InfocomError> {
+        let v = mem.version_number();
+        if v >= 4 {
+            mem.set_byte(0x20, std::cmp::min(rows, 255) as u8)?;
+            mem.set_byte(0x21, std::cmp::min(columns, 255) as u8)?;
+        }
+        if v >= 5 {
+            // One unit per character
+            mem.set_word(0x22, columns)?;
+            mem.set_word(0x24, rows)?;
+        }
+        if v == 6 {
+            let flags = self.flags2 | FLAGS2_REDRAW;
+            self.set_flags2(mem, flags)?;
+        }
+
+        Ok(())
+    }
+
+    /// Set the interpreter capability flags, number, and version in the header
+    pub fn set_interpreter_capabilities(&mut self, mem: &mut MemoryMap) -> Result<(), InfocomError> {
+        match self.version {
+            Version::V(1) | Version::V(2) | Version::V(3) => {
+                let flags = (self.flags1 & !(FLAGS1_STATUS_LINE_UNAVAILABLE | FLAGS1_VARIABLE_PITCH_DEFAULT)) | FLAGS1_SCREEN_SPLIT_AVAILABLE;
+                self.set_flags1(mem, flags)?;
+            },
+            Version::V(4) => {
+                let flags = (self.flags1 & !(FLAGS1_PICTURES_AVAILABLE | FLAGS1_SOUND_AVAILABLE | FLAGS1_TIMED_INPUT_AVAILABLE)) | FLAGS1_BOLD_AVAILABLE | FLAGS1_ITALIC_AVAILABLE | FLAGS1_FIXED_AVAILABLE;
+                self.set_flags1(mem, flags)?;
+            },
+            _ => {
+                let flags = (self.flags1 & !(FLAGS1_PICTURES_AVAILABLE | FLAGS1_SOUND_AVAILABLE | FLAGS1_TIMED_INPUT_AVAILABLE)) | FLAGS1_COLOURS_AVAILABLE | FLAGS1_BOLD_AVAILABLE | FLAGS1_ITALIC_AVAILABLE | FLAGS1_FIXED_AVAILABLE;
+                self.set_flags1(mem, flags)?;
+
+                // Clear requests for features that aren't supported
+                let flags2 = self.flags2 & !(FLAGS2_PICTURES | FLAGS2_UNDO | FLAGS2_MOUSE | FLAGS2_SOUND | FLAGS2_MENUS);
+                self.set_flags2(mem, flags2)?;
+
+                // Transparency is the only Flags 3 feature and isn't supported
+                let flags3 = self.flags3(mem)? & !FLAGS3_TRANSPARENCY;
+                self.set_extension_word(mem, EXTENSION_FLAGS3, flags3)?;
+            }
+        }
+
+        // Interpreter number (IBM PC) and version
+        mem.set_byte(0x1E, 6)?;
+        mem.set_byte(0x1F, 'A' as u8)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::state::FrameStack;
+    use super::super::test_util::{StoryBuilder, CODE, DICTIONARY, GLOBALS, OBJECTS, STATIC};
+
+    #[test]
+    fn new_parses_the_header_fields() {
+        let mem = StoryBuilder::new(3)
+            .byte(0x01, FLAGS1_STATUS_LINE_TIMED)
+            .word(0x02, 88)
+            .word(0x18, 0x40)
+            .word(0x1C, 0xBEEF)
+            .build();
+        let header = Header::new(&mem).unwrap();
+        assert_eq!(header.version().number(), 3);
+        assert_eq!(header.flags1(), FLAGS1_STATUS_LINE_TIMED);
+        assert_eq!(header.release(), 88);
+        assert_eq!(header.high_memory_base(), CODE);
+        assert_eq!(header.initial_pc(), CODE);
+        assert_eq!(header.dictionary(), DICTIONARY);
+        assert_eq!(header.object_table(), OBJECTS);
+        assert_eq!(header.global_variables(), GLOBALS);
+        assert_eq!(header.static_memory_base(), STATIC);
+        assert_eq!(header.serial(), "200101");
+        assert_eq!(header.abbreviations(), 0x40);
+        assert_eq!(header.file_length(), mem.len());
+        assert_eq!(header.checksum(), 0xBEEF);
+        // There is no extension table before V5
+        assert_eq!(header.extension_table(), 0);
+    }
+
+    #[test]
+    fn extension_table_entries_are_read_and_written() {
+        // A 3 word table: mouse x and y, and a Unicode table address
+        let mut mem = StoryBuilder::new(5)
+            .word(0x36, 0x700)
+            .word(0x700, 3)
+            .word(0x702, 12)
+            .word(0x704, 34)
+            .word(0x706, 0x780)
+            .build();
+        let header = Header::new(&mem).unwrap();
+        assert_eq!(header.extension_table(), 0x700);
+        assert_eq!(header.extension_word(&mem, EXTENSION_MOUSE_X).unwrap(), Some(12));
+        assert_eq!(header.extension_word(&mem, EXTENSION_MOUSE_Y).unwrap(), Some(34));
+        assert_eq!(header.extension_word(&mem, EXTENSION_UNICODE_TABLE).unwrap(), Some(0x780));
+        // Words past the end of the table are missing
+        assert_eq!(header.extension_word(&mem, EXTENSION_FLAGS3).unwrap(), None);
+        assert_eq!(header.extension_word(&mem, 0).unwrap(), None);
+        assert_eq!(header.flags3(&mem).unwrap(), 0);
+
+        assert!(header.set_extension_word(&mut mem, EXTENSION_MOUSE_X, 56).unwrap());
+        assert_eq!(mem.get_word(0x702).unwrap(), 56);
+        assert!(!header.set_extension_word(&mut mem, EXTENSION_FLAGS3, 1).unwrap());
+        assert_eq!(mem.get_word(0x708).unwrap(), 0);
+    }
+
+    #[test]
+    fn starting_a_story_sets_the_interpreter_capabilities() {
+        let mut mem = StoryBuilder::new(3).byte(0x01, FLAGS1_STATUS_LINE_TIMED | FLAGS1_STATUS_LINE_UNAVAILABLE).build();
+        FrameStack::new(&mut mem).unwrap();
+        assert_eq!(mem.get_byte(0x01).unwrap(), FLAGS1_STATUS_LINE_TIMED | FLAGS1_SCREEN_SPLIT_AVAILABLE);
+        assert_eq!(mem.get_byte(0x1E).unwrap(), 6);
+        assert_eq!(mem.get_byte(0x1F).unwrap(), b'A');
+
+        // Unsupported features requested by a V5 story are cleared in Flags 2 and 3
+        let mut mem = StoryBuilder::new(5)
+            .byte(0x01, FLAGS1_SOUND_AVAILABLE)
+            .word(0x10, FLAGS2_TRANSCRIPTING | FLAGS2_UNDO | FLAGS2_MOUSE)
+            .word(0x36, 0x700)
+            .word(0x700, 4)
+            .word(0x708, FLAGS3_TRANSPARENCY)
+            .build();
+        FrameStack::new(&mut mem).unwrap();
+        assert_eq!(mem.get_byte(0x01).unwrap(), FLAGS1_COLOURS_AVAILABLE | FLAGS1_BOLD_AVAILABLE | FLAGS1_ITALIC_AVAILABLE | FLAGS1_FIXED_AVAILABLE);
+        assert_eq!(mem.get_word(0x10).unwrap(), FLAGS2_TRANSCRIPTING);
+        assert_eq!(mem.get_word(0x708).unwrap(), 0);
+    }
+}
@@ -1,17 +1,19 @@
 use super::memory::{ MemoryMap, Version };
 use super::InfocomError;
-use super::state::FrameStack;
+use super::header::Header;
+use super::state::{FrameStack, StepOutcome};
 use super::object_table::ObjectTable;
 use super::text::{ Decoder, Encoder };
 use super::interface::{ Interface, StatusLineFormat };
 use super::dictionary::Dictionary;
+use super::quetzal::Quetzal;
 
-use log::debug;
-use serde::{ Serialize };
+use log::{debug, warn};
 use std::collections::HashSet;
-use std::iter::FromIterator;
+use std::fs;
+use serde::{ Serialize };
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 enum OpcodeForm {
     Long,
     Short,
@@ -52,7 +54,7 @@ impl From<u8> for OperandType {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct Instruction {
     address: usize,
     form: OpcodeForm,
@@ -62,6 +64,9 @@ pub struct Instruction {
     operands: Vec<u16>,
     store_variable: Option<u8>,
     branch_offset: Option<BranchOffset>,
+    literal: Option<String>,
+    #[serde(skip)]
+    handler: Option<Handler>,
     next_pc: usize
 }
 
@@ -107,6 +112,43 @@ impl fmt::Debug for Instruction {
     }
 }
 
+/// Outcome of a bounded run: the next instruction address and whether execution
+/// stopped because that instruction needs input
+#[derive(Debug, Serialize)]
+pub struct RunResult {
+    pub pc: usize,
+    pub instructions: usize,
+    pub awaiting_input: bool
+}
+
+/// Counts instructions executed since the last input and reports a probable infinite
+/// loop once `limit` is exceeded.  A guard with no limit never fires.
+#[derive(Debug, Default)]
+pub struct LoopGuard {
+    limit: Option<usize>,
+    count: usize
+}
+
+impl LoopGuard {
+    pub fn new(limit: Option<usize>) -> LoopGuard {
+        LoopGuard { limit, count: 0 }
+    }
+
+    /// Call before executing each instruction
+    pub fn check(&mut self, instruction: &Instruction) -> Result<(), InfocomError> {
+        if instruction.is_input() {
+            self.count = 0;
+            return Ok(())
+        }
+
+        self.count = self.count + 1;
+        match self.limit {
+            Some(l) if self.count > l => Err(InfocomError::API(format!("possible infinite loop at ${:06x}", instruction.address))),
+            _ => Ok(())
+        }
+    }
+}
+
 #[derive(Default, Serialize)]
 pub struct InstructionResult {
     store_value: Option<u16>,
@@ -131,6 +173,27 @@ impl fmt::Debug for InstructionResult {
 }
 
 impl Instruction {
+    /// The error for an instruction this interpreter doesn't support yet
+    fn unimplemented(&self) -> InfocomError {
+        InfocomError::Unimplemented { opcode: format!("{} (${:02x})", self.name, self.opcode), address: self.address }
+    }
+
+    /// True for the `quit` opcode, which ends execution
+    pub fn is_quit(&self) -> bool {
+        match self.form {
+            OpcodeForm::Extended => false,
+            _ => self.opcode == 0xBA
+        }
+    }
+
+    /// True for the opcodes that wait for input from the player
+    pub fn is_input(&self) -> bool {
+        match self.form {
+            OpcodeForm::Extended => false,
+            _ => self.opcode == 0xE4 || self.opcode == 0xF6
+        }
+    }
+
     fn get_argument(&self, state: &mut FrameStack, index: usize) -> Result<u16,InfocomError> {
         Ok(match self.operand_types[index] {
             OperandType::SmallConstant => self.operands[index] & 0xFF,
@@ -154,16 +217,20 @@ impl Instruction {
 
     // 2OP
     fn je(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        if self.operands.len() < 2 || self.operands.len() > 4 {
+            return Err(InfocomError::OperandCount { opcode: format!("{} (${:02x})", self.name, self.opcode), address: self.address, count: self.operands.len() })
+        }
+
+        // Read every operand so stack operands are always popped
         let a = self.get_argument(state, 0)?;
+        let mut condition = false;
         for i in 1..self.operands.len() {
             let b = self.get_argument(state, i)?;
             debug!("JE: ${:04x} ${:04x}", a, b);
-            if a == b {
-                return Ok(InstructionResult { branch_condition: Some(true), ..Default::default() })
-            }
+            condition = condition || a == b;
         }
 
-        return Ok(InstructionResult { branch_condition: Some(false), ..Default::default() })
+        Ok(InstructionResult { branch_condition: Some(condition), ..Default::default() })
     }
 
     fn jg(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
@@ -269,7 +336,8 @@ impl Instruction {
     fn store(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
         let var = self.get_indirect_variable_reference(state, 0)?;
         let value = self.get_argument(state, 1)?;
-        state.set_variable(var, value, false)?;
+        // Storing to SP replaces the top of the stack
+        state.set_variable(var, value, true)?;
         Ok(InstructionResult::default())   
     }
 
@@ -283,9 +351,10 @@ impl Instruction {
     }
 
     fn loadw(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        let array = self.get_argument(state, 0)?;
-        let index = self.get_argument(state, 1)?;
-        let value = state.get_memory().get_word(array as usize + (index as usize * 2))?;
+        let array = self.get_argument(state, 0)? as usize;
+        let index = self.get_argument(state, 1)? as usize;
+        let address = array_address(state.get_memory(), array, index * 2, 2)?;
+        let value = state.get_memory().get_word(address)?;
 
         Ok(InstructionResult { store_value: Some(value), ..Default::default() })
     }
@@ -293,7 +362,8 @@ impl Instruction {
     fn loadb(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
         let array = self.get_argument(state, 0)? as usize;
         let index = self.get_argument(state, 1)? as usize;
-        let value = state.get_memory().get_byte(array + index)?;
+        let address = array_address(state.get_memory(), array, index, 1)?;
+        let value = state.get_memory().get_byte(address)?;
 
         Ok(InstructionResult { store_value: Some(value as u16), ..Default::default() })
     }
@@ -312,6 +382,9 @@ impl Instruction {
         let property = self.get_argument(state, 1)? as usize;
         let ot = ObjectTable::new(state.get_memory())?;
         let value = ot.get_property_address(state.get_memory(), object, property)?;
+        if value > 0xFFFF {
+            return Err(InfocomError::ReadViolation(value, 0xFFFF))
+        }
 
         Ok(InstructionResult { store_value: Some(value as u16), ..Default::default() })
     }
@@ -405,11 +478,19 @@ impl Instruction {
     }
 
     fn set_colour(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("set_colour not implemented yet")))
+        let foreground = self.get_argument(state, 0)?;
+        let background = self.get_argument(state, 1)?;
+        debug!("set_colour {} {} ignored, colours are not supported", foreground, background);
+
+        Ok(InstructionResult::default())
     }
 
     fn throw(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("throw not implemented yet")))
+        let value = self.get_argument(state, 0)?;
+        let frame = self.get_argument(state, 1)?;
+        let next_pc = state.throw(value, frame)?;
+
+        Ok(InstructionResult { next_pc: Some(next_pc), ..Default::default() })
     }
 
     // 1OP
@@ -461,11 +542,11 @@ impl Instruction {
         Ok(InstructionResult::default())
     }
 
-    fn print_addr(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+    fn print_addr(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
         let addr = self.get_argument(state, 0)? as usize;
         let decoder = Decoder::new(state.get_memory())?;
         let string = decoder.decode(addr)?;
-        print!("{}", string);
+        interface.print(&string);
 
         Ok(InstructionResult::default())
     }
@@ -503,16 +584,21 @@ impl Instruction {
     }
 
     fn jump(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        // The label is a signed offset biased by 2, the same as a branch offset
         let label = self.get_argument(state, 0)? as i16;
-        let address = self.next_pc as isize + label as isize - 2;
-        debug!("JUMP: {} -> {}", label, self.next_pc);
+        let address = offset_address(self.next_pc, label);
+        debug!("JUMP: {} -> ${:06x}", label, address);
+
+        if address < 0 || address as usize >= state.get_memory().len() {
+            return Err(InfocomError::Memory(format!("Jump at ${:06x} to ${:06x} is outside of story memory", self.address, address)))
+        }
 
         Ok(InstructionResult { next_pc: Some(address as usize), ..Default::default() })
     }
 
     fn print_paddr(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
         let packed_address = self.get_argument(state, 0)?;
-        let address = state.unpack_address(packed_address)?;
+        let address = state.unpack_string_address(packed_address)?;
         let decoder = Decoder::new(state.get_memory())?;
         let string = decoder.decode(address)?;
         interface.print(&string);
@@ -528,6 +614,7 @@ impl Instruction {
     }
 
     // Also VAR:18 for version 5+
+    /// 16-bit complement.  `not` is 1OP:$0F in V1-4 and VAR:$18 in V5+, both storing the result.
     fn not(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
         let value = self.get_argument(state, 0)?;
         let result = !value;
@@ -554,20 +641,22 @@ impl Instruction {
         Ok(InstructionResult { next_pc: Some(next_pc), ..Default::default() })
     }
 
+    /// The literal string decoded with the instruction
+    fn literal(&self) -> Result<&str,InfocomError> {
+        match &self.literal {
+            Some(s) => Ok(s),
+            None => Err(InfocomError::Text(format!("{} at ${:06x} has no literal string", self.name, self.address)))
+        }
+    }
+
     fn print(&self, state: &FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
-        let address = self.address + 1;
-        let decoder = Decoder::new(state.get_memory())?;
-        let string = decoder.decode(address)?;
-        interface.print(&string);
+        interface.print(self.literal()?);
 
         Ok(InstructionResult::default())
     }
 
     fn print_ret(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
-        let address = self.address + 1;
-        let decoder = Decoder::new(state.get_memory())?;
-        let string = decoder.decode(address)?;
-        interface.print(&string);
+        interface.print(self.literal()?);
         interface.new_line();
 
         let next_pc = state.return_from(1)?;
@@ -579,26 +668,154 @@ impl Instruction {
         Ok(InstructionResult::default())
     }
 
-    fn save_v1(&self, state: &FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("save_v1 not implemented yet")))
+    /// Prompt for a save file name, returning None if the player entered nothing
+    fn prompt_filename(&self, interface: &mut dyn Interface) -> Option<String> {
+        interface.print("Filename: ");
+        let mut terminators = HashSet::new();
+        terminators.insert(13);
+        let (name, _) = interface.read(&terminators, 255);
+        let name = name.trim();
+        if name.is_empty() {
+            None
+        } else {
+            Some(String::from(name))
+        }
+    }
+
+    /// Prompt for a file name and write a Quetzal save resuming at `pc`, returning
+    /// whether the file was written
+    fn write_save(&self, state: &FrameStack, interface: &mut dyn Interface, pc: usize) -> Result<bool,InfocomError> {
+        match self.prompt_filename(interface) {
+            Some(name) => {
+                let quetzal = Quetzal::new(state.get_memory(), state.quetzal_frames(), pc)?;
+                match fs::write(&name, quetzal.to_bytes()) {
+                    Ok(_) => Ok(true),
+                    Err(e) => {
+                        warn!("Error writing save file '{}': {}", name, e);
+                        Ok(false)
+                    }
+                }
+            },
+            None => Ok(false)
+        }
+    }
+
+    /// Prompt for a file name and read a Quetzal save for this story, or None if there
+    /// is nothing to restore
+    fn read_save(&self, state: &FrameStack, interface: &mut dyn Interface) -> Result<Option<Quetzal>,InfocomError> {
+        let quetzal = match self.prompt_filename(interface) {
+            Some(name) => match fs::read(&name) {
+                Ok(data) => Quetzal::from_bytes(&data),
+                Err(e) => Err(InfocomError::API(format!("Error reading save file '{}': {}", name, e)))
+            },
+            None => return Ok(None)
+        };
+
+        let quetzal = match quetzal {
+            Ok(q) => q,
+            Err(e) => {
+                warn!("{}", e);
+                return Ok(None)
+            }
+        };
+
+        if !quetzal.ifhd.matches(state.get_memory())? {
+            warn!("Save file is for a different story");
+            return Ok(None)
+        }
+
+        Ok(Some(quetzal))
+    }
+
+    fn save_v1(&self, state: &FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        // Quetzal saves the address of the branch data for V1-3 saves
+        let pc = match &self.branch_offset {
+            Some(b) => self.next_pc - b.size,
+            None => return Err(InfocomError::Memory(format!("save at ${:06x} has no branch", self.address)))
+        };
+
+        let condition = self.write_save(state, interface, pc)?;
+        Ok(InstructionResult { branch_condition: Some(condition), ..Default::default() })
     }
 
-    fn save_v4(&self, state: &FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("save_v4 not implemented yet")))
+    /// `save` for V4, and the extended `save` from V5 on.  Saving part of memory to an
+    /// auxiliary file isn't supported and fails.
+    fn save_v4(&self, state: &FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        // Quetzal saves the address of the store variable for V4+ saves
+        let pc = match self.store_variable {
+            Some(_) => self.next_pc - 1,
+            None => return Err(InfocomError::Memory(format!("save at ${:06x} has no store variable", self.address)))
+        };
+
+        if self.operands.len() > 0 {
+            warn!("Auxiliary save files are not supported");
+            return Ok(InstructionResult { store_value: Some(0), ..Default::default() })
+        }
+
+        let saved = self.write_save(state, interface, pc)?;
+        Ok(InstructionResult { store_value: Some(saved as u16), ..Default::default() })
     }
 
-    fn restore_v1(&self, state: &FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("restore_v1 not implemented yet")))
+    fn restore_v1(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let quetzal = match self.read_save(state, interface)? {
+            Some(q) => q,
+            None => return Ok(InstructionResult { branch_condition: Some(false), ..Default::default() })
+        };
+
+        // The saved pc addresses the branch data of the original save, which is 1 or 2 bytes
+        if quetzal.ifhd.pc + 1 >= state.get_memory().len() {
+            return Err(InfocomError::Memory(format!("Saved pc ${:06x} is beyond the end of memory", quetzal.ifhd.pc)))
+        }
+        state.restore(&quetzal)?;
+
+        // Execution resumes as though the original save had succeeded
+        let branch = decode_branch_offset(&state.get_memory().get_memory(), quetzal.ifhd.pc);
+        let next_pc = if branch.condition {
+            match (branch.return_value, branch.address) {
+                (Some(ret), _) => state.return_from(ret as u16)?,
+                (None, Some(address)) => address,
+                (None, None) => return Err(InfocomError::Memory(format!("Saved branch at ${:06x} has no destination", quetzal.ifhd.pc)))
+            }
+        } else {
+            quetzal.ifhd.pc + branch.size
+        };
+
+        // Never take this instruction's own branch once the restore has succeeded
+        let condition = match &self.branch_offset {
+            Some(b) => !b.condition,
+            None => false
+        };
+        Ok(InstructionResult { branch_condition: Some(condition), next_pc: Some(next_pc), ..Default::default() })
     }
 
-    fn restore_v4(&self, state: &FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("restore_v4 not implemented yet")))
+    /// `restore` for V4, and the extended `restore` from V5 on.  A successful restore
+    /// resumes after the original `save`, which stores 2.
+    fn restore_v4(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        if self.operands.len() > 0 {
+            warn!("Auxiliary save files are not supported");
+            return Ok(InstructionResult { store_value: Some(0), ..Default::default() })
+        }
+
+        let quetzal = match self.read_save(state, interface)? {
+            Some(q) => q,
+            None => return Ok(InstructionResult { store_value: Some(0), ..Default::default() })
+        };
+
+        state.restore(&quetzal)?;
+        let variable = state.get_memory().get_byte(quetzal.ifhd.pc)?;
+        state.set_variable(variable, 2, false)?;
+
+        Ok(InstructionResult { next_pc: Some(quetzal.ifhd.pc + 1), ..Default::default() })
     }
 
-    fn restart(&self, state: &FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("restart not implemented yet")))
+    fn restart(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let next_pc = state.restart()?;
+
+        Ok(InstructionResult { next_pc: Some(next_pc), ..Default::default() })
     }
 
+    /// Return the value popped from the stack; an empty stack is an error and the routine
+    /// does not return
     fn ret_popped(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
         let value = state.get_variable(0, false)?;
         let next_pc = state.return_from(value)?;
@@ -613,11 +830,15 @@ impl Instruction {
     }
 
     fn catch(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("catch not implemented yet")))
+        let frame = state.frame_depth() as u16;
+
+        Ok(InstructionResult { store_value: Some(frame), ..Default::default() })
     }
 
+    /// `FrameStack::step` halts before executing `quit`, so executing it directly leaves
+    /// the pc on the instruction
     fn quit(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("quit not implemented yet")))
+        Ok(InstructionResult { next_pc: Some(self.address), ..Default::default() })
     }
 
     fn new_line(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
@@ -630,6 +851,11 @@ impl Instruction {
         let v1 = state.get_variable(17, false)? as i16;
         let v2 = state.get_variable(18, false)?;
         let name_obj = state.get_variable(16, false)? as usize;
+        // The status line isn't set up until the game has stored the location object
+        if name_obj == 0 {
+            debug!("show_status skipped, no location object");
+            return Ok(InstructionResult::default())
+        }
         let o = ObjectTable::new(state.get_memory())?.get_object(state.get_memory(), name_obj)?;
         let status_type = match state.get_memory().version {
             Version::V(3) => {
@@ -647,8 +873,13 @@ impl Instruction {
         Ok(InstructionResult::default())    
     }
 
+    /// Branch if the checksum of the original story data matches the header
     fn verify(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("verify not implemented yet")))
+        let header = Header::new(state.get_memory())?;
+        let checksum = state.get_memory().checksum(header.file_length());
+        debug!("Checksum ${:04x}, header ${:04x}", checksum, header.checksum());
+
+        Ok(InstructionResult { branch_condition: Some(checksum == header.checksum()), ..Default::default() })
     }
 
     fn piracy(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
@@ -674,7 +905,8 @@ impl Instruction {
         let word_index = self.get_argument(state, 1)? as usize;
         let value = self.get_argument(state, 2)?;
 
-        state.set_word(array + (2 * word_index), value)?;
+        let address = array_address(state.get_memory(), array, 2 * word_index, 2)?;
+        state.set_word(address, value)?;
 
         Ok(InstructionResult::default())
     }
@@ -684,7 +916,8 @@ impl Instruction {
         let byte_index = self.get_argument(state, 1)? as usize;
         let value = (self.get_argument(state, 2)? as u8) & 0xFF;
 
-        state.set_byte(array + byte_index, value)?;
+        let address = array_address(state.get_memory(), array, byte_index, 1)?;
+        state.set_byte(address, value)?;
 
         Ok(InstructionResult::default())
     }
@@ -699,8 +932,27 @@ impl Instruction {
         Ok(InstructionResult::default())
     }
 
+    /// Update the header after a terminal resize, repainting the status line in V1-3
+    fn check_resize(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<(),InfocomError> {
+        if let Some((rows, columns)) = interface.on_resize() {
+            state.set_screen_size(rows, columns)?;
+            if state.get_memory().is_v3_or_earlier() {
+                self.show_status(state, interface)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `sread` for versions 1-4.  Only versions 1-3 redisplay the status line.
     fn sread_v1(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
-        self.show_status(state, interface)?;
+        // The status line is repainted below, so only the header needs updating
+        if let Some((rows, columns)) = interface.on_resize() {
+            state.set_screen_size(rows, columns)?;
+        }
+        if state.get_memory().is_v3_or_earlier() {
+            self.show_status(state, interface)?;
+        }
         // let v2 = state.get_variable(18, false)?;
         // let name_obj = state.get_variable(16, false)? as usize;
         // let o = ObjectTable::new(state.get_memory())?.get_object(state.get_memory(), name_obj)?;
@@ -724,29 +976,25 @@ impl Instruction {
 
         debug!("Text buffer: ${:04x} for ${:02x} bytes", text_buffer, max_chars);
 
-        let mut input = interface.read(HashSet::from_iter(vec!['\n', '\r']), max_chars);
-        // Remove the terminating character from the buffer...
-        let terminator = input.pop();
+        let (input, _) = interface.read(&state.terminating_characters, max_chars);
         debug!("Input: {}", input);
+        self.check_resize(state, interface)?;
 
         let encoder = Encoder::new(state.get_memory())?;
         let mut input_bytes = encoder.to_bytes(&input);
-        // ...and replace it with a 0 byte
+        // Terminate the text with a 0 byte
         input_bytes.push(0);
 
-        // Byte 1 of the buffer is the number of characters read
-        state.set_byte(text_buffer + 1, input.len() as u8)?;
-
-        // Byte 2 onward is the text with a '\0' terminator.
+        // Byte 1 onward is the text with a '\0' terminator; there is no count byte before V5
         for (i, c) in input_bytes.iter().enumerate() {
-            state.set_byte(text_buffer + i + 2, *c)?;
+            state.set_byte(text_buffer + i + 1, *c)?;
         }
 
         let max_words = state.get_memory().get_byte(parse_buffer)?;
         debug!("Parse buffer: ${:04x} for ${:02x} words", parse_buffer, max_words);
 
         let dic = Dictionary::new(state.get_memory())?;
-        dic.analyze_text(state, &input, parse_buffer)?;
+        dic.analyze_text(state, &input, parse_buffer, false)?;
         // state.set_byte(parse_buffer + 1, 1)?;
         // state.set_word(parse_buffer + 2, 0)?;
         // state.set_byte(parse_buffer + 4, input.len() as u8)?;
@@ -756,18 +1004,50 @@ impl Instruction {
         Ok(InstructionResult::default())
     }
 
-    fn sread_v4(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("sread not implemented yet")))
-    }
+    fn aread(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let text_buffer = self.get_argument(state, 0)? as usize;
+        let parse_buffer = if self.operands.len() > 1 {
+            self.get_argument(state, 1)? as usize
+        } else {
+            0
+        };
+        let max_chars = state.get_memory().get_byte(text_buffer)? as usize;
+
+        debug!("Text buffer: ${:04x} for ${:02x} bytes", text_buffer, max_chars);
+
+        self.check_resize(state, interface)?;
+        let (input, terminator) = interface.read(&state.terminating_characters, max_chars);
+        self.check_resize(state, interface)?;
+        debug!("Input: {}, terminated by {}", input, terminator);
+
+        let encoder = Encoder::new(state.get_memory())?;
+        let input_bytes = encoder.to_bytes(&input);
 
-    fn aread(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("aread not implemented yet")))
+        // Byte 1 of the buffer is the number of characters read, followed by the text
+        state.set_byte(text_buffer + 1, input_bytes.len() as u8)?;
+        for (i, c) in input_bytes.iter().enumerate() {
+            state.set_byte(text_buffer + i + 2, *c)?;
+        }
+
+        if parse_buffer != 0 {
+            let dic = Dictionary::new(state.get_memory())?;
+            dic.analyze_text(state, &input, parse_buffer, false)?;
+        }
+
+        Ok(InstructionResult { store_value: Some(terminator), ..Default::default() })
     }
 
     fn print_char(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
         let z = self.get_argument(state, 0)?;
-        let d = Decoder::new(state.get_memory())?;
-        interface.print(&format!("{}", d.zscii_to_char(z)?));
+        match z {
+            0 => {},
+            13 => interface.new_line(),
+            32..=126 | 155..=251 => {
+                let d = Decoder::new(state.get_memory())?;
+                interface.print(&format!("{}", d.zscii_to_char(z)?));
+            },
+            _ => return Err(InfocomError::Text(format!("print_char with invalid output character ${:04x}", z)))
+        }
 
         Ok(InstructionResult::default())
     }
@@ -780,9 +1060,15 @@ impl Instruction {
     }
 
     fn random(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        let range = self.get_argument(state, 0)?;
-        let value = state.random(range)?;
-        Ok(InstructionResult { store_value: Some(value), ..Default::default() })
+        let range = self.get_argument(state, 0)? as i16;
+        if range > 0 {
+            let value = state.random(range as u16)?;
+            Ok(InstructionResult { store_value: Some(value), ..Default::default() })
+        } else {
+            // 0 reseeds randomly, a negative range seeds predictably
+            state.seed((range as i32).abs() as u16);
+            Ok(InstructionResult { store_value: Some(0), ..Default::default() })
+        }
     }
 
     fn push(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
@@ -793,19 +1079,48 @@ impl Instruction {
     }
 
     fn pull(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        // V6 stores the value popped from the game stack, or from a user stack if one is given
+        if state.get_memory().version_number() == 6 {
+            let value = if self.operands.len() > 0 {
+                // The first word of a user stack is the number of free slots.  The table doesn't
+                // record its capacity, but a writable stack ends in dynamic memory, so a free
+                // count whose next slot lies beyond it has pulled past the bottom of the stack.
+                let stack = self.get_argument(state, 0)? as usize;
+                let free = state.get_memory().get_word(stack)? as usize + 1;
+                let slot = stack + (2 * free);
+                if free > 0xFFFF || slot + 2 > state.get_memory().static_mark() {
+                    return Err(InfocomError::Memory(format!("Pull from empty user stack at ${:04x}", stack)))
+                }
+                let value = state.get_memory().get_word(slot)?;
+                state.set_word(stack, free as u16)?;
+                value
+            } else {
+                state.current_frame.pop()?
+            };
+
+            return Ok(InstructionResult { store_value: Some(value), ..Default::default() })
+        }
+
         let variable = self.get_indirect_variable_reference(state, 0)?;
         let value = state.current_frame.pop()?;
-        state.set_variable(variable, value, false)?;
+        // Pulling to SP replaces the new top of the stack
+        state.set_variable(variable, value, true)?;
 
         Ok(InstructionResult::default())
     }
 
-    fn split_window(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("split_window not implemented yet")))
+    fn split_window(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let lines = self.get_argument(state, 0)?;
+        interface.split_window(lines);
+
+        Ok(InstructionResult::default())
     }
 
-    fn set_window(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("set_window not implemented yet")))
+    fn set_window(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let window = self.get_argument(state, 0)?;
+        interface.set_window(window);
+
+        Ok(InstructionResult::default())
     }
 
     fn call_vs2(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
@@ -819,48 +1134,152 @@ impl Instruction {
         Ok(InstructionResult { next_pc: Some(next_pc), ..Default::default() })
     }
 
-    fn erase_window(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("erase_window not implemented yet")))
+    fn erase_window(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let window = self.get_argument(state, 0)? as i16;
+        interface.erase_window(window);
+
+        Ok(InstructionResult::default())
     }
 
-    fn erase_line(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("erase_line not implemented yet")))
+    fn erase_line(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let value = self.get_argument(state, 0)?;
+        // 1 erases to the end of the line.  V6 treats any other value as a pixel count,
+        // which is approximated as columns, while V4 and V5 do nothing.
+        match value {
+            0 => {},
+            1 => interface.erase_line(None),
+            _ => if state.get_memory().version_number() == 6 {
+                interface.erase_line(Some(value))
+            } else {
+                debug!("erase_line {} ignored", value)
+            }
+        }
+
+        Ok(InstructionResult::default())
     }
 
-    fn set_cursor(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("set_cursor not implemented yet")))
+    fn set_cursor(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let line = self.get_argument(state, 0)?;
+        let column = self.get_argument(state, 1)?;
+
+        // V4 only allows the cursor to be positioned in the upper window
+        match state.get_memory().version {
+            Version::V(4) if interface.get_window() == 0 => {
+                warn!("set_cursor in lower window ignored");
+            },
+            _ => interface.set_cursor(line, column)
+        }
+
+        Ok(InstructionResult::default())
     }
 
-    fn get_cursor(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("get_cursor not implemented yet")))
+    fn get_cursor(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let array = self.get_argument(state, 0)? as usize;
+        let (line, column) = interface.get_cursor();
+        state.set_word(array, line)?;
+        state.set_word(array + 2, column)?;
+
+        Ok(InstructionResult::default())
     }
 
-    fn set_text_style(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("set_text_style not implemented yet")))
+    fn set_text_style(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let style = self.get_argument(state, 0)?;
+        interface.set_text_style(style);
+
+        Ok(InstructionResult::default())
     }
 
-    fn buffer_mode(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("buffer_mode not implemented yet")))
+    fn buffer_mode(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let flag = self.get_argument(state, 0)?;
+        interface.buffer_mode(flag);
+
+        Ok(InstructionResult::default())
     }
 
-    fn output_stream(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("output_stream not implemented yet")))
+    fn output_stream(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        // A positive stream number selects the stream, negative deselects it
+        let stream = self.get_argument(state, 0)? as i16;
+        match stream {
+            4 => interface.record_commands(true),
+            -4 => interface.record_commands(false),
+            _ => warn!("Unsupported output stream: {}", stream)
+        }
+
+        Ok(InstructionResult::default())
     }
 
-    fn input_stream(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("input_stream not implemented yet")))
+    fn input_stream(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let stream = self.get_argument(state, 0)?;
+        match stream {
+            0 | 1 => interface.input_stream(stream),
+            _ => warn!("Unsupported input stream: {}", stream)
+        }
+
+        Ok(InstructionResult::default())
     }
 
-    fn sound_effect(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("sound_effect not implemented yet")))
+    /// Effects 1 and 2 are beeps.  Higher numbers are sounds from the Blorb resource
+    /// file, which are only started; preparing, stopping, volume and repeats are ignored.
+    fn sound_effect(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let number = if self.operands.len() > 0 {
+            self.get_argument(state, 0)?
+        } else {
+            1
+        };
+        let effect = if self.operands.len() > 1 {
+            self.get_argument(state, 1)?
+        } else {
+            2
+        };
+
+        match number {
+            1 | 2 => interface.beep(number == 1),
+            _ => if effect == 2 {
+                match state.sound(number) {
+                    Some(data) => interface.play_sound(number, data),
+                    None => debug!("sound_effect {} has no sound resource", number)
+                }
+            } else {
+                debug!("sound_effect {} effect {} ignored", number, effect);
+            }
+        }
+
+        Ok(InstructionResult::default())
     }
 
-    fn read_char(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("read_char not implemented yet")))
+    /// Timed input isn't supported, so the time and routine operands are ignored
+    fn read_char(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let c = interface.read_char();
+
+        Ok(InstructionResult { store_value: Some(c), ..Default::default() })
     }
 
     fn scan_table(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("scan_table not implemented yet")))
+        let value = self.get_argument(state, 0)?;
+        let table = self.get_argument(state, 1)? as usize;
+        let len = self.get_argument(state, 2)? as usize;
+        // Bit 7 of the form selects word entries, bits 0-6 the stride between entries
+        let form = if self.operands.len() > 3 {
+            self.get_argument(state, 3)?
+        } else {
+            0x82
+        };
+        let words = form & 0x80 == 0x80;
+        let stride = (form & 0x7F) as usize;
+
+        for i in 0..len {
+            let address = table + (i * stride);
+            let entry = if words {
+                state.get_memory().get_word(address)?
+            } else {
+                state.get_memory().get_byte(address)? as u16
+            };
+            if entry == value {
+                return Ok(InstructionResult { store_value: Some(address as u16), branch_condition: Some(true), ..Default::default() })
+            }
+        }
+
+        Ok(InstructionResult { store_value: Some(0), branch_condition: Some(false), ..Default::default() })
     }
 
     fn call_vn(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
@@ -886,23 +1305,436 @@ impl Instruction {
     }
 
     fn tokenise(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("tokenise not implemented yet")))
+        let text_buffer = self.get_argument(state, 0)? as usize;
+        let parse_buffer = self.get_argument(state, 1)? as usize;
+        let dictionary = if self.operands.len() > 2 {
+            self.get_argument(state, 2)? as usize
+        } else {
+            0
+        };
+        let skip_unknown = self.operands.len() > 3 && self.get_argument(state, 3)? != 0;
+
+        // Byte 1 of the text buffer holds the length of the text that follows
+        let length = state.get_memory().get_byte(text_buffer + 1)? as usize;
+        let mut input = String::new();
+        for i in 0..length {
+            input.push(state.get_memory().get_byte(text_buffer + 2 + i)? as char);
+        }
+        debug!("Tokenise: {} with dictionary ${:04x}", input, dictionary);
+
+        let dic = if dictionary == 0 {
+            Dictionary::new(state.get_memory())?
+        } else {
+            Dictionary::at(state.get_memory(), dictionary)?
+        };
+        dic.analyze_text(state, &input, parse_buffer, skip_unknown)?;
+
+        Ok(InstructionResult::default())
     }
 
+    /// Encode `length` ZSCII characters starting `from` bytes into the text as a
+    /// dictionary word at `coded_text`
     fn encode_text(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("encode_text not implemented yet")))
+        let text = self.get_argument(state, 0)? as usize;
+        let length = self.get_argument(state, 1)? as usize;
+        let from = self.get_argument(state, 2)? as usize;
+        let coded_text = self.get_argument(state, 3)? as usize;
+
+        let decoder = Decoder::new(state.get_memory())?;
+        let mut word = String::new();
+        for i in 0..length {
+            let z = state.get_memory().get_byte(text + from + i)? as u16;
+            word.push(decoder.zscii_to_char(z)?);
+        }
+
+        let encoded = Encoder::new(state.get_memory())?.encode(&word)?;
+        for (i, w) in encoded.iter().enumerate() {
+            state.set_word(coded_text + (2 * i), *w)?;
+        }
+
+        Ok(InstructionResult::default())
     }
 
     fn copy_table(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("copy_table not implemented yet")))
+        let first = self.get_argument(state, 0)? as usize;
+        let second = self.get_argument(state, 1)? as usize;
+        let size = self.get_argument(state, 2)? as i16;
+        let length = (size as i32).abs() as usize;
+
+        // Nothing to copy, or copying a table onto itself
+        if length == 0 || first == second {
+            return Ok(InstructionResult::default())
+        }
+
+        if second == 0 {
+            // Zero the first table
+            for i in 0..length {
+                state.set_byte(first + i, 0)?;
+            }
+        } else if size < 0 {
+            // A negative size forces a forward copy, even if the tables overlap
+            for i in 0..length {
+                let b = state.get_memory().get_byte(first + i)?;
+                state.set_byte(second + i, b)?;
+            }
+        } else {
+            // Copy via a buffer so overlapping tables aren't corrupted
+            let mut data = Vec::new();
+            for i in 0..length {
+                data.push(state.get_memory().get_byte(first + i)?);
+            }
+            for (i, b) in data.iter().enumerate() {
+                state.set_byte(second + i, *b)?;
+            }
+        }
+
+        Ok(InstructionResult::default())
     }
 
-    fn print_table(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("print_table not implemented yet")))
+    /// Print `height` rows of `width` characters from `table`, skipping `skip` bytes after
+    /// each row.  In the upper window each row starts below the first; in the lower
+    /// window rows are separated by new lines.
+    fn print_table(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let table = self.get_argument(state, 0)? as usize;
+        let width = self.get_argument(state, 1)? as usize;
+        let height = if self.operands.len() > 2 {
+            self.get_argument(state, 2)? as usize
+        } else {
+            1
+        };
+        let skip = if self.operands.len() > 3 {
+            self.get_argument(state, 3)? as usize
+        } else {
+            0
+        };
+
+        let decoder = Decoder::new(state.get_memory())?;
+        let (line, column) = interface.get_cursor();
+        for row in 0..height {
+            if row > 0 {
+                if interface.get_window() == 1 {
+                    interface.set_cursor(line + row as u16, column);
+                } else {
+                    interface.new_line();
+                }
+            }
+
+            let start = table + (row * (width + skip));
+            let mut text = String::new();
+            for i in 0..width {
+                let z = state.get_memory().get_byte(start + i)? as u16;
+                text.push(decoder.zscii_to_char(z)?);
+            }
+            interface.print(&text);
+        }
+
+        Ok(InstructionResult::default())
     }
 
+    /// Branch if argument `n`, counting from 1, was supplied to the current routine
     fn check_arg_count(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
-        Err(InfocomError::Memory(format!("check_arg_count not implemented yet")))
+        let argument = self.get_argument(state, 0)? as usize;
+
+        Ok(InstructionResult { branch_condition: Some(argument <= state.argument_count()), ..Default::default() })
+    }
+
+    // EXT
+    /// Shift left for positive `places`, right for negative.  Shifts of 16 or more
+    /// places clear the value instead of overflowing.
+    fn log_shift(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let number = self.get_argument(state, 0)?;
+        let places = self.get_argument(state, 1)? as i16;
+        let result = if places >= 16 || places <= -16 {
+            0
+        } else if places >= 0 {
+            number << places
+        } else {
+            number >> -places
+        };
+
+        Ok(InstructionResult { store_value: Some(result), ..Default::default() })
+    }
+
+    /// As `log_shift`, but right shifts preserve the sign.  Right shifts of 16 or more
+    /// places fill the value with the sign bit.
+    fn art_shift(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let number = self.get_argument(state, 0)? as i16;
+        let places = self.get_argument(state, 1)? as i16;
+        let result = if places >= 16 {
+            0
+        } else if places <= -16 {
+            number >> 15
+        } else if places >= 0 {
+            number << places
+        } else {
+            number >> -places
+        };
+
+        Ok(InstructionResult { store_value: Some(result as u16), ..Default::default() })
+    }
+
+    fn set_font(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let font = self.get_argument(state, 0)?;
+        let previous = interface.set_font(font);
+
+        Ok(InstructionResult { store_value: Some(previous), ..Default::default() })
+    }
+
+    fn get_wind_prop(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let window = self.get_argument(state, 0)?;
+        let property = self.get_argument(state, 1)?;
+        let value = interface.get_window_property(window, property);
+
+        Ok(InstructionResult { store_value: Some(value), ..Default::default() })
+    }
+
+    fn put_wind_prop(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let window = self.get_argument(state, 0)?;
+        let property = self.get_argument(state, 1)?;
+        let value = self.get_argument(state, 2)?;
+        interface.set_window_property(window, property, value);
+
+        Ok(InstructionResult::default())
+    }
+
+    fn set_margins(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let left = self.get_argument(state, 0)?;
+        let right = self.get_argument(state, 1)?;
+        // -3 is the current window
+        let window = if self.operands.len() > 2 {
+            self.get_argument(state, 2)?
+        } else {
+            -3i16 as u16
+        };
+
+        // Margins are window properties 6 and 7
+        interface.set_window_property(window, 6, left);
+        interface.set_window_property(window, 7, right);
+
+        Ok(InstructionResult::default())
+    }
+
+    fn draw_picture(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let picture = self.get_argument(state, 0)?;
+        debug!("draw_picture {} ignored, pictures are not supported", picture);
+
+        Ok(InstructionResult::default())
+    }
+
+    fn picture_data(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let picture = self.get_argument(state, 0)?;
+        let array = self.get_argument(state, 1)? as usize;
+        debug!("picture_data {}: no pictures available", picture);
+
+        // Picture 0 asks for the picture count and release, otherwise height and width
+        state.set_word(array, 0)?;
+        state.set_word(array + 2, 0)?;
+
+        Ok(InstructionResult { branch_condition: Some(false), ..Default::default() })
+    }
+
+    fn erase_picture(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let picture = self.get_argument(state, 0)?;
+        debug!("erase_picture {} ignored, pictures are not supported", picture);
+
+        Ok(InstructionResult::default())
+    }
+
+    fn picture_table(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let table = self.get_argument(state, 0)?;
+        debug!("picture_table ${:04x} ignored, pictures are not supported", table);
+
+        Ok(InstructionResult::default())
+    }
+
+    fn read_mouse(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let array = self.get_argument(state, 0)? as usize;
+        debug!("read_mouse: no mouse available");
+
+        // y, x, buttons, and menu selection
+        for i in 0..4 {
+            state.set_word(array + (2 * i), 0)?;
+        }
+
+        Ok(InstructionResult::default())
+    }
+
+    fn mouse_window(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let window = self.get_argument(state, 0)?;
+        debug!("mouse_window {} ignored, no mouse available", window);
+
+        Ok(InstructionResult::default())
+    }
+
+    /// Bit 0 of the result is set if the character can be printed, bit 1 if it can be read
+    fn check_unicode(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let code_point = self.get_argument(state, 0)? as u32;
+        let mut result = 0;
+        if interface.can_output(code_point) {
+            result = result | 0x01;
+        }
+        if interface.can_input(code_point) {
+            result = result | 0x02;
+        }
+
+        Ok(InstructionResult { store_value: Some(result), ..Default::default() })
+    }
+
+    fn buffer_screen(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let mode = self.get_argument(state, 0)?;
+        let previous = interface.buffer_screen(mode);
+
+        Ok(InstructionResult { store_value: Some(previous), ..Default::default() })
+    }
+
+    /// Undo isn't available, which is reported by storing -1
+    fn save_undo(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        debug!("save_undo: undo is not supported");
+
+        Ok(InstructionResult { store_value: Some(-1i16 as u16), ..Default::default() })
+    }
+
+    fn restore_undo(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        debug!("restore_undo: undo is not supported");
+
+        Ok(InstructionResult { store_value: Some(0), ..Default::default() })
+    }
+
+    /// Characters the interface can't show are printed as '?'
+    fn print_unicode(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let code_point = self.get_argument(state, 0)? as u32;
+        match std::char::from_u32(code_point) {
+            Some(c) if interface.can_output(code_point) => interface.print(&c.to_string()),
+            _ => interface.print("?")
+        }
+
+        Ok(InstructionResult::default())
+    }
+
+    fn set_true_colour(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let foreground = self.get_argument(state, 0)?;
+        let background = self.get_argument(state, 1)?;
+        debug!("set_true_colour ${:04x} ${:04x} ignored, colours are not supported", foreground, background);
+
+        Ok(InstructionResult::default())
+    }
+
+    /// Window position, size and style are kept as window properties 0-3 and 10
+    fn move_window(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let window = self.get_argument(state, 0)?;
+        let y = self.get_argument(state, 1)?;
+        let x = self.get_argument(state, 2)?;
+        interface.set_window_property(window, 0, y);
+        interface.set_window_property(window, 1, x);
+
+        Ok(InstructionResult::default())
+    }
+
+    fn window_size(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let window = self.get_argument(state, 0)?;
+        let y = self.get_argument(state, 1)?;
+        let x = self.get_argument(state, 2)?;
+        interface.set_window_property(window, 2, y);
+        interface.set_window_property(window, 3, x);
+
+        Ok(InstructionResult::default())
+    }
+
+    /// Operation 0 sets the style flags, 1 sets bits, 2 clears bits and 3 toggles bits
+    fn window_style(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let window = self.get_argument(state, 0)?;
+        let flags = self.get_argument(state, 1)?;
+        let operation = if self.operands.len() > 2 {
+            self.get_argument(state, 2)?
+        } else {
+            0
+        };
+
+        let style = interface.get_window_property(window, 10);
+        let style = match operation {
+            0 => flags,
+            1 => style | flags,
+            2 => style & !flags,
+            3 => style ^ flags,
+            _ => {
+                warn!("Invalid window_style operation {}", operation);
+                style
+            }
+        };
+        interface.set_window_property(window, 10, style);
+
+        Ok(InstructionResult::default())
+    }
+
+    fn scroll_window(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let window = self.get_argument(state, 0)?;
+        let pixels = self.get_argument(state, 1)? as i16;
+        debug!("scroll_window {} by {} ignored, pixel scrolling is not supported", window, pixels);
+
+        Ok(InstructionResult::default())
+    }
+
+    /// Discard `items` values from the game stack, or from a user stack if one is given
+    fn pop_stack(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let items = self.get_argument(state, 0)?;
+        if self.operands.len() > 1 {
+            let stack = self.get_argument(state, 1)? as usize;
+            let free = state.get_memory().get_word(stack)?;
+            state.set_word(stack, free.wrapping_add(items))?;
+        } else {
+            for _ in 0..items {
+                state.current_frame.pop()?;
+            }
+        }
+
+        Ok(InstructionResult::default())
+    }
+
+    /// Push onto a user stack, branching if there was room
+    fn push_stack(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let value = self.get_argument(state, 0)?;
+        let stack = self.get_argument(state, 1)? as usize;
+        // The first word of a user stack is the number of free slots
+        let free = state.get_memory().get_word(stack)?;
+        if free == 0 {
+            return Ok(InstructionResult { branch_condition: Some(false), ..Default::default() })
+        }
+
+        state.set_word(stack + (2 * free as usize), value)?;
+        state.set_word(stack, free - 1)?;
+        Ok(InstructionResult { branch_condition: Some(true), ..Default::default() })
+    }
+
+    /// Print each line of a formatted table: a word length followed by that many
+    /// characters, ending with a length of 0
+    fn print_form(&self, state: &mut FrameStack, interface: &mut dyn Interface) -> Result<InstructionResult,InfocomError> {
+        let mut address = self.get_argument(state, 0)? as usize;
+        let decoder = Decoder::new(state.get_memory())?;
+        loop {
+            let length = state.get_memory().get_word(address)? as usize;
+            if length == 0 {
+                break;
+            }
+
+            let mut text = String::new();
+            for i in 0..length {
+                let z = state.get_memory().get_byte(address + 2 + i)? as u16;
+                text.push(decoder.zscii_to_char(z)?);
+            }
+            interface.print(&text);
+            interface.new_line();
+            address = address + 2 + length;
+        }
+
+        Ok(InstructionResult::default())
+    }
+
+    fn make_menu(&self, state: &mut FrameStack) -> Result<InstructionResult,InfocomError> {
+        let number = self.get_argument(state, 0)?;
+        let table = self.get_argument(state, 1)?;
+        debug!("make_menu {} from ${:04x} ignored, native menus are not supported", number, table);
+
+        Ok(InstructionResult { branch_condition: Some(false), ..Default::default() })
     }
 
     pub fn execute<T>(&mut self, state: &mut FrameStack, interface: &mut T) -> Result<usize,InfocomError> 
@@ -910,97 +1742,10 @@ impl Instruction {
         T: Interface
     {
         debug!("{:?}", self);
-        let result = match state.get_memory().version {
-            Version::V(3) => {
-                if self.opcode < 0x80 || (self.opcode > 0xBf && self.opcode < 0xE0) {
-                    match self.opcode & 0x1F {
-                        0x01 => self.je(state),
-                        0x02 => self.jl(state),
-                        0x03 => self.jg(state),
-                        0x04 => self.dec_chk(state),
-                        0x05 => self.inc_chk(state),
-                        0x06 => self.jin(state),
-                        0x07 => self.test(state),
-                        0x08 => self.or(state),
-                        0x09 => self.and(state),
-                        0x0A => self.test_attr(state),
-                        0x0B => self.set_attr(state),
-                        0x0C => self.clear_attr(state),
-                        0x0D => self.store(state),
-                        0x0E => self.insert_obj(state),
-                        0x0F => self.loadw(state),
-                        0x10 => self.loadb(state),
-                        0x11 => self.get_prop(state),
-                        0x12 => self.get_prop_addr(state),
-                        0x13 => self.get_next_prop(state),
-                        0x14 => self.add(state),
-                        0x15 => self.sub(state),
-                        0x16 => self.mul(state),
-                        0x17 => self.div(state),
-                        0x18 => self.modulo(state),
-                        _ => Err(InfocomError::Memory(format!("Unimplemented opcode ${:02x}", self.opcode)))
-                    }
-                } else if self.opcode > 0x7F && self.opcode < 0xB0 {
-                    match self.opcode & 0xF {
-                        0x00 => self.jz(state),
-                        0x01 => self.get_sibling(state),
-                        0x02 => self.get_child(state),
-                        0x03 => self.get_parent(state),
-                        0x04 => self.get_prop_len(state),
-                        0x05 => self.inc(state),
-                        0x06 => self.dec(state),
-                        0x07 => self.print_addr(state),
-                        0x09 => self.remove_obj(state),
-                        0x0A => self.print_obj(state, interface),
-                        0x0B => self.ret(state),
-                        0x0C => self.jump(state),
-                        0x0D => self.print_paddr(state, interface),
-                        0x0E => self.load(state),
-                        0x0F => self.not(state),
-                        _ => Err(InfocomError::Memory(format!("Unimplemented opcode ${:02x}", self.opcode)))
-                    }
-                } else if self.opcode > 0xAF && self.opcode < 0xC0 {
-                    match self.opcode & 0xF {
-                        0x00 => self.rtrue(state),
-                        0x01 => self.rfalse(state),
-                        0x02 => self.print(state, interface),
-                        0x03 => self.print_ret(state, interface),
-                        0x04 => self.nop(state),
-                        0x05 => self.save_v1(state),
-                        0x06 => self.restore_v1(state),
-                        0x07 => self.restart(state),
-                        0x08 => self.ret_popped(state),
-                        0x09 => self.pop(state),
-                        0x0A => self.quit(state),
-                        0x0B => self.new_line(state, interface),
-                        0x0C => self.show_status(state, interface),
-                        0x0D => self.verify(state),
-                        _ => Err(InfocomError::Memory(format!("Unimplemented opcode ${:02x}", self.opcode)))
-                    }
-                } else {
-                    match self.opcode & 0x1F {
-                        0x00 => self.call(state),
-                        0x01 => self.storew(state),
-                        0x02 => self.storeb(state),
-                        0x03 => self.put_prop(state),
-                        0x04 => self.sread_v1(state, interface),
-                        0x05 => self.print_char(state, interface),
-                        0x06 => self.print_num(state, interface),
-                        0x07 => self.random(state),
-                        0x08 => self.push(state),
-                        0x09 => self.pull(state),
-                        0x0A => self.split_window(state),
-                        0x0B => self.set_window(state),
-                        0x13 => self.output_stream(state),
-                        0x14 => self.input_stream(state),
-                        0x15 => self.sound_effect(state),
-                        _ => Err(InfocomError::Memory(format!("Unimplemented opcode ${:02x}", self.opcode)))
-
-                    }
-                } 
-            },
-            _ => Err(InfocomError::Memory(format!("Unimplemented verison {:?}", state.get_memory().version)))
-        }?;
+        let result = match self.handler {
+            Some(handler) => handler(self, state, interface)?,
+            None => return Err(self.unimplemented())
+        };
 
         match result.store_value {
             Some(_) => debug!("{:?}", result),
@@ -1018,7 +1763,11 @@ impl Instruction {
         }
 
         if let Some(offset) = &self.branch_offset {
-            if result.branch_condition.unwrap() == offset.condition {
+            let condition = match result.branch_condition {
+                Some(c) => c,
+                None => return Err(InfocomError::Memory(format!("{} at ${:06x} did not produce a branch condition", self.name, self.address)))
+            };
+            if condition == offset.condition {
                 if let Some(ret) = offset.return_value {
                     return state.return_from(ret as u16)
                 }
@@ -1034,6 +1783,17 @@ impl Instruction {
     }
 }
 
+/// Effective address of an array element, validated against the story size and the 64k limit
+fn array_address(mem: &MemoryMap, array: usize, offset: usize, size: usize) -> Result<usize,InfocomError> {
+    let address = array + offset;
+    debug!("Array ${:04x} + ${:04x} => ${:04x}", array, offset, address);
+    if address + size > mem.len() || address + size > 0x10000 {
+        Err(InfocomError::Memory(format!("Array index out of range: ${:04x} + ${:04x} => ${:06x}", array, offset, address)))
+    } else {
+        Ok(address)
+    }
+}
+
 fn read_byte(mem: &Vec<u8>, address: usize) -> u8 {
     mem[address]
 }
@@ -1045,11 +1805,21 @@ fn read_word(mem: &Vec<u8>, address: usize) -> u16 {
     (((high as u16) << 8) & 0xFF00) | (low as u16 & 0xFF)
 }
 
+fn read_signed_word(mem: &Vec<u8>, address: usize) -> i16 {
+    read_word(mem, address) as i16
+}
+
+/// The target of a branch or jump: `offset` - 2 bytes from the address following the
+/// branch data or jump instruction
+fn offset_address(next: usize, offset: i16) -> isize {
+    next as isize + offset as isize - 2
+}
+
 fn get_store_variable(mem: &Vec<u8>, address: usize, opcode: u8, form: &OpcodeForm) -> Option<u8> {
     match form {
         OpcodeForm::Extended => {
             match opcode {
-              1 | 2 | 3 | 4 | 9 | 10 | 19 | 29 => { Some(read_byte(mem, address)) },
+              0 | 1 | 2 | 3 | 4 | 9 | 10 | 12 | 19 | 29 => { Some(read_byte(mem, address)) },
               _ => None
             }
         },
@@ -1110,7 +1880,7 @@ fn get_store_variable(mem: &Vec<u8>, address: usize, opcode: u8, form: &OpcodeFo
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 struct BranchOffset {
     size: usize,
     condition: bool,
@@ -1126,19 +1896,16 @@ fn decode_branch_offset(mem: &Vec<u8>, address: usize) -> BranchOffset {
         match offset {
             0 => BranchOffset { size: 1, condition, return_value: Some(0), address: None },
             1 => BranchOffset { size: 1, condition, return_value: Some(1), address: None },
-            _ => BranchOffset { size: 1, condition, return_value: None, address: Some((address as isize + offset as isize - 1) as usize) }
+            _ => BranchOffset { size: 1, condition, return_value: None, address: Some(offset_address(address + 1, offset as i16) as usize) }
         }
     } else {
-        let mut high = b1 & 0x3F;
-        if high & 0x20 == 0x20 {
-            high |= 0xC0;
-        }
-        let low = read_byte(mem, address + 1);
-        let offset:i16 = ((((high as u16) << 8) & 0xFF00) | (low as u16 & 0xFF)) as i16;
+        // 14-bit two's complement offset: shift the sign bit into bit 15 and back.  Code may
+        // lie above 64k, beyond MemoryMap::get_signed_word, so this reads the raw image.
+        let offset:i16 = (read_signed_word(mem, address) << 2) >> 2;
         match offset {
             0 => BranchOffset { size: 2, condition, return_value: Some(0), address: None },
             1 => BranchOffset { size: 2, condition, return_value: Some(1), address: None },
-            _ => BranchOffset { size: 2, condition, return_value: None, address: Some((address as isize + offset as isize) as usize) }
+            _ => BranchOffset { size: 2, condition, return_value: None, address: Some(offset_address(address + 2, offset) as usize) }
         }
     }
 }
@@ -1181,7 +1948,8 @@ fn get_branch_offset(mem: &Vec<u8>, address: usize, opcode: u8, form: &OpcodeFor
             // Variable VAR
             0xE0..=0xFF => {
                 match opcode & 0x1F {
-                    17 | 31 => { Some(decode_branch_offset(mem, address)) },
+                    // scan_table, check_arg_count
+                    0x17 | 0x1F => { Some(decode_branch_offset(mem, address)) },
                     _ => None
                 }
             }
@@ -1189,26 +1957,221 @@ fn get_branch_offset(mem: &Vec<u8>, address: usize, opcode: u8, form: &OpcodeFor
     }
 }
 
-fn get_literal_string(mem: &Vec<u8>, address: usize, opcode: u8, form: &OpcodeForm) -> Option<usize> {
+/// Decode the literal string following `print` and `print_ret`, returning the text and its
+/// encoded length in bytes
+fn get_literal_string(mem: &MemoryMap, address: usize, opcode: u8, form: &OpcodeForm) -> Result<Option<(String, usize)>, InfocomError> {
     match form {
-        OpcodeForm::Extended => None,
+        OpcodeForm::Extended => Ok(None),
         _ => match opcode {
-            0xB2 | 0xB3 => {
-                let mut size = 0;
-                loop {
-                    let v = read_word(mem, address + size);
-                    size += 2;
-                    if v & 0x8000 == 0x8000 {
-                        break;
-                    }
-                }
-                Some(size)
+            0xB2 | 0xB3 => Ok(Some(Decoder::new(mem)?.decode_with_len(address)?)),
+            _ => Ok(None)
+        }
+    }
+}
+
+/// Operand count of an opcode, which together with its number identifies the instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OperandCount {
+    Op0,
+    Op1,
+    Op2,
+    Var,
+    Ext
+}
+
+impl OperandCount {
+    /// The operand count and opcode number of `opcode_byte`, or of `ext_opcode` after $BE
+    fn of(opcode_byte: u8, ext_opcode: Option<u8>) -> (OperandCount, u8) {
+        match OpcodeForm::from(opcode_byte) {
+            OpcodeForm::Extended => (OperandCount::Ext, ext_opcode.unwrap_or(0)),
+            OpcodeForm::Long => (OperandCount::Op2, opcode_byte & 0x1F),
+            OpcodeForm::Short => if opcode_byte & 0x30 == 0x30 {
+                (OperandCount::Op0, opcode_byte & 0xF)
+            } else {
+                (OperandCount::Op1, opcode_byte & 0xF)
             },
-            _ => None
+            OpcodeForm::Variable => if opcode_byte & 0x20 == 0x20 {
+                (OperandCount::Var, opcode_byte & 0x1F)
+            } else {
+                (OperandCount::Op2, opcode_byte & 0x1F)
+            }
         }
     }
 }
 
+type Handler = fn(&Instruction, &mut FrameStack, &mut dyn Interface) -> Result<InstructionResult,InfocomError>;
+
+/// The single table of instructions: operand count, opcode number, first and last version,
+/// name and handler.  Decoding takes the name from here and execution calls the handler
+/// from the same entry, so the two can't disagree.
+const OPCODES: &[(OperandCount, u8, u8, u8, &str, Handler)] = &[
+    // 2OP
+    (OperandCount::Op2, 0x01, 1, 8, "je", |i, s, _| i.je(s)),
+    (OperandCount::Op2, 0x02, 1, 8, "jl", |i, s, _| i.jl(s)),
+    (OperandCount::Op2, 0x03, 1, 8, "jg", |i, s, _| i.jg(s)),
+    (OperandCount::Op2, 0x04, 1, 8, "dec_chk", |i, s, _| i.dec_chk(s)),
+    (OperandCount::Op2, 0x05, 1, 8, "inc_chk", |i, s, _| i.inc_chk(s)),
+    (OperandCount::Op2, 0x06, 1, 8, "jin", |i, s, _| i.jin(s)),
+    (OperandCount::Op2, 0x07, 1, 8, "test", |i, s, _| i.test(s)),
+    (OperandCount::Op2, 0x08, 1, 8, "or", |i, s, _| i.or(s)),
+    (OperandCount::Op2, 0x09, 1, 8, "and", |i, s, _| i.and(s)),
+    (OperandCount::Op2, 0x0A, 1, 8, "test_attr", |i, s, _| i.test_attr(s)),
+    (OperandCount::Op2, 0x0B, 1, 8, "set_attr", |i, s, _| i.set_attr(s)),
+    (OperandCount::Op2, 0x0C, 1, 8, "clear_attr", |i, s, _| i.clear_attr(s)),
+    (OperandCount::Op2, 0x0D, 1, 8, "store", |i, s, _| i.store(s)),
+    (OperandCount::Op2, 0x0E, 1, 8, "insert_obj", |i, s, _| i.insert_obj(s)),
+    (OperandCount::Op2, 0x0F, 1, 8, "loadw", |i, s, _| i.loadw(s)),
+    (OperandCount::Op2, 0x10, 1, 8, "loadb", |i, s, _| i.loadb(s)),
+    (OperandCount::Op2, 0x11, 1, 8, "get_prop", |i, s, _| i.get_prop(s)),
+    (OperandCount::Op2, 0x12, 1, 8, "get_prop_addr", |i, s, _| i.get_prop_addr(s)),
+    (OperandCount::Op2, 0x13, 1, 8, "get_next_prop", |i, s, _| i.get_next_prop(s)),
+    (OperandCount::Op2, 0x14, 1, 8, "add", |i, s, _| i.add(s)),
+    (OperandCount::Op2, 0x15, 1, 8, "sub", |i, s, _| i.sub(s)),
+    (OperandCount::Op2, 0x16, 1, 8, "mul", |i, s, _| i.mul(s)),
+    (OperandCount::Op2, 0x17, 1, 8, "div", |i, s, _| i.div(s)),
+    (OperandCount::Op2, 0x18, 1, 8, "mod", |i, s, _| i.modulo(s)),
+    (OperandCount::Op2, 0x19, 1, 8, "call_2s", |i, s, _| i.call_2s(s)),
+    (OperandCount::Op2, 0x1A, 1, 8, "call_2n", |i, s, _| i.call_2n(s)),
+    (OperandCount::Op2, 0x1B, 1, 8, "set_colour", |i, s, _| i.set_colour(s)),
+    (OperandCount::Op2, 0x1C, 1, 8, "throw", |i, s, _| i.throw(s)),
+    // 1OP
+    (OperandCount::Op1, 0x00, 1, 8, "jz", |i, s, _| i.jz(s)),
+    (OperandCount::Op1, 0x01, 1, 8, "get_sibling", |i, s, _| i.get_sibling(s)),
+    (OperandCount::Op1, 0x02, 1, 8, "get_child", |i, s, _| i.get_child(s)),
+    (OperandCount::Op1, 0x03, 1, 8, "get_parent", |i, s, _| i.get_parent(s)),
+    (OperandCount::Op1, 0x04, 1, 8, "get_prop_len", |i, s, _| i.get_prop_len(s)),
+    (OperandCount::Op1, 0x05, 1, 8, "inc", |i, s, _| i.inc(s)),
+    (OperandCount::Op1, 0x06, 1, 8, "dec", |i, s, _| i.dec(s)),
+    (OperandCount::Op1, 0x07, 1, 8, "print_addr", |i, s, f| i.print_addr(s, f)),
+    (OperandCount::Op1, 0x08, 1, 8, "call_1s", |i, s, _| i.call_1s(s)),
+    (OperandCount::Op1, 0x09, 1, 8, "remove_obj", |i, s, _| i.remove_obj(s)),
+    (OperandCount::Op1, 0x0A, 1, 8, "print_obj", |i, s, f| i.print_obj(s, f)),
+    (OperandCount::Op1, 0x0B, 1, 8, "ret", |i, s, _| i.ret(s)),
+    (OperandCount::Op1, 0x0C, 1, 8, "jump", |i, s, _| i.jump(s)),
+    (OperandCount::Op1, 0x0D, 1, 8, "print_paddr", |i, s, f| i.print_paddr(s, f)),
+    (OperandCount::Op1, 0x0E, 1, 8, "load", |i, s, _| i.load(s)),
+    (OperandCount::Op1, 0x0F, 1, 4, "not", |i, s, _| i.not(s)),
+    (OperandCount::Op1, 0x0F, 5, 8, "call_1n", |i, s, _| i.call_1n(s)),
+    // 0OP
+    (OperandCount::Op0, 0x00, 1, 8, "rtrue", |i, s, _| i.rtrue(s)),
+    (OperandCount::Op0, 0x01, 1, 8, "rfalse", |i, s, _| i.rfalse(s)),
+    (OperandCount::Op0, 0x02, 1, 8, "print", |i, s, f| i.print(s, f)),
+    (OperandCount::Op0, 0x03, 1, 8, "print_ret", |i, s, f| i.print_ret(s, f)),
+    (OperandCount::Op0, 0x04, 1, 8, "nop", |i, s, _| i.nop(s)),
+    (OperandCount::Op0, 0x05, 1, 3, "save", |i, s, f| i.save_v1(s, f)),
+    (OperandCount::Op0, 0x05, 4, 8, "save", |i, s, f| i.save_v4(s, f)),
+    (OperandCount::Op0, 0x06, 1, 3, "restore", |i, s, f| i.restore_v1(s, f)),
+    (OperandCount::Op0, 0x06, 4, 8, "restore", |i, s, f| i.restore_v4(s, f)),
+    (OperandCount::Op0, 0x07, 1, 8, "restart", |i, s, _| i.restart(s)),
+    (OperandCount::Op0, 0x08, 1, 8, "ret_popped", |i, s, _| i.ret_popped(s)),
+    (OperandCount::Op0, 0x09, 1, 4, "pop", |i, s, _| i.pop(s)),
+    (OperandCount::Op0, 0x09, 5, 8, "catch", |i, s, _| i.catch(s)),
+    (OperandCount::Op0, 0x0A, 1, 8, "quit", |i, s, _| i.quit(s)),
+    (OperandCount::Op0, 0x0B, 1, 8, "new_line", |i, s, f| i.new_line(s, f)),
+    (OperandCount::Op0, 0x0C, 1, 8, "show_status", |i, s, f| i.show_status(s, f)),
+    (OperandCount::Op0, 0x0D, 1, 8, "verify", |i, s, _| i.verify(s)),
+    (OperandCount::Op0, 0x0F, 1, 8, "piracy", |i, s, _| i.piracy(s)),
+    // VAR
+    (OperandCount::Var, 0x00, 1, 3, "call", |i, s, _| i.call(s)),
+    (OperandCount::Var, 0x00, 4, 8, "call_vs", |i, s, _| i.call(s)),
+    (OperandCount::Var, 0x01, 1, 8, "storew", |i, s, _| i.storew(s)),
+    (OperandCount::Var, 0x02, 1, 8, "storeb", |i, s, _| i.storeb(s)),
+    (OperandCount::Var, 0x03, 1, 8, "put", |i, s, _| i.put_prop(s)),
+    (OperandCount::Var, 0x04, 1, 4, "sread", |i, s, f| i.sread_v1(s, f)),
+    (OperandCount::Var, 0x04, 5, 8, "aread", |i, s, f| i.aread(s, f)),
+    (OperandCount::Var, 0x05, 1, 8, "print_char", |i, s, f| i.print_char(s, f)),
+    (OperandCount::Var, 0x06, 1, 8, "print_num", |i, s, f| i.print_num(s, f)),
+    (OperandCount::Var, 0x07, 1, 8, "random", |i, s, _| i.random(s)),
+    (OperandCount::Var, 0x08, 1, 8, "push", |i, s, _| i.push(s)),
+    (OperandCount::Var, 0x09, 1, 8, "pull", |i, s, _| i.pull(s)),
+    (OperandCount::Var, 0x0A, 1, 8, "split_window", |i, s, f| i.split_window(s, f)),
+    (OperandCount::Var, 0x0B, 1, 8, "set_window", |i, s, f| i.set_window(s, f)),
+    (OperandCount::Var, 0x0C, 1, 8, "call_vs2", |i, s, _| i.call_vs2(s)),
+    (OperandCount::Var, 0x0D, 1, 8, "erase_window", |i, s, f| i.erase_window(s, f)),
+    (OperandCount::Var, 0x0E, 1, 8, "erase_line", |i, s, f| i.erase_line(s, f)),
+    (OperandCount::Var, 0x0F, 1, 8, "set_cursor", |i, s, f| i.set_cursor(s, f)),
+    (OperandCount::Var, 0x10, 1, 8, "get_cursor", |i, s, f| i.get_cursor(s, f)),
+    (OperandCount::Var, 0x11, 1, 8, "set_text_style", |i, s, f| i.set_text_style(s, f)),
+    (OperandCount::Var, 0x12, 1, 8, "buffer_mode", |i, s, f| i.buffer_mode(s, f)),
+    (OperandCount::Var, 0x13, 1, 8, "output_stream", |i, s, f| i.output_stream(s, f)),
+    (OperandCount::Var, 0x14, 1, 8, "input_stream", |i, s, f| i.input_stream(s, f)),
+    (OperandCount::Var, 0x15, 1, 8, "sound_effect", |i, s, f| i.sound_effect(s, f)),
+    (OperandCount::Var, 0x16, 1, 8, "read_char", |i, s, f| i.read_char(s, f)),
+    (OperandCount::Var, 0x17, 1, 8, "scan_table", |i, s, _| i.scan_table(s)),
+    (OperandCount::Var, 0x18, 1, 8, "not", |i, s, _| i.not(s)),
+    (OperandCount::Var, 0x19, 1, 8, "call_vn", |i, s, _| i.call_vn(s)),
+    (OperandCount::Var, 0x1A, 1, 8, "call_vn2", |i, s, _| i.call_vn2(s)),
+    (OperandCount::Var, 0x1B, 1, 8, "tokenise", |i, s, _| i.tokenise(s)),
+    (OperandCount::Var, 0x1C, 1, 8, "encode_text", |i, s, _| i.encode_text(s)),
+    (OperandCount::Var, 0x1D, 1, 8, "copy_table", |i, s, _| i.copy_table(s)),
+    (OperandCount::Var, 0x1E, 1, 8, "print_table", |i, s, f| i.print_table(s, f)),
+    (OperandCount::Var, 0x1F, 1, 8, "check_arg_count", |i, s, _| i.check_arg_count(s)),
+    // EXT
+    (OperandCount::Ext, 0x00, 1, 8, "save", |i, s, f| i.save_v4(s, f)),
+    (OperandCount::Ext, 0x01, 1, 8, "restore", |i, s, f| i.restore_v4(s, f)),
+    (OperandCount::Ext, 0x02, 1, 8, "log_shift", |i, s, _| i.log_shift(s)),
+    (OperandCount::Ext, 0x03, 1, 8, "art_shift", |i, s, _| i.art_shift(s)),
+    (OperandCount::Ext, 0x04, 1, 8, "set_font", |i, s, f| i.set_font(s, f)),
+    (OperandCount::Ext, 0x05, 1, 8, "draw_picture", |i, s, _| i.draw_picture(s)),
+    (OperandCount::Ext, 0x06, 1, 8, "picture_data", |i, s, _| i.picture_data(s)),
+    (OperandCount::Ext, 0x07, 1, 8, "erase_picture", |i, s, _| i.erase_picture(s)),
+    (OperandCount::Ext, 0x08, 1, 8, "set_margins", |i, s, f| i.set_margins(s, f)),
+    (OperandCount::Ext, 0x09, 1, 8, "save_undo", |i, s, _| i.save_undo(s)),
+    (OperandCount::Ext, 0x0A, 1, 8, "restore_undo", |i, s, _| i.restore_undo(s)),
+    (OperandCount::Ext, 0x0B, 1, 8, "print_unicode", |i, s, f| i.print_unicode(s, f)),
+    (OperandCount::Ext, 0x0C, 1, 8, "check_unicode", |i, s, f| i.check_unicode(s, f)),
+    (OperandCount::Ext, 0x0D, 1, 8, "set_true_colour", |i, s, _| i.set_true_colour(s)),
+    (OperandCount::Ext, 0x10, 1, 8, "move_window", |i, s, f| i.move_window(s, f)),
+    (OperandCount::Ext, 0x11, 1, 8, "window_size", |i, s, f| i.window_size(s, f)),
+    (OperandCount::Ext, 0x12, 1, 8, "window_style", |i, s, f| i.window_style(s, f)),
+    (OperandCount::Ext, 0x13, 1, 8, "get_wind_prop", |i, s, f| i.get_wind_prop(s, f)),
+    (OperandCount::Ext, 0x14, 1, 8, "scroll_window", |i, s, _| i.scroll_window(s)),
+    (OperandCount::Ext, 0x15, 1, 8, "pop_stack", |i, s, _| i.pop_stack(s)),
+    (OperandCount::Ext, 0x16, 1, 8, "read_mouse", |i, s, _| i.read_mouse(s)),
+    (OperandCount::Ext, 0x17, 1, 8, "mouse_window", |i, s, _| i.mouse_window(s)),
+    (OperandCount::Ext, 0x18, 1, 8, "push_stack", |i, s, _| i.push_stack(s)),
+    (OperandCount::Ext, 0x19, 1, 8, "put_wind_prop", |i, s, f| i.put_wind_prop(s, f)),
+    (OperandCount::Ext, 0x1A, 1, 8, "print_form", |i, s, f| i.print_form(s, f)),
+    (OperandCount::Ext, 0x1B, 1, 8, "make_menu", |i, s, _| i.make_menu(s)),
+    (OperandCount::Ext, 0x1C, 1, 8, "picture_table", |i, s, _| i.picture_table(s)),
+    (OperandCount::Ext, 0x1D, 1, 8, "buffer_screen", |i, s, f| i.buffer_screen(s, f)),
+];
+
+/// The table entry for an opcode in `version`, giving its name and handler
+fn lookup_opcode(version: u8, opcode_byte: u8, ext_opcode: Option<u8>) -> Option<(&'static str, Handler)> {
+    let (count, number) = OperandCount::of(opcode_byte, ext_opcode);
+    OPCODES.iter()
+        .find(|(c, n, first, last, _, _)| *c == count && *n == number && version >= *first && version <= *last)
+        .map(|(_, _, _, _, name, handler)| (*name, *handler))
+}
+
+/// The name of an opcode and its handler, if it has one.  Extended opcodes are named
+/// "EXT <name>" and undefined opcodes by their number.
+fn opcode_name(version: u8, opcode_byte: u8, ext_opcode: Option<u8>) -> (String, Option<Handler>) {
+    match (lookup_opcode(version, opcode_byte, ext_opcode), ext_opcode) {
+        (Some((name, handler)), Some(_)) => (format!("EXT {}", name), Some(handler)),
+        (Some((name, handler)), None) => (String::from(name), Some(handler)),
+        (None, Some(o)) => (format!("EXT ${:02x}", o), None),
+        (None, None) => (format!("${:02x}", opcode_byte), None)
+    }
+}
+
+/// Decode the instruction at `address`, using the `FrameStack` instruction cache when it is
+/// enabled.  Only instructions in static memory are cached, since dynamic memory can change.
+pub fn decode_cached(state: &mut FrameStack, address: usize) -> Result<Instruction, InfocomError> {
+    if address < state.get_memory().static_mark() {
+        return decode_instruction(state, address)
+    }
+
+    if let Some(i) = state.cached_instruction(address) {
+        return Ok(i.clone())
+    }
+
+    let i = decode_instruction(state, address)?;
+    state.cache_instruction(address, &i);
+    Ok(i)
+}
+
 pub fn decode_instruction(state: &FrameStack, address: usize) -> Result<Instruction, InfocomError> {
     let mem = state.get_memory().get_memory();
     let mut opcode_byte = read_byte(&mem, address);
@@ -1303,167 +2266,1183 @@ pub fn decode_instruction(state: &FrameStack, address: usize) -> Result<Instruct
         }
     }
 
-    let store_variable = get_store_variable(&mem, address + skip, opcode_byte, &form);
+    // Extended instructions are identified by the opcode following 0xBE
+    let store_variable = get_store_variable(&mem, address + skip, ext_opcode.unwrap_or(opcode_byte), &form);
     if let Some(_) = store_variable {
         skip = skip + 1;
     }
 
-    let branch_offset = get_branch_offset(&mem, address + skip, opcode_byte, &form);
+    let branch_offset = get_branch_offset(&mem, address + skip, ext_opcode.unwrap_or(opcode_byte), &form);
     if let Some(b) = &branch_offset {
         skip += b.size;
     }
 
-    if let Some(l) = get_literal_string(&mem, address + skip, opcode_byte, &form) {
-        skip += l;
-    }
-    
-    let name = match opcode_byte {
-        0x01 | 0x21 | 0x41 | 0x61 | 0xC1 => String::from("je"),
-        0x02 | 0x22 | 0x42 | 0x62 | 0xC2 => String::from("jl"),
-        0x03 | 0x23 | 0x43 | 0x63 | 0xC3 => String::from("jg"),
-        0x04 | 0x24 | 0x44 | 0x64 | 0xC4 => String::from("dec_chk"),
-        0x05 | 0x25 | 0x45 | 0x65 | 0xC5 => String::from("inc_chk"),
-        0x06 | 0x26 | 0x46 | 0x66 | 0xC6 => String::from("jin"),
-        0x07 | 0x27 | 0x47 | 0x67 | 0xC7 => String::from("test"),
-        0x08 | 0x28 | 0x48 | 0x68 | 0xC8 => String::from("or"),
-        0x09 | 0x29 | 0x49 | 0x69 | 0xC9 => String::from("and"),
-        0x0A | 0x2A | 0x4A | 0x6A | 0xCA => String::from("test_attr"),
-        0x0B | 0x2B | 0x4B | 0x6B | 0xCB => String::from("set_attr"),
-        0x0C | 0x2C | 0x4C | 0x6C | 0xCC => String::from("clear_attr"),
-        0x0D | 0x2D | 0x4D | 0x6D | 0xCD => String::from("store"),
-        0x0E | 0x2E | 0x4E | 0x6E | 0xCE => String::from("insert_obj"),
-        0x0F | 0x2F | 0x4F | 0x6F | 0xCF => String::from("loadw"),
-        0x10 | 0x30 | 0x50 | 0x70 | 0xD0 => String::from("loadb"),
-        0x11 | 0x31 | 0x51 | 0x71 | 0xD1 => String::from("get_prop"),
-        0x12 | 0x32 | 0x52 | 0x72 | 0xD2 => String::from("get_prop_addr"),
-        0x13 | 0x33 | 0x53 | 0x73 | 0xD3 => String::from("get_next_prop"),
-        0x14 | 0x34 | 0x54 | 0x74 | 0xD4 => String::from("add"),
-        0x15 | 0x35 | 0x55 | 0x75 | 0xD5 => String::from("sub"),
-        0x16 | 0x36 | 0x56 | 0x76 | 0xD6 => String::from("mul"),
-        0x17 | 0x37 | 0x57 | 0x77 | 0xD7 => String::from("div"),
-        0x18 | 0x38 | 0x58 | 0x78 | 0xD8 => String::from("mod"),
-        0x19 | 0x39 | 0x59 | 0x79 | 0xD9 => String::from("call_2s"),
-        0x1A | 0x3A | 0x5A | 0x7A | 0xDA => String::from("call_2n"),
-        0x1B | 0x3B | 0x5B | 0x7B | 0xDB => String::from("set_colour"),
-        0x1C | 0x3C | 0x5C | 0x7C | 0xDC => String::from("throw"),
-        0x80 | 0x90 | 0xA0 => String::from("jz"),
-        0x81 | 0x91 | 0xA1 => String::from("get_sibling"),
-        0x82 | 0x92 | 0xA2 => String::from("get_child"),
-        0x83 | 0x93 | 0xA3 => String::from("get_parent"),
-        0x84 | 0x94 | 0xA4 => String::from("get_prop_len"),
-        0x85 | 0x95 | 0xA5 => String::from("inc"),
-        0x86 | 0x96 | 0xA6 => String::from("dec"),
-        0x87 | 0x97 | 0xA7 => String::from("print_addr"),
-        0x88 | 0x98 | 0xA8 => String::from("call_1s"),
-        0x89 | 0x99 | 0xA9 => String::from("remove_obj"),
-        0x8A | 0x9A | 0xAA => String::from("print_obj"),
-        0x8B | 0x9B | 0xAB => String::from("ret"),
-        0x8C | 0x9C | 0xAC => String::from("jump"),
-        0x8D | 0x9D | 0xAD => String::from("print_paddr"),
-        0x8E | 0x9E | 0xAE => String::from("load"),
-        0x8F | 0x9F | 0xAF => match state.get_memory().version {
-            Version::V(1) | Version::V(2) | Version::V(3) | Version::V(4) => String::from("not"),
-            _ => String::from("call_1n")
-        },
-        0xB0 => String::from("rtrue"),
-        0xB1 => String::from("rfalse"),
-        0xB2 => String::from("print"),
-        0xB3 => String::from("print_ret"),
-        0xB4 => String::from("nop"),
-        0xB5 => String::from("save"),
-        0xB6 => String::from("restore"),
-        0xB7 => String::from("restart"),
-        0xB8 => String::from("ret_popped"),
-        0xB9 => String::from("rtrue"),
-        0xBA => String::from("quit"),
-        0xBB => String::from("new_line"),
-        0xBC => String::from("show_status"),
-        0xBD => String::from("verify"),
-        0xBE => {
-            let mut s = String::from("EXT ");
-            let default = format!("${:02x}", ext_opcode.unwrap());
-            s.push_str(match ext_opcode.unwrap() {
-                0x00 => "save",
-                0x01 => "restore",
-                0x02 => "log_shift",
-                0x03 => "art_shift",
-                0x04 => "set_font",
-                0x05 => "draw_picture",
-                0x06 => "picture_data",
-                0x07 => "erase_picture",
-                0x08 => "set_margins",
-                0x09 => "save_undo",
-                0x0A => "restore_undo",
-                0x0B => "print_unicode",
-                0x0C => "check_unicode",
-                0x0D => "set_true_colour",
-                0x10 => "move_window",
-                0x11 => "window_size",
-                0x12 => "window_style",
-                0x13 => "get_wind_prop",
-                0x14 => "scroll_window",
-                0x15 => "pop_stack",
-                0x16 => "read_mouse",
-                0x17 => "mouse_window",
-                0x18 => "push_stack",
-                0x19 => "put_wind_prop",
-                0x1A => "print_form",
-                0x1B => "make_menu",
-                0x1C => "picture_table",
-                0x1D => "buffer_screen",
-                _ => &default
-            });
-            s
-        },
-        0xBF => String::from("piracy"),
-        0xE0 => match state.get_memory().version {
-            Version::V(1) | Version::V(2) | Version::V(3) => String::from("call"),
-            _ => String::from("call_vs")
-        },
-        0xE1 => String::from("storew"),
-        0xE2 => String::from("storeb"),
-        0xE3 => String::from("put"),
-        0xE4 => match state.get_memory().version {
-            Version::V(v) => {
-                match v {
-                    1..=4 => String::from("sread"),
-                    _ => String::from("aread")
-                }
-            }
+    let literal = match get_literal_string(state.get_memory(), address + skip, opcode_byte, &form)? {
+        Some((text, length)) => {
+            skip += length;
+            Some(text)
         },
-        0xE5 => String::from("print_char"),
-        0xE6 => String::from("print_num"),
-        0xE7 => String::from("random"),
-        0xE8 => String::from("push"),
-        0xE9 => String::from("pull"),
-        0xEA => String::from("split_window"),
-        0xEB => String::from("set_window"),
-        0xEC => String::from("call_vs2"),
-        0xED => String::from("erase_window"),
-        0xEE => String::from("erase_line"),
-        0xEF => String::from("set_cursor"),
-        0xF0 => String::from("get_cursor"),
-        0xF1 => String::from("set_text_style"),
-        0xF2 => String::from("buffer_mode"),
-        0xF3 => String::from("output_stream"),
-        0xF4 => String::from("input_stream"),
-        0xF5 => String::from("sound_effect"),
-        0xF6 => String::from("read_char"),
-        0xF7 => String::from("scan_table"),
-        0xF8 => String::from("not"),
-        0xF9 => String::from("call_vn"),
-        0xFA => String::from("call_vn2"),
-        0xFB => String::from("tokenize"),
-        0xFC => String::from("encode_text"),
-        0xFD => String::from("copy_table"),
-        0xFE => String::from("print_table"),
-        0xFF => String::from("check_arg_count"),
-        _ => format!("${:02x}", opcode_byte)
+        None => None
     };
+    
+    let (name, handler) = opcode_name(state.get_memory().version_number(), opcode_byte, ext_opcode);
 
     if let Some(o) = ext_opcode {
         opcode_byte = o;
     }
 
-    Ok(Instruction { address, name, form, opcode: opcode_byte, operand_types, operands, store_variable, branch_offset, next_pc: address + skip })
+    Ok(Instruction { address, name, form, opcode: opcode_byte, operand_types, operands, store_variable, branch_offset, literal, handler, next_pc: address + skip })
+}
+
+/// Execute up to `max_instructions` instructions starting at `pc`, stopping before any
+/// instruction that reads input when the interface has no input available.
+pub fn run_n<T>(state: &mut FrameStack, interface: &mut T, pc: usize, max_instructions: usize) -> Result<RunResult, InfocomError>
+where
+    T: Interface
+{
+    state.set_pc(pc);
+    for n in 0..max_instructions {
+        match state.step(interface)? {
+            StepOutcome::Running => {},
+            StepOutcome::AwaitingInput => return Ok(RunResult { pc: state.pc(), instructions: n, awaiting_input: true }),
+            StepOutcome::Halted => return Ok(RunResult { pc: state.pc(), instructions: n, awaiting_input: false })
+        }
+    }
+
+    Ok(RunResult { pc: state.pc(), instructions: max_instructions, awaiting_input: false })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::blorb::Blorb;
+    use super::super::test_util::{blorb, pack_zchars, zstring, StoryBuilder, TestInterface, CODE};
+
+    /// Decode and execute the instruction at `address`, returning the next pc
+    fn execute_at(state: &mut FrameStack, interface: &mut TestInterface, address: usize) -> Result<usize, InfocomError> {
+        decode_instruction(state, address)?.execute(state, interface)
+    }
+
+    #[test]
+    fn branch_offsets_sign_extend_from_14_bits() {
+        let mut mem = vec![0; 0x4000];
+        // Largest positive offset, 0x1FFF, branching on true
+        mem[0x3000] = 0x9F;
+        mem[0x3001] = 0xFF;
+        // Smallest negative offset, 0x2000
+        mem[0x3002] = 0xA0;
+        mem[0x3003] = 0x00;
+        // -10, branching on false
+        mem[0x3004] = 0x3F;
+        mem[0x3005] = 0xF6;
+
+        let b = decode_branch_offset(&mem, 0x3000);
+        assert_eq!(b.size, 2);
+        assert!(b.condition);
+        assert_eq!(b.address, Some(0x3000 + 8191));
+
+        let b = decode_branch_offset(&mem, 0x3002);
+        assert_eq!(b.address, Some(0x3002 - 8192));
+
+        let b = decode_branch_offset(&mem, 0x3004);
+        assert!(!b.condition);
+        assert_eq!(b.address, Some(0x3004 - 10));
+    }
+
+    #[test]
+    fn jump_uses_the_branch_offset_calculation() {
+        // jump -10, jump +10, jump +$7FFF, jump -$2000
+        let mut mem = StoryBuilder::new(3)
+            .code(&[0x8C, 0xFF, 0xF6, 0x8C, 0x00, 0x0A, 0x8C, 0x7F, 0xFF, 0x8C, 0xE0, 0x00])
+            .byte(0x1100, 0)
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 3 - 10 - 2);
+        assert_eq!(execute_at(&mut state, &mut interface, CODE + 3).unwrap(), CODE + 6 + 10 - 2);
+
+        // Jumps outside of story memory are errors rather than wild addresses
+        assert!(execute_at(&mut state, &mut interface, CODE + 6).is_err());
+        assert!(execute_at(&mut state, &mut interface, CODE + 9).is_err());
+    }
+
+    #[test]
+    fn set_cursor_and_get_cursor() {
+        // set_cursor 2 10; get_cursor $0700
+        let mut mem = StoryBuilder::new(5).code(&[0xEF, 0x5F, 0x02, 0x0A, 0xF0, 0x3F, 0x07, 0x00]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        interface.window = 1;
+
+        let next = execute_at(&mut state, &mut interface, CODE).unwrap();
+        assert_eq!(interface.cursor, (2, 10));
+        execute_at(&mut state, &mut interface, next).unwrap();
+        assert_eq!(state.get_memory().get_word(0x700).unwrap(), 2);
+        assert_eq!(state.get_memory().get_word(0x702).unwrap(), 10);
+    }
+
+    #[test]
+    fn set_cursor_in_the_v4_lower_window_is_ignored() {
+        let mut mem = StoryBuilder::new(4).code(&[0xEF, 0x5F, 0x02, 0x0A]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+
+        execute_at(&mut state, &mut interface, CODE).unwrap();
+        assert_eq!(interface.cursor, (1, 1));
+    }
+
+    #[test]
+    fn set_font_stores_the_previous_font() {
+        // set_font 4 -> G00, set_font 0 -> G01, set_font 3 -> G02
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xBE, 0x04, 0x7F, 0x04, 0x10,
+                    0xBE, 0x04, 0x7F, 0x00, 0x11,
+                    0xBE, 0x04, 0x7F, 0x03, 0x12])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        let mut pc = CODE;
+        for _ in 0..3 {
+            pc = execute_at(&mut state, &mut interface, pc).unwrap();
+        }
+        assert_eq!(pc, CODE + 15);
+        assert_eq!(interface.font, 4);
+
+        // Font 0 reads the current font, and unavailable fonts store 0 and change nothing
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 1);
+        assert_eq!(state.get_variable(0x11, false).unwrap(), 4);
+        assert_eq!(state.get_variable(0x12, false).unwrap(), 0);
+    }
+
+    #[test]
+    fn text_buffer_layouts_change_in_v5() {
+        // sread $700 $780 in V4, aread $700 $780 -> G00 in V5
+        for (version, code) in &[(4, &[0xE4, 0x0F, 0x07, 0x00, 0x07, 0x80][..]),
+                                 (5, &[0xE4, 0x0F, 0x07, 0x00, 0x07, 0x80, 0x10][..])] {
+            let mut mem = StoryBuilder::new(*version).code(code).byte(0x700, 20).byte(0x780, 5).build();
+            let mut state = FrameStack::new(&mut mem).unwrap();
+            let mut interface = TestInterface::with_input(&["go north"]);
+            assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + code.len());
+            let text:Vec<u8> = (0..10).map(|i| state.get_memory().get_byte(0x700 + i).unwrap()).collect();
+            if *version == 4 {
+                // Text from byte 1 with a terminating 0
+                assert_eq!(text, b"\x14go north\0");
+            } else {
+                // The length in byte 1 and text from byte 2
+                assert_eq!(text, b"\x14\x08go north");
+            }
+        }
+    }
+
+    #[test]
+    fn aread_stores_the_terminating_character() {
+        // aread $200 -> G00, with a 20 character text buffer
+        let story = |table: &[u8]| StoryBuilder::new(5)
+            .code(&[0xE4, 0x3F, 0x02, 0x00, 0x10])
+            .byte(0x200, 20)
+            .word(0x2E, 0x700)
+            .bytes(0x700, table)
+            .build();
+
+        // An up arrow ends input when it is in the terminating characters table
+        for (table, terminator) in &[(&[129, 0][..], 129), (&[130, 0][..], 13)] {
+            let mut mem = story(table);
+            let mut state = FrameStack::new(&mut mem).unwrap();
+            let mut interface = TestInterface::with_input(&["north"]);
+            interface.terminators.push_back(129);
+            execute_at(&mut state, &mut interface, CODE).unwrap();
+            assert_eq!(state.get_variable(0x10, false).unwrap(), *terminator);
+            assert_eq!(state.get_memory().get_byte(0x201).unwrap(), 5);
+        }
+
+        // 255 stands for every function key, and characters that can't end input are ignored
+        let mut mem = story(&[255, 65, 0]);
+        let state = FrameStack::new(&mut mem).unwrap();
+        let mut terminators: Vec<u16> = state.terminating_characters.iter().cloned().collect();
+        terminators.sort();
+        assert_eq!(terminators, vec![13, 255]);
+    }
+
+    #[test]
+    fn window_properties_round_trip_through_the_interface() {
+        // put_wind_prop 1 10 $1234, then get_wind_prop 1 10 -> G00
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xBE, 0x19, 0x53, 0x01, 0x0A, 0x12, 0x34,
+                    0xBE, 0x13, 0x5F, 0x01, 0x0A, 0x10])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        let pc = execute_at(&mut state, &mut interface, CODE).unwrap();
+        assert_eq!(interface.windows[1].get_property(10), Some(0x1234));
+        assert_eq!(execute_at(&mut state, &mut interface, pc).unwrap(), CODE + 13);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 0x1234);
+    }
+
+    #[test]
+    fn set_margins_sets_the_window_margin_properties() {
+        // set_margins 2 3 1, then set_margins 4 5 in the current window
+        let mut mem = StoryBuilder::new(6)
+            .code(&[0xBE, 0x08, 0x57, 0x02, 0x03, 0x01,
+                    0xBE, 0x08, 0x5F, 0x04, 0x05])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 6);
+        assert_eq!(interface.windows[1].get_property(6), Some(2));
+        assert_eq!(interface.windows[1].get_property(7), Some(3));
+        assert_eq!(execute_at(&mut state, &mut interface, CODE + 6).unwrap(), CODE + 11);
+        assert_eq!(interface.windows[0].get_property(6), Some(4));
+        assert_eq!(interface.windows[0].get_property(7), Some(5));
+    }
+
+    #[test]
+    fn output_stream_4_records_commands() {
+        // output_stream 4, aread twice, output_stream -4, aread, quit
+        let aread = [0xE4, 0x0F, 0x07, 0x00, 0x07, 0x80, 0x10];
+        let mut code = vec![0xF3, 0x7F, 0x04];
+        code.extend_from_slice(&aread);
+        code.extend_from_slice(&aread);
+        code.extend_from_slice(&[0xF3, 0x3F, 0xFF, 0xFC]);
+        code.extend_from_slice(&aread);
+        code.push(0xBA);
+        let mut mem = StoryBuilder::new(5).code(&code).byte(0x700, 20).byte(0x780, 5).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::with_input(&["open mailbox", "read leaflet", "north"]);
+        let result = run_n(&mut state, &mut interface, CODE, 10).unwrap();
+        assert_eq!(result.instructions, 5);
+        assert!(!interface.recording);
+        assert_eq!(interface.commands, vec![String::from("open mailbox"), String::from("read leaflet")]);
+    }
+
+    #[test]
+    fn print_decodes_a_literal_with_an_abbreviation_once() {
+        // print "the lamp", with "the " as abbreviation 0, then new_line
+        let mut code = vec![0xB2];
+        code.extend_from_slice(&pack_zchars(&[1, 0, 17, 6, 18, 21]));
+        code.push(0xBB);
+        let mut mem = StoryBuilder::new(3)
+            .code(&code)
+            .word(0x18, 0x700)
+            .word(0x700, 0x900 / 2)
+            .bytes(0x900, &zstring(3, "the "))
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        let i = decode_instruction(&state, CODE).unwrap();
+        assert_eq!(i.literal.as_deref(), Some("the lamp"));
+        assert_eq!(i.next_pc, CODE + 5);
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 5);
+        assert_eq!(interface.output, "the lamp");
+    }
+
+    #[test]
+    fn read_mouse_writes_four_zero_words() {
+        // read_mouse $700, mouse_window 1
+        let mut mem = StoryBuilder::new(6)
+            .code(&[0xBE, 0x16, 0x3F, 0x07, 0x00, 0xBE, 0x17, 0x7F, 0x01])
+            .bytes(0x700, &[0xFF; 10])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 5);
+        for i in 0..4 {
+            assert_eq!(state.get_memory().get_word(0x700 + (2 * i)).unwrap(), 0);
+        }
+        assert_eq!(state.get_memory().get_word(0x708).unwrap(), 0xFFFF);
+        assert_eq!(execute_at(&mut state, &mut interface, CODE + 5).unwrap(), CODE + 9);
+    }
+
+    #[test]
+    fn erase_line_takes_a_pixel_count_only_in_v6() {
+        // erase_line 1, erase_line 0, erase_line 5
+        let code = [0xEE, 0x7F, 0x01, 0xEE, 0x7F, 0x00, 0xEE, 0x7F, 0x05];
+        let mut mem = StoryBuilder::new(5).code(&code).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        assert_eq!(run_n(&mut state, &mut interface, CODE, 3).unwrap().pc, CODE + 9);
+        assert_eq!(interface.erased, vec![None]);
+
+        let mut mem = StoryBuilder::new(6).code(&code).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        assert_eq!(run_n(&mut state, &mut interface, CODE, 3).unwrap().pc, CODE + 9);
+        assert_eq!(interface.erased, vec![None, Some(5)]);
+    }
+
+    #[test]
+    fn buffer_screen_stores_the_previous_mode() {
+        // buffer_screen 0 -> G00, buffer_screen -1 -> G01, buffer_screen 1 -> G02
+        let mut mem = StoryBuilder::new(6)
+            .code(&[0xBE, 0x1D, 0x7F, 0x00, 0x10,
+                    0xBE, 0x1D, 0x3F, 0xFF, 0xFF, 0x11,
+                    0xBE, 0x1D, 0x7F, 0x01, 0x12])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 5);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 1);
+        assert_eq!(interface.buffer_screen, 0);
+
+        // -1 redraws without changing the mode
+        assert_eq!(execute_at(&mut state, &mut interface, CODE + 5).unwrap(), CODE + 11);
+        assert_eq!(state.get_variable(0x11, false).unwrap(), 0);
+        assert_eq!(interface.buffer_screen, 0);
+
+        assert_eq!(execute_at(&mut state, &mut interface, CODE + 11).unwrap(), CODE + 16);
+        assert_eq!(state.get_variable(0x12, false).unwrap(), 0);
+        assert_eq!(interface.buffer_screen, 1);
+    }
+
+    #[test]
+    fn sound_effect_plays_blorb_sounds() {
+        // sound_effect 1, sound_effect 3 2, sound_effect 4 2, sound_effect 3 3
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xF5, 0x7F, 0x01, 0xF5, 0x5F, 0x03, 0x02, 0xF5, 0x5F, 0x04, 0x02, 0xF5, 0x5F, 0x03, 0x03])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let sound = b"FORM\0\0\0\x04AIFF";
+        state.set_resources(Blorb::from_bytes(blorb(&[(b"Snd ", 3, 0)], &[(b"FORM", &sound[8..])])).unwrap());
+        let mut interface = TestInterface::new();
+        assert_eq!(run_n(&mut state, &mut interface, CODE, 4).unwrap().pc, CODE + 15);
+        assert_eq!(interface.beeps, vec![true]);
+        // Sound 4 has no resource and stopping sound 3 is ignored
+        assert_eq!(interface.sounds, vec![(3, sound.len())]);
+    }
+
+    #[test]
+    fn check_unicode_asks_the_interface() {
+        // check_unicode $2022 -> G00, check_unicode 'A' -> G01, check_unicode $E9 -> G02
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xBE, 0x0C, 0x3F, 0x20, 0x22, 0x10,
+                    0xBE, 0x0C, 0x7F, 0x41, 0x11,
+                    0xBE, 0x0C, 0x7F, 0xE9, 0x12])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        interface.unsupported.insert(0x2022);
+        assert_eq!(run_n(&mut state, &mut interface, CODE, 3).unwrap().pc, CODE + 16);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 0);
+        assert_eq!(state.get_variable(0x11, false).unwrap(), 3);
+        // The test interface only reads ASCII
+        assert_eq!(state.get_variable(0x12, false).unwrap(), 1);
+    }
+
+    #[test]
+    fn make_menu_does_not_branch() {
+        // make_menu 3 $200 ?+5
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xBE, 0x1B, 0x4F, 0x03, 0x02, 0x00, 0xC5])
+            .bytes(0x200, &[0, 2, 0, 0])
+            .build();
+        let before = mem.get_memory();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 7);
+        // Starting the story sets header flags, but nothing past the header changes
+        assert_eq!(state.get_memory().get_memory()[0x40..], before[0x40..]);
+    }
+
+    #[test]
+    fn picture_opcodes_report_no_pictures() {
+        // picture_data 1 $200 ?+5, draw_picture 1, erase_picture 1
+        let mut mem = StoryBuilder::new(6)
+            .code(&[0xBE, 0x06, 0x4F, 0x01, 0x02, 0x00, 0xC5,
+                    0xBE, 0x05, 0x7F, 0x01,
+                    0xBE, 0x07, 0x7F, 0x01])
+            .bytes(0x200, &[0xFF; 4])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        let mut pc = execute_at(&mut state, &mut interface, CODE).unwrap();
+        assert_eq!(pc, CODE + 7);
+        assert_eq!(state.get_memory().get_word(0x200).unwrap(), 0);
+        assert_eq!(state.get_memory().get_word(0x202).unwrap(), 0);
+
+        for _ in 0..2 {
+            pc = execute_at(&mut state, &mut interface, pc).unwrap();
+        }
+        assert_eq!(pc, CODE + 15);
+        assert!(interface.output.is_empty());
+    }
+
+    #[test]
+    fn resizing_during_input_updates_the_header() {
+        // aread $200 -> G00
+        let mut mem = StoryBuilder::new(5).code(&[0xE4, 0x3F, 0x02, 0x00, 0x10]).byte(0x200, 20).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::with_input(&["look"]);
+        interface.resize = Some((30, 100));
+        execute_at(&mut state, &mut interface, CODE).unwrap();
+        assert!(interface.resize.is_none());
+
+        let mem = state.get_memory();
+        assert_eq!((mem.get_byte(0x20).unwrap(), mem.get_byte(0x21).unwrap()), (30, 100));
+        assert_eq!((mem.get_word(0x22).unwrap(), mem.get_word(0x24).unwrap()), (100, 30));
+
+        // A screen wider than 255 columns is capped in the byte fields
+        let mut mem = StoryBuilder::new(5).code(&[0xE4, 0x3F, 0x02, 0x00, 0x10]).byte(0x200, 20).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        interface.resize = Some((50, 300));
+        execute_at(&mut state, &mut interface, CODE).unwrap();
+        assert_eq!(state.get_memory().get_byte(0x21).unwrap(), 255);
+        assert_eq!(state.get_memory().get_word(0x22).unwrap(), 300);
+    }
+
+    #[test]
+    fn buffer_mode_is_passed_to_the_interface() {
+        // buffer_mode 0; buffer_mode 1
+        let mut mem = StoryBuilder::new(4).code(&[0xF2, 0x7F, 0x00, 0xF2, 0x7F, 0x01]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+
+        let next = execute_at(&mut state, &mut interface, CODE).unwrap();
+        assert_eq!(interface.buffer_mode, Some(0));
+        execute_at(&mut state, &mut interface, next).unwrap();
+        assert_eq!(interface.buffer_mode, Some(1));
+    }
+
+    /// One instance of every opcode byte, with small constant operands of 1
+    fn opcode_instances() -> Vec<Vec<u8>> {
+        let mut instances = Vec::new();
+        // 2OP in the long form
+        for opcode in 0x01..=0x1F {
+            instances.push(vec![opcode, 0x01, 0x01]);
+        }
+        // 1OP with a small constant
+        for opcode in 0x90..=0x9F {
+            instances.push(vec![opcode, 0x01]);
+        }
+        // 0OP
+        for opcode in (0xB0..=0xBF).filter(|o| *o != 0xBE) {
+            instances.push(vec![opcode]);
+        }
+        // VAR, where call_vs2 and call_vn2 have a second operand type byte
+        for opcode in 0xE0..=0xFF {
+            match opcode {
+                0xEC | 0xFA => instances.push(vec![opcode, 0x55, 0xFF, 0x01, 0x01, 0x01, 0x01]),
+                _ => instances.push(vec![opcode, 0x55, 0x01, 0x01, 0x01, 0x01])
+            }
+        }
+        // EXT, skipping the unassigned $0E and $0F
+        for opcode in (0x00..=0x1D).filter(|o| *o != 0x0E && *o != 0x0F) {
+            instances.push(vec![0xBE, opcode, 0x55, 0x01, 0x01, 0x01, 0x01]);
+        }
+        instances
+    }
+
+    #[test]
+    fn every_decodable_opcode_has_an_executor() {
+        for version in &[3, 5] {
+            for instance in opcode_instances() {
+                // $90 serves as a store variable, a branch byte and a terminated literal string
+                let mut code = instance.clone();
+                code.extend_from_slice(&[0x90, 0x90, 0x90, 0x90]);
+                let mut mem = StoryBuilder::new(*version).code(&code).build();
+                let mut state = FrameStack::new(&mut mem).unwrap();
+                let mut interface = TestInterface::new();
+
+                let mut i = decode_instruction(&state, CODE).unwrap();
+                // Undefined opcodes are named by their number
+                if i.name.starts_with('$') {
+                    continue;
+                }
+                if let Err(InfocomError::Unimplemented { opcode, .. }) = i.execute(&mut state, &mut interface) {
+                    panic!("V{} {:02x?} decodes as {} with no executor", version, instance, opcode);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn opcode_table_entries_do_not_overlap() {
+        for (n, (count, number, first, last, name, _)) in OPCODES.iter().enumerate() {
+            assert!(first <= last, "{} has an empty version range", name);
+            for (c, o, f, l, other, _) in &OPCODES[n + 1..] {
+                assert!(!(c == count && o == number && f <= last && first <= l), "{} overlaps {}", name, other);
+            }
+        }
+    }
+
+    #[test]
+    fn version_dependent_opcodes_are_named_from_the_table() {
+        for (opcode, v3, v5) in &[(0x8F, "not", "call_1n"), (0xB9, "pop", "catch"), (0xE0, "call", "call_vs"), (0xE4, "sread", "aread")] {
+            assert_eq!(opcode_name(3, *opcode, None).0, *v3);
+            assert_eq!(opcode_name(5, *opcode, None).0, *v5);
+        }
+        // Every form of a 2OP finds the same entry
+        for opcode in &[0x14, 0x34, 0x54, 0x74, 0xD4] {
+            assert_eq!(opcode_name(5, *opcode, None).0, "add");
+        }
+        assert_eq!(opcode_name(5, 0xBE, Some(0x02)).0, "EXT log_shift");
+        assert_eq!(opcode_name(5, 0xBE, Some(0x0E)).0, "EXT $0e");
+        assert!(opcode_name(5, 0xBE, Some(0x0E)).1.is_none());
+    }
+
+    #[test]
+    fn undefined_opcodes_are_unimplemented() {
+        // 2OP:0 with two small constants
+        let mut mem = StoryBuilder::new(5).code(&[0x00, 0x01, 0x02]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        match execute_at(&mut state, &mut interface, CODE) {
+            Err(InfocomError::Unimplemented { opcode, address }) => {
+                assert_eq!(opcode, "$00 ($00)");
+                assert_eq!(address, CODE);
+            },
+            _ => panic!("2OP:0 should be unimplemented")
+        }
+    }
+
+    #[test]
+    fn check_arg_count_counts_supplied_arguments() {
+        // call_vn $1100 5, calling a routine with 2 locals that checks for arguments 1 and 2
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xF9, 0x1F, 0x04, 0x40, 0x05])
+            .routine(0x1100, &[0, 0], &[0xFF, 0x7F, 0x01, 0xC5, 0xFF, 0x7F, 0x02, 0xC5])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), 0x1101);
+        assert_eq!(state.argument_count(), 1);
+        assert_eq!(execute_at(&mut state, &mut interface, 0x1101).unwrap(), 0x1105 + 5 - 2);
+        assert_eq!(execute_at(&mut state, &mut interface, 0x1105).unwrap(), 0x1109);
+    }
+
+    #[test]
+    fn pull_stores_to_the_referenced_variable() {
+        // call_vn $1100, to a routine with 1 local that does push 5, push 7, pull L01
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xF9, 0x3F, 0x04, 0x40])
+            .routine(0x1100, &[0], &[0xE8, 0x7F, 0x05, 0xE8, 0x7F, 0x07, 0xE9, 0x7F, 0x01])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        let result = run_n(&mut state, &mut interface, CODE, 4).unwrap();
+        assert_eq!(result.pc, 0x110A);
+        assert_eq!(state.get_variable(1, false).unwrap(), 7);
+        assert_eq!(state.get_variable(0, false).unwrap(), 5);
+    }
+
+    #[test]
+    fn v6_pull_pops_a_user_stack() {
+        // pull $200 -> G00, twice, from a 4 slot user stack holding 2 values, then
+        // push 9 and pull -> G00 from the game stack
+        let mut mem = StoryBuilder::new(6)
+            .code(&[0xE9, 0x3F, 0x02, 0x00, 0x10, 0xE9, 0x3F, 0x02, 0x00, 0x10,
+                    0xE8, 0x7F, 0x09, 0xE9, 0xFF, 0x10])
+            .word(0x200, 2)
+            .word(0x206, 0x1234)
+            .word(0x208, 0x5678)
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 5);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 0x1234);
+        assert_eq!(state.get_memory().get_word(0x200).unwrap(), 3);
+        assert_eq!(execute_at(&mut state, &mut interface, CODE + 5).unwrap(), CODE + 10);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 0x5678);
+        assert_eq!(state.get_memory().get_word(0x200).unwrap(), 4);
+
+        let result = run_n(&mut state, &mut interface, CODE + 10, 2).unwrap();
+        assert_eq!(result.pc, CODE + 16);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 9);
+    }
+
+    #[test]
+    fn v6_pull_from_an_empty_stack_is_an_error() {
+        // pull $200 -> G00 with a free count leaving no slot below static memory at $800,
+        // then pull -> G00 from the empty game stack
+        let mut mem = StoryBuilder::new(6)
+            .code(&[0xE9, 0x3F, 0x02, 0x00, 0x10, 0xE9, 0xFF, 0x10])
+            .word(0x200, 0x2FF)
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        match execute_at(&mut state, &mut interface, CODE) {
+            Err(InfocomError::Memory(message)) => assert_eq!(message, "Pull from empty user stack at $0200"),
+            r => panic!("expected an underflow, got {:?}", r)
+        }
+        assert_eq!(state.get_memory().get_word(0x200).unwrap(), 0x2FF);
+        assert!(execute_at(&mut state, &mut interface, CODE + 5).is_err());
+    }
+
+    #[test]
+    fn verify_checks_the_original_story_data() {
+        // verify ?(+5); the checksum covers $0040 to the end of the file
+        let code = [0xBD, 0xC5];
+        let checksum = code.iter().fold(0u16, |sum, b| sum.wrapping_add(*b as u16));
+        let mut mem = StoryBuilder::new(3).code(&code).word(0x1C, checksum).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+
+        // Changes to dynamic memory don't affect the checksum
+        state.set_byte(0x700, 0xFF).unwrap();
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 2 + 5 - 2);
+
+        let mut mem = StoryBuilder::new(3).code(&code).word(0x1C, checksum + 1).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 2);
+    }
+
+    /// Run `scan_table` from `code` against `table` at $0700, returning the next pc and
+    /// the address stored in global 0
+    fn scan_table(code: &[u8], table: &[u8]) -> (usize, u16) {
+        let mut mem = StoryBuilder::new(5).bytes(0x700, table).code(code).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        let next = execute_at(&mut state, &mut interface, CODE).unwrap();
+        (next, state.get_variable(16, false).unwrap())
+    }
+
+    #[test]
+    fn scan_table_defaults_to_words_with_a_stride_of_2() {
+        // scan_table $1234 $0700 3 -> G00 ?(+5)
+        let (next, address) = scan_table(&[0xF7, 0x07, 0x12, 0x34, 0x07, 0x00, 0x03, 0x10, 0xC5],
+                                         &[0x00, 0x01, 0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(address, 0x702);
+        assert_eq!(next, CODE + 9 + 5 - 2);
+    }
+
+    #[test]
+    fn scan_table_compares_bytes_with_form_4() {
+        // scan_table 3 $0700 3 $04 -> G00 ?(+5)
+        let (next, address) = scan_table(&[0xF7, 0x05, 0x00, 0x03, 0x07, 0x00, 0x03, 0x04, 0x10, 0xC5],
+                                         &[1, 3, 3, 3, 2, 3, 3, 3, 3, 0, 0, 0]);
+        assert_eq!(address, 0x708);
+        assert_eq!(next, CODE + 10 + 5 - 2);
+    }
+
+    #[test]
+    fn scan_table_strides_over_structures() {
+        // scan_table $0033 $0700 3 $86 -> G00 ?(+5), skipping the $0033 inside the first entry
+        let (next, address) = scan_table(&[0xF7, 0x05, 0x00, 0x33, 0x07, 0x00, 0x03, 0x86, 0x10, 0xC5],
+                                         &[0x00, 0x11, 0x00, 0x33, 0x00, 0x00,
+                                           0x00, 0x22, 0x00, 0x00, 0x00, 0x00,
+                                           0x00, 0x33, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(address, 0x70C);
+        assert_eq!(next, CODE + 10 + 5 - 2);
+    }
+
+    #[test]
+    fn scan_table_miss_stores_0_and_does_not_branch() {
+        // scan_table $9999 $0700 3 -> G00 ?(+5)
+        let (next, address) = scan_table(&[0xF7, 0x07, 0x99, 0x99, 0x07, 0x00, 0x03, 0x10, 0xC5],
+                                         &[0x00, 0x01, 0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(address, 0);
+        assert_eq!(next, CODE + 9);
+    }
+
+    #[test]
+    fn run_n_stops_at_the_first_sread() {
+        // print "hi", sread $700 $780, quit
+        let text = zstring(3, "hi");
+        let mut code = vec![0xB2];
+        code.extend_from_slice(&text);
+        let sread = CODE + code.len();
+        code.extend_from_slice(&[0xE4, 0x0F, 0x07, 0x00, 0x07, 0x80, 0xBA]);
+        let mut mem = StoryBuilder::new(3).byte(0x700, 20).byte(0x780, 5).code(&code).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+
+        // The instruction limit stops the run first
+        let result = run_n(&mut state, &mut interface, CODE, 1).unwrap();
+        assert!(!result.awaiting_input);
+        assert_eq!((result.pc, result.instructions), (sread, 1));
+
+        let result = run_n(&mut state, &mut interface, CODE, 100).unwrap();
+        assert!(result.awaiting_input);
+        assert_eq!((result.pc, result.instructions), (sread, 1));
+        assert_eq!(interface.output, "hihi");
+
+        // With input available the read completes and the run halts at quit
+        interface.input.push_back(String::from("look"));
+        let result = run_n(&mut state, &mut interface, sread, 100).unwrap();
+        assert!(!result.awaiting_input);
+        assert_eq!((result.pc, result.instructions), (sread + 6, 1));
+    }
+
+    #[test]
+    fn the_loop_guard_stops_a_tight_loop() {
+        // jump to itself
+        let mut mem = StoryBuilder::new(3).code(&[0x8C, 0xFF, 0xFF]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        state.set_loop_guard(Some(100));
+        match run_n(&mut state, &mut interface, CODE, 1000) {
+            Err(InfocomError::API(m)) => assert_eq!(m, "possible infinite loop at $001000"),
+            _ => panic!("expected the loop guard to stop the run")
+        }
+
+        // Without a guard the run ends at the instruction limit
+        state.set_loop_guard(None);
+        let result = run_n(&mut state, &mut interface, CODE, 1000).unwrap();
+        assert_eq!((result.pc, result.instructions), (CODE, 1000));
+    }
+
+    /// A V5 story holding "lamp xyzzy" in the text buffer at $200, with a parse buffer at
+    /// $280 whose second entry is already filled in
+    fn tokenise_story(code: &[u8]) -> StoryBuilder {
+        let mut text = vec![20, 10];
+        text.extend_from_slice(b"lamp xyzzy");
+        StoryBuilder::new(5)
+            .code(code)
+            .dictionary(&[], &["lamp"])
+            .bytes(0x200, &text)
+            .bytes(0x280, &[4, 0, 0, 0, 0, 0, 0xBE, 0xEF, 0x12, 0x34])
+    }
+
+    #[test]
+    fn tokenise_can_leave_unknown_words_alone() {
+        // tokenise $200 $280 0 1, then tokenise $200 $280
+        let mut mem = tokenise_story(&[0xFB, 0x05, 0x02, 0x00, 0x02, 0x80, 0x00, 0x01,
+                                       0xFB, 0x0F, 0x02, 0x00, 0x02, 0x80]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        let parse = |state: &FrameStack| (0x281..0x28A).map(|a| state.get_memory().get_byte(a).unwrap()).collect::<Vec<u8>>();
+
+        let pc = execute_at(&mut state, &mut interface, CODE).unwrap();
+        assert_eq!(parse(&state), vec![2, 0x06, 0x04, 4, 2, 0xBE, 0xEF, 0x12, 0x34]);
+
+        // Without the flag the unknown word's entry is cleared
+        execute_at(&mut state, &mut interface, pc).unwrap();
+        assert_eq!(parse(&state), vec![2, 0x06, 0x04, 4, 2, 0x00, 0x00, 5, 7]);
+    }
+
+    #[test]
+    fn tokenise_can_use_a_custom_dictionary() {
+        // tokenise $200 $280 $700, against an unsorted dictionary in dynamic memory
+        let mut mem = tokenise_story(&[0xFB, 0x03, 0x02, 0x00, 0x02, 0x80, 0x07, 0x00])
+            .dictionary_at(0x700, &[], &["xyzzy", "plugh"])
+            .word(0x702, -2i16 as u16)
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 8);
+        let parse:Vec<u8> = (0x281..0x28A).map(|a| state.get_memory().get_byte(a).unwrap()).collect();
+        // "lamp" is only in the main dictionary, "xyzzy" is the second custom entry
+        assert_eq!(parse, vec![2, 0x00, 0x00, 4, 2, 0x07, 0x0D, 5, 7]);
+    }
+
+    /// A story that selects input stream 1, then reads a command into the text buffer at
+    /// $0700 and parse buffer at $0780 and jumps back to read again
+    fn read_loop(version: u8) -> StoryBuilder {
+        StoryBuilder::new(version)
+            .byte(0x700, 20)
+            .byte(0x780, 5)
+            .code(&[0xF4, 0x7F, 0x01,
+                    0xE4, 0x0F, 0x07, 0x00, 0x07, 0x80, 0x8C, 0xFF, 0xF9])
+    }
+
+    #[test]
+    fn scripted_commands_are_read_in_turn() {
+        let mut mem = read_loop(3).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::with_input(&["open mailbox", "take leaflet", "read it"]);
+
+        let result = run_n(&mut state, &mut interface, CODE, 100).unwrap();
+        assert_eq!(interface.input_stream, 1);
+        assert!(result.awaiting_input);
+        assert_eq!(result.pc, CODE + 3);
+        assert_eq!(result.instructions, 7);
+        assert!(interface.input.is_empty());
+
+        // The last command is in the text buffer, with 2 words parsed
+        let text:Vec<u8> = (0..8).map(|i| state.get_memory().get_byte(0x701 + i).unwrap()).collect();
+        assert_eq!(text, b"read it\0");
+        assert_eq!(state.get_memory().get_byte(0x781).unwrap(), 2);
+    }
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir().join(format!("infocom-{}-{}", std::process::id(), name)).to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn restore_resumes_after_the_original_save() {
+        // save ?+5, store G00 7, restore ?+5
+        let mut mem = StoryBuilder::new(3).code(&[0xB5, 0xC5, 0xB0, 0xB0, 0xB0, 0x0D, 0x10, 0x07, 0xB6, 0xC5]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let path = temp_path("restore.qzl");
+        let mut interface = TestInterface::with_input(&[&path, &path]);
+
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 5);
+        assert_eq!(execute_at(&mut state, &mut interface, CODE + 5).unwrap(), CODE + 8);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 7);
+
+        assert_eq!(execute_at(&mut state, &mut interface, CODE + 8).unwrap(), CODE + 5);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn restore_rejects_a_saved_pc_beyond_memory() {
+        let mut mem = StoryBuilder::new(3).code(&[0xB5, 0xC5, 0xB6, 0xC5]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let path = temp_path("bad-pc.qzl");
+        let mut interface = TestInterface::with_input(&[&path, &path]);
+        execute_at(&mut state, &mut interface, CODE).unwrap();
+
+        let mut quetzal = Quetzal::from_bytes(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(quetzal.ifhd.pc, CODE + 1);
+        quetzal.ifhd.pc = 0xFFFFFF;
+        fs::write(&path, quetzal.to_bytes()).unwrap();
+
+        assert!(execute_at(&mut state, &mut interface, CODE + 2).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn je_compares_the_first_operand_with_each_of_the_others() {
+        let cases:Vec<(Vec<u8>, bool)> = vec![
+            // je 3 3, je 3 4
+            (vec![0x01, 0x03, 0x03], true),
+            (vec![0x01, 0x03, 0x04], false),
+            // je 3 4 3, je 3 4 5
+            (vec![0xC1, 0x57, 0x03, 0x04, 0x03], true),
+            (vec![0xC1, 0x57, 0x03, 0x04, 0x05], false),
+            // je 3 4 5 3, je 3 4 5 6
+            (vec![0xC1, 0x55, 0x03, 0x04, 0x05, 0x03], true),
+            (vec![0xC1, 0x55, 0x03, 0x04, 0x05, 0x06], false),
+        ];
+        for (code, taken) in cases {
+            let mut code = code.clone();
+            let length = code.len() + 1;
+            code.push(0xC5);
+            let mut mem = StoryBuilder::new(3).code(&code).build();
+            let mut state = FrameStack::new(&mut mem).unwrap();
+            let mut interface = TestInterface::new();
+            let next = execute_at(&mut state, &mut interface, CODE).unwrap();
+            assert_eq!(next, if taken { CODE + length + 3 } else { CODE + length }, "{:02x?}", code);
+        }
+    }
+
+    /// Execute `random seed -> G00`, then draw `random 1000` into G01 to G04, returning
+    /// the result of the seeding and the 4 draws
+    fn random_after_seed(seed: i16) -> (u16, Vec<u16>) {
+        let mut code = vec![0xE7, 0x3F, (seed >> 8) as u8, seed as u8, 0x10];
+        for g in 0x11..0x15 {
+            code.extend_from_slice(&[0xE7, 0x3F, 0x03, 0xE8, g]);
+        }
+        let mut mem = StoryBuilder::new(5).code(&code).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        run_n(&mut state, &mut interface, CODE, 5).unwrap();
+        let draws = (0x11..0x15).map(|g| state.get_variable(g, false).unwrap()).collect();
+        (state.get_variable(0x10, false).unwrap(), draws)
+    }
+
+    #[test]
+    fn random_with_a_negative_range_seeds_predictably() {
+        let (stored, draws) = random_after_seed(-5);
+        assert_eq!(stored, 0);
+        assert!(draws.iter().all(|d| *d >= 1 && *d <= 1000));
+        assert_eq!(random_after_seed(-5).1, draws);
+        assert_ne!(random_after_seed(-6).1, draws);
+
+        // The magnitude of -32768 doesn't fit in an i16
+        let (stored, draws) = random_after_seed(-32768);
+        assert_eq!(stored, 0);
+        assert_eq!(random_after_seed(-32768).1, draws);
+    }
+
+    #[test]
+    fn random_with_a_zero_range_reseeds_unpredictably() {
+        // random -5; random 0; then 4 draws of random 1000
+        let mut code = vec![0xE7, 0x3F, 0xFF, 0xFB, 0x10, 0xE7, 0x7F, 0x00, 0x10];
+        for g in 0x11..0x15 {
+            code.extend_from_slice(&[0xE7, 0x3F, 0x03, 0xE8, g]);
+        }
+        let mut mem = StoryBuilder::new(5).code(&code).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        run_n(&mut state, &mut interface, CODE, 6).unwrap();
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 0);
+        let draws:Vec<u16> = (0x11..0x15).map(|g| state.get_variable(g, false).unwrap()).collect();
+        assert_ne!(draws, random_after_seed(-5).1);
+    }
+
+    #[test]
+    fn not_is_a_var_instruction_in_v5() {
+        // not $1234 -> G00
+        let mut mem = StoryBuilder::new(5).code(&[0xF8, 0x3F, 0x12, 0x34, 0x10]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        let i = decode_instruction(&state, CODE).unwrap();
+        assert_eq!(i.name, "not");
+        assert_eq!(i.store_variable, Some(0x10));
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 5);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 0xEDCB);
+    }
+
+    #[test]
+    fn not_complements_all_16_bits_in_either_encoding() {
+        // V3 1OP not $00FF -> G00
+        let mut mem = StoryBuilder::new(3).code(&[0x8F, 0x00, 0xFF, 0x10]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 4);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 0xFF00);
+
+        // V5 VAR not $00FF -> G00
+        let mut mem = StoryBuilder::new(5).code(&[0xF8, 0x3F, 0x00, 0xFF, 0x10]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 5);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 0xFF00);
+    }
+
+    #[test]
+    fn random_with_a_positive_range_draws_within_it() {
+        // random 6 -> G00
+        let mut mem = StoryBuilder::new(5).code(&[0xE7, 0x7F, 0x06, 0x10]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        let mut seen = HashSet::new();
+        for _ in 0..200 {
+            execute_at(&mut state, &mut interface, CODE).unwrap();
+            seen.insert(state.get_variable(0x10, false).unwrap());
+        }
+        assert_eq!(seen, (1..=6).collect());
+    }
+
+    /// Execute the extended shift `opcode` on `number` and `places`, returning the result
+    fn shift(opcode: u8, number: u16, places: i16) -> u16 {
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xBE, opcode, 0x0F, (number >> 8) as u8, number as u8, (places >> 8) as u8, places as u8, 0x10])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        execute_at(&mut state, &mut interface, CODE).unwrap();
+        state.get_variable(0x10, false).unwrap()
+    }
+
+    #[test]
+    fn extended_stores_are_decoded_from_the_extended_opcode() {
+        // EXT log_shift 1 2 -> G00, then EXT set_margins 1 2 0, which doesn't store
+        let mut mem = StoryBuilder::new(5).code(&[0xBE, 0x02, 0x5F, 0x01, 0x02, 0x10, 0xBE, 0x08, 0x57, 0x01, 0x02, 0x00]).build();
+        let state = FrameStack::new(&mut mem).unwrap();
+        let i = decode_instruction(&state, CODE).unwrap();
+        assert_eq!(i.name, "EXT log_shift");
+        assert_eq!(i.store_variable, Some(0x10));
+        assert!(i.branch_offset.is_none());
+        assert_eq!(i.next_pc, CODE + 6);
+
+        let i = decode_instruction(&state, CODE + 6).unwrap();
+        assert_eq!(i.name, "EXT set_margins");
+        assert_eq!(i.store_variable, None);
+        assert_eq!(i.next_pc, CODE + 12);
+    }
+
+    #[test]
+    fn log_shift_fills_with_zeros_and_clamps_large_shifts() {
+        assert_eq!(shift(0x02, 0x8001, 1), 0x0002);
+        assert_eq!(shift(0x02, 0x8001, -1), 0x4000);
+        assert_eq!(shift(0x02, 0x8001, 15), 0x8000);
+        assert_eq!(shift(0x02, 0x8001, -15), 0x0001);
+        for places in &[16, -16, 100, -100, 32767, -32768] {
+            assert_eq!(shift(0x02, 0xFFFF, *places), 0, "{}", places);
+        }
+    }
+
+    #[test]
+    fn art_shift_extends_the_sign_on_right_shifts() {
+        assert_eq!(shift(0x03, 0x8001, 1), 0x0002);
+        assert_eq!(shift(0x03, 0x8001, -1), 0xC000);
+        assert_eq!(shift(0x03, 0x4001, -1), 0x2000);
+        assert_eq!(shift(0x03, 0x8000, -15), 0xFFFF);
+        // Right shifts past the width leave only the sign, left shifts leave nothing
+        for places in &[-16, -100, -32768] {
+            assert_eq!(shift(0x03, 0x8000, *places), 0xFFFF, "{}", places);
+            assert_eq!(shift(0x03, 0x7FFF, *places), 0, "{}", places);
+        }
+        for places in &[16, 100, 32767] {
+            assert_eq!(shift(0x03, 0xFFFF, *places), 0, "{}", places);
+        }
+    }
+
+    #[test]
+    fn loadw_and_storew_reject_addresses_out_of_range() {
+        // loadw $1ffe 0 -> G00, loadw $1ffe 1 -> G00, loadw $fffe 1 -> G00
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xCF, 0x1F, 0x1F, 0xFE, 0x00, 0x10, 0xCF, 0x1F, 0x1F, 0xFE, 0x01, 0x10, 0xCF, 0x1F, 0xFF, 0xFE, 0x01, 0x10])
+            .word(0x1FFE, 0x1234)
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        execute_at(&mut state, &mut interface, CODE).unwrap();
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 0x1234);
+        assert!(execute_at(&mut state, &mut interface, CODE + 6).is_err());
+        assert!(execute_at(&mut state, &mut interface, CODE + 12).is_err());
+
+        // A story larger than 64K still can't index past $ffff
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xCF, 0x1F, 0xFF, 0xFC, 0x00, 0x10, 0xCF, 0x1F, 0xFF, 0xFE, 0x01, 0x10])
+            .word(0xFFFC, 0x5678)
+            .byte(0x10100, 0)
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        execute_at(&mut state, &mut interface, CODE).unwrap();
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 0x5678);
+        assert!(execute_at(&mut state, &mut interface, CODE + 6).is_err());
+
+        // storew $07fe 0 $12, storew $07fe 1 $12, storew $fffe 2 $12
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xE1, 0x17, 0x07, 0xFE, 0x00, 0x12, 0xE1, 0x17, 0x07, 0xFE, 0x01, 0x12, 0xE1, 0x17, 0xFF, 0xFE, 0x02, 0x12])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        execute_at(&mut state, &mut interface, CODE).unwrap();
+        assert_eq!(state.get_memory().get_word(0x7FE).unwrap(), 0x12);
+        match execute_at(&mut state, &mut interface, CODE + 6) {
+            Err(InfocomError::WriteViolation(a, _)) => assert_eq!(a, 0x800),
+            r => panic!("expected a write violation, got {:?}", r)
+        }
+        assert!(execute_at(&mut state, &mut interface, CODE + 12).is_err());
+    }
+
+    #[test]
+    fn show_status_waits_for_a_location_object() {
+        // show_status, with G00 holding the location object
+        let story = |location| StoryBuilder::new(3)
+            .code(&[0xBC])
+            .object(1, &[], (0, 0, 0), "Attic", &[])
+            .global(0, location)
+            .global(1, 5)
+            .global(2, 12)
+            .build();
+        let mut mem = story(0);
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 1);
+        assert!(interface.status.is_empty());
+
+        let mut mem = story(1);
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        execute_at(&mut state, &mut interface, CODE).unwrap();
+        assert_eq!(interface.status, vec!["Attic 5/12"]);
+    }
+
+    #[test]
+    fn print_addr_and_print_obj_print_through_the_interface() {
+        // print_addr $900, then print_obj 1
+        let mut mem = StoryBuilder::new(3)
+            .code(&[0x87, 0x09, 0x00, 0x9A, 0x01])
+            .bytes(0x900, &zstring(3, "You see "))
+            .object(1, &[], (0, 0, 0), "a lamp", &[])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        let pc = execute_at(&mut state, &mut interface, CODE).unwrap();
+        assert_eq!(execute_at(&mut state, &mut interface, pc).unwrap(), CODE + 5);
+        assert_eq!(interface.output, "You see a lamp");
+    }
+
+    #[test]
+    fn print_char_and_print_num_format_their_output() {
+        // print_char 65, print_char 13, print_char 0, print_num -5, print_num 32767
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xE5, 0x7F, 0x41, 0xE5, 0x7F, 0x0D, 0xE5, 0x7F, 0x00,
+                    0xE6, 0x3F, 0xFF, 0xFB, 0xE6, 0x3F, 0x7F, 0xFF,
+                    0xE5, 0x7F, 0x07])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        let mut pc = CODE;
+        for _ in 0..5 {
+            pc = execute_at(&mut state, &mut interface, pc).unwrap();
+        }
+        assert_eq!(interface.output, "A\n-532767");
+
+        // 7 is not an output character
+        assert!(execute_at(&mut state, &mut interface, pc).is_err());
+    }
+
+    #[test]
+    fn print_paddr_reads_strings_above_64k() {
+        // print_paddr with a large constant, in V5 and V8, for a string at $10400
+        for (version, packed) in &[(5, 0x4100u16), (8, 0x2080)] {
+            let mut mem = StoryBuilder::new(*version)
+                .code(&[0x8D, (packed >> 8) as u8, *packed as u8])
+                .bytes(0x10400, &zstring(*version, "Far away"))
+                .build();
+            let mut state = FrameStack::new(&mut mem).unwrap();
+            let mut interface = TestInterface::new();
+            assert_eq!(execute_at(&mut state, &mut interface, CODE).unwrap(), CODE + 3);
+            assert_eq!(interface.output, "Far away", "V{}", version);
+        }
+    }
+
+    #[test]
+    fn only_static_code_is_cached() {
+        // add 1 2 -> sp in static memory, and rtrue in dynamic memory
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0x14, 0x01, 0x02, 0x00])
+            .bytes(0x200, &[0xB0])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        state.set_instruction_cache(true);
+
+        let decoded = serde_json::to_string(&decode_cached(&mut state, CODE).unwrap()).unwrap();
+        assert!(state.cached_instruction(CODE).is_some());
+        assert_eq!(serde_json::to_string(&decode_cached(&mut state, CODE).unwrap()).unwrap(), decoded);
+        assert_eq!(serde_json::to_string(&decode_instruction(&state, CODE).unwrap()).unwrap(), decoded);
+
+        // Code rewritten in dynamic memory is decoded afresh
+        assert_eq!(decode_cached(&mut state, 0x200).unwrap().name, "rtrue");
+        assert!(state.cached_instruction(0x200).is_none());
+        state.set_byte(0x200, 0xB1).unwrap();
+        assert_eq!(decode_cached(&mut state, 0x200).unwrap().name, "rfalse");
+
+        // Restarting empties the cache
+        state.restart().unwrap();
+        assert!(state.cached_instruction(CODE).is_none());
+    }
+
+    /// Execute `copy_table first second size` over the bytes 1 to 8 at $200, returning
+    /// the bytes at $200 to $20b afterwards
+    fn copy_table(first: u16, second: u16, size: i16) -> Result<Vec<u8>, InfocomError> {
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xFD, 0x03, (first >> 8) as u8, first as u8, (second >> 8) as u8, second as u8, (size >> 8) as u8, size as u8])
+            .bytes(0x200, &[1, 2, 3, 4, 5, 6, 7, 8])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        execute_at(&mut state, &mut interface, CODE)?;
+        Ok(state.get_memory().get_memory()[0x200..0x20C].to_vec())
+    }
+
+    #[test]
+    fn copy_table_copies_zeroes_and_skips_empty_copies() {
+        let original = vec![1, 2, 3, 4, 5, 6, 7, 8, 0, 0, 0, 0];
+        assert_eq!(copy_table(0x200, 0x208, 4).unwrap(), vec![1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4]);
+        assert_eq!(copy_table(0x200, 0x208, 0).unwrap(), original);
+        assert_eq!(copy_table(0x200, 0x200, 8).unwrap(), original);
+        assert_eq!(copy_table(0x202, 0, 3).unwrap(), vec![1, 2, 0, 0, 0, 6, 7, 8, 0, 0, 0, 0]);
+        // Neither an empty copy nor a copy onto itself writes, even to static memory
+        assert!(copy_table(0x200, 0x900, 0).is_ok());
+        assert!(copy_table(0x900, 0x900, 4).is_ok());
+        assert!(copy_table(0x200, 0x900, 4).is_err());
+    }
+
+    #[test]
+    fn copy_table_overlaps_by_the_sign_of_the_size() {
+        // A positive size copies as if through a buffer, a negative size copies forwards
+        // one byte at a time and repeats the start of the table
+        assert_eq!(copy_table(0x200, 0x202, 4).unwrap(), vec![1, 2, 1, 2, 3, 4, 7, 8, 0, 0, 0, 0]);
+        assert_eq!(copy_table(0x200, 0x202, -4).unwrap(), vec![1, 2, 1, 2, 1, 2, 7, 8, 0, 0, 0, 0]);
+        assert_eq!(copy_table(0x202, 0x200, -4).unwrap(), vec![3, 4, 5, 6, 5, 6, 7, 8, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn je_with_a_single_operand_is_an_error() {
+        // je 3 ?+5
+        let mut mem = StoryBuilder::new(3).code(&[0xC1, 0x7F, 0x03, 0xC5]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        match execute_at(&mut state, &mut interface, CODE) {
+            Err(InfocomError::OperandCount { address, count, .. }) => {
+                assert_eq!(address, CODE);
+                assert_eq!(count, 1);
+            },
+            _ => panic!("je with one operand did not fail")
+        }
+    }
 }
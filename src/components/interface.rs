@@ -1,10 +1,12 @@
 extern crate easycurses;
 
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Stdin, Stdout, Write};
 use easycurses::*;
 use easycurses::Color::*;
 
-use log::debug;
+use log::{debug, error, warn};
 
 pub enum StatusLineFormat {
     SCORED,
@@ -14,12 +16,268 @@ pub enum StatusLineFormat {
 pub trait Interface {
     fn print(&mut self, text: &str);
     fn new_line(&mut self);
-    fn read(&mut self, terminating_characters: HashSet<char>, max_chars: usize) -> String;
+    fn read(&mut self, terminating_characters: &HashSet<u16>, max_chars: usize) -> (String, u16);
+    /// Read a single keypress as a ZSCII code
+    fn read_char(&mut self) -> u16;
     fn status_line(&mut self, name: &str, format: StatusLineFormat, v1: i16, v2: u16);
+    fn split_window(&mut self, lines: u16);
+    fn set_window(&mut self, window: u16);
+    fn get_window(&self) -> u16;
+    fn set_cursor(&mut self, line: u16, column: u16);
+    fn get_cursor(&mut self) -> (u16, u16);
+    /// Erase from the cursor to the end of the line, or only `columns` characters,
+    /// leaving the cursor where it was
+    fn erase_line(&mut self, columns: Option<u16>);
+    /// Clear `window`.  -1 unsplits the screen and clears it, -2 clears the whole screen
+    /// keeping the split.
+    fn erase_window(&mut self, window: i16);
+    /// Select a text style: 0 is roman, otherwise a combination of 1 reverse video,
+    /// 2 bold, 4 italic and 8 fixed pitch
+    fn set_text_style(&mut self, style: u16);
+    fn buffer_mode(&mut self, flag: u16);
+    /// Turn screen buffering off (0) or on (1), returning the previous setting.
+    /// -1 redraws the screen without changing the setting.
+    fn buffer_screen(&mut self, mode: u16) -> u16;
+    fn input_stream(&mut self, stream: u16);
+    /// Select or deselect output stream 4, which records each line of player input
+    fn record_commands(&mut self, enabled: bool);
+    fn recorded_commands(&self) -> &[String];
+    fn set_font(&mut self, font: u16) -> u16;
+    fn get_window_property(&self, window: u16, property: u16) -> u16;
+    fn set_window_property(&mut self, window: u16, property: u16, value: u16);
+    fn has_input(&self) -> bool;
+    /// The new screen size as (rows, columns) if the terminal has been resized
+    /// since the last call
+    fn on_resize(&mut self) -> Option<(u16, u16)> {
+        None
+    }
+    /// Display any output that has been batched since the last flush
+    fn flush(&mut self);
+    /// Whether the Unicode character `code_point` can be printed
+    fn can_output(&self, code_point: u32) -> bool {
+        std::char::from_u32(code_point).map_or(false, |c| !c.is_control())
+    }
+    /// Whether the Unicode character `code_point` can be typed
+    fn can_input(&self, code_point: u32) -> bool {
+        self.can_output(code_point)
+    }
+    /// Sound effects 1 and 2, a high or low beep
+    fn beep(&mut self, high: bool);
+    /// Play sound resource `number` from its AIFF or Ogg data
+    fn play_sound(&mut self, number: u16, data: &[u8]) {
+        debug!("Sound {} ({} bytes) ignored", number, data.len());
+    }
+}
+
+/// Backing store for the numbered window properties (V6)
+#[derive(Clone, Copy, Default)]
+pub struct Window {
+    properties: [u16; 16]
+}
+
+impl Window {
+    pub fn get_property(&self, property: u16) -> Option<u16> {
+        self.properties.get(property as usize).cloned()
+    }
+
+    pub fn set_property(&mut self, property: u16, value: u16) -> bool {
+        match self.properties.get_mut(property as usize) {
+            Some(p) => {
+                *p = value;
+                true
+            },
+            None => false
+        }
+    }
+}
+
+/// Screen model shared by the interfaces: the selected window, font, window properties,
+/// screen buffering, the commands recorded by output stream 4 and the transcript
+pub struct Screen {
+    current_window: u16,
+    font: u16,
+    windows: [Window; 8],
+    buffer_screen: u16,
+    record_commands: bool,
+    commands: Vec<String>,
+    transcript: Option<File>
+}
+
+impl Screen {
+    pub fn new() -> Screen {
+        Screen { current_window: 0, font: 1, windows: [Window::default(); 8], buffer_screen: 1, record_commands: false, commands: Vec::new(), transcript: None }
+    }
+
+    pub fn current_window(&self) -> u16 {
+        self.current_window
+    }
+
+    pub fn set_current_window(&mut self, window: u16) {
+        self.current_window = window;
+    }
+
+    fn window_index(&self, window: u16) -> usize {
+        // -3 refers to the current window
+        if window as i16 == -3 {
+            self.current_window as usize
+        } else {
+            window as usize
+        }
+    }
+
+    pub fn window(&self, window: u16) -> Option<&Window> {
+        self.windows.get(self.window_index(window))
+    }
+
+    pub fn set_font(&mut self, font: u16) -> u16 {
+        match font {
+            // Font 0 queries the current font without changing it
+            0 => self.font,
+            // Only the normal and fixed-pitch fonts can be honored
+            1 | 4 => {
+                let previous = self.font;
+                self.font = font;
+                previous
+            },
+            _ => 0
+        }
+    }
+
+    pub fn get_window_property(&self, window: u16, property: u16) -> u16 {
+        match self.window(window) {
+            Some(w) => match w.get_property(property) {
+                Some(v) => v,
+                None => {
+                    warn!("Invalid window property {} for window {}", property, window);
+                    0
+                }
+            },
+            None => {
+                warn!("Invalid window {}", window);
+                0
+            }
+        }
+    }
+
+    pub fn set_window_property(&mut self, window: u16, property: u16, value: u16) {
+        let index = self.window_index(window);
+        match self.windows.get_mut(index) {
+            Some(w) => if !w.set_property(property, value) {
+                warn!("Invalid window property {} for window {}", property, window);
+            },
+            None => warn!("Invalid window {}", window)
+        }
+    }
+
+    /// Turn screen buffering off (0) or on (1), returning the previous setting.  -1
+    /// leaves the setting alone.
+    pub fn buffer_screen(&mut self, mode: u16) -> u16 {
+        let previous = self.buffer_screen;
+        if mode as i16 != -1 {
+            self.buffer_screen = if mode == 0 { 0 } else { 1 };
+        }
+        previous
+    }
+
+    pub fn is_screen_buffered(&self) -> bool {
+        self.buffer_screen == 1
+    }
+
+    pub fn record_commands(&mut self, enabled: bool) {
+        self.record_commands = enabled;
+    }
+
+    /// Keep a line of player input if output stream 4 is selected
+    pub fn record_command(&mut self, command: &str) {
+        if self.record_commands {
+            self.commands.push(command.to_string());
+        }
+    }
+
+    pub fn recorded_commands(&self) -> &[String] {
+        &self.commands
+    }
+
+    /// Record all commands and output to a transcript file.  Commands are written
+    /// on their own line prefixed with '>' so the file can be replayed as a script.
+    pub fn set_transcript(&mut self, file: File) {
+        self.transcript = Some(file);
+    }
+
+    pub fn transcribe(&mut self, text: &str) {
+        if let Some(f) = self.transcript.as_mut() {
+            if let Err(e) = f.write_all(text.as_bytes()) {
+                error!("Error writing transcript: {}", e);
+            }
+        }
+    }
+
+    /// Transcribe a line of player input
+    pub fn transcribe_command(&mut self, command: &str) {
+        self.transcribe(&format!(">{}\n", command));
+    }
+}
+
+/// True if the locale says the terminal uses UTF-8, otherwise only ASCII can be shown
+fn unicode_locale() -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+        .filter_map(|v| std::env::var(v).ok())
+        .find(|v| !v.is_empty())
+        .map_or(false, |v| v.to_uppercase().contains("UTF-8") || v.to_uppercase().contains("UTF8"))
+}
+
+/// True when `lines` printed since the last input fill the lower window below an upper
+/// window of `upper_window_lines`, leaving the bottom line for the [MORE] prompt
+fn page_full(lines: i32, rows: i32, upper_window_lines: i32) -> bool {
+    lines >= rows - upper_window_lines - 1
+}
+
+/// True if `word` fits between `column` and the `right` margin.  Words are measured in
+/// characters, not bytes, so accented characters take one column.
+fn fits(word: &str, column: i32, right: i32) -> bool {
+    word.chars().count() as i32 <= right - column
+}
+
+/// The spaces between the location `name` and the score or time on a status line
+/// `width` columns wide, after the leading space
+fn status_padding(width: usize, name: &str, right: &str) -> usize {
+    (width - 1).saturating_sub(name.chars().count() + right.chars().count())
+}
+
+/// Map cursor and function keys to their ZSCII input codes
+fn function_key(input: &Input) -> Option<u16> {
+    match input {
+        Input::KeyUp => Some(129),
+        Input::KeyDown => Some(130),
+        Input::KeyLeft => Some(131),
+        Input::KeyRight => Some(132),
+        Input::KeyF1 => Some(133),
+        Input::KeyF2 => Some(134),
+        Input::KeyF3 => Some(135),
+        Input::KeyF4 => Some(136),
+        Input::KeyF5 => Some(137),
+        Input::KeyF6 => Some(138),
+        Input::KeyF7 => Some(139),
+        Input::KeyF8 => Some(140),
+        Input::KeyF9 => Some(141),
+        Input::KeyF10 => Some(142),
+        Input::KeyF11 => Some(143),
+        Input::KeyF12 => Some(144),
+        _ => None
+    }
 }
 
 pub struct Curses {
-    pub window: EasyCurses
+    pub window: EasyCurses,
+    upper_window_lines: i32,
+    lower_window_cursor: (i32, i32),
+    buffered: bool,
+    input_stream: u16,
+    script: VecDeque<String>,
+    screen: Screen,
+    lines_since_input: i32,
+    size: (i32, i32),
+    unicode: bool
 }
 
 impl Curses {
@@ -33,45 +291,36 @@ impl Curses {
         window.set_input_mode(easycurses::InputMode::RawCharacter);
         window.refresh();
         window.set_color_pair(colorpair!(White on Black));
+        let size = window.get_row_col_count();
 
-        Curses { window: window }
+        Curses { window: window, upper_window_lines: 0, lower_window_cursor: (39, 0), buffered: true, input_stream: 0, script: VecDeque::new(), screen: Screen::new(), lines_since_input: 0, size, unicode: unicode_locale() }
     }
-}
 
-impl Interface for Curses {
-    fn print(&mut self, text: &str) {
-        let words: Vec<&str> = text.split(' ').collect();
-        debug!("{:?}", words);
-        let (rows, cols) = self.window.get_row_col_count();
-        debug!("{} {}", rows, cols);
-        for (i, word) in words.iter().enumerate() {
-            let (r,c) = self.window.get_cursor_rc();
-            debug!("{},{} => {} :: {}", r, c, word.len(), cols - c);
-            if word.len() > cols as usize - c as usize {
-                self.window.print_char('\n');
-                // if r == rows - 1 {
-                //     self.window.move_rc(0, 0);
-                //     self.window.delete_line();
-                //     self.window.move_rc(rows - 1, 0);
-                // } else {
-                //     self.window.move_rc(r + 1 , 0);
-                // }
-            }
-            self.window.print(word);
-            if i < words.len() - 1 {
-                self.window.print_char(' ');
-            }
-        }
-        
-        self.window.refresh();
+    /// Load a command script to be read when input stream 1 is selected
+    pub fn set_script(&mut self, commands: VecDeque<String>) {
+        self.script = commands;
     }
 
-    fn new_line(&mut self) {
-        self.window.print_char('\n');
-        self.window.refresh();
+    /// Record all commands and output to a transcript file
+    pub fn set_transcript(&mut self, file: File) {
+        self.screen.set_transcript(file);
     }
 
-    fn read(&mut self, terminating_characters: HashSet<char>, max_chars: usize) -> String {
+    fn read_script(&mut self, max_chars: usize) -> Option<String> {
+        if let Some(command) = self.script.pop_front() {
+            let result:String = command.chars().take(max_chars).collect();
+            self.window.print(&result);
+            self.window.print_char('\n');
+            self.window.refresh();
+            Some(result)
+        } else {
+            debug!("Command script exhausted, reading from keyboard");
+            self.input_stream = 0;
+            None
+        }
+    }
+
+    fn read_keyboard(&mut self, terminating_characters: &HashSet<u16>, max_chars: usize) -> (String, u16) {
         let mut result = String::new();
         loop {
             if let Some(e) = self.window.get_input() {
@@ -79,10 +328,10 @@ impl Interface for Curses {
                 debug!("get_input() -> {:?} at {},{}", e, r, c);
                 match e {
                     easycurses::Input::Character(c) => {
-                        if terminating_characters.contains(&c) {
-                            result.push(c);
-                            self.new_line();
-                            break;
+                        if c == '\n' || c == '\r' {
+                            self.window.print_char('\n');
+                            self.window.refresh();
+                            return (result, 13);
                         }
 
                         if c as u16 == 8 {
@@ -96,7 +345,7 @@ impl Interface for Curses {
                         // TODO: Filter the specific accented characters that we support
                         // TODO: include A2 punctuation
                         } else if c.is_alphabetic() || c.is_ascii() || c as u16 == 32 {
-                            if result.len() < max_chars {
+                            if result.chars().count() < max_chars {
                                 self.window.print_char(c);
                                 self.window.refresh();
                                 result.push(c);
@@ -104,15 +353,171 @@ impl Interface for Curses {
                         }
                     },
                     easycurses::Input::KeyEnter => {
-                        result.push('\n');
-                        break;
+                        return (result, 13);
                     },
-                    _ => {}
+                    _ => if let Some(z) = function_key(&e) {
+                        // 255 in the terminating characters table means any function key
+                        if terminating_characters.contains(&z) || terminating_characters.contains(&255) {
+                            return (result, z);
+                        }
+                    }
                 }
             }
         }
-        
-        result
+    }
+
+    fn prompt(&mut self) {
+        let (r, c) = self.window.get_cursor_rc();
+        self.window.print("[MORE]");
+        self.window.refresh();
+        self.window.get_input();
+        self.window.move_rc(r, c);
+        self.window.print("      ");
+        self.window.move_rc(r, c);
+        self.window.refresh();
+    }
+
+    /// Move to a new line, pausing with a [MORE] prompt when the lower window has
+    /// filled since the last input
+    fn line_break(&mut self) {
+        if self.screen.current_window() == 0 && self.input_stream == 0 {
+            self.lines_since_input = self.lines_since_input + 1;
+            if page_full(self.lines_since_input, self.window.get_row_col_count().0, self.upper_window_lines) {
+                self.prompt();
+                self.lines_since_input = 0;
+            }
+        }
+        self.window.print_char('\n');
+
+        let left = self.margins().0;
+        if left > 0 {
+            let (r, _) = self.window.get_cursor_rc();
+            self.window.move_rc(r, left);
+        }
+    }
+
+    /// Left and right margins of the current window in columns.  Margins are set in
+    /// V6 units, which are approximated as one column each.
+    fn margins(&self) -> (i32, i32) {
+        match self.screen.window(self.screen.current_window()) {
+            Some(w) => (w.get_property(6).unwrap_or(0) as i32, w.get_property(7).unwrap_or(0) as i32),
+            None => (0, 0)
+        }
+    }
+
+    /// Output is only batched until the next flush while screen buffering is on
+    fn refresh_unbuffered(&mut self) {
+        if !self.screen.is_screen_buffered() {
+            self.window.refresh();
+        }
+    }
+
+    /// Blank rows `from` up to `to`.  The bottom right cell is left alone so the screen
+    /// doesn't scroll.
+    fn clear_rows(&mut self, from: i32, to: i32) {
+        let (rows, columns) = self.window.get_row_col_count();
+        for r in from..to {
+            self.window.move_rc(r, 0);
+            let width = if r == rows - 1 { columns - 1 } else { columns };
+            for _ in 0..width {
+                self.window.print_char(' ');
+            }
+        }
+    }
+
+    fn window_origin(&self) -> i32 {
+        if self.screen.current_window() == 1 {
+            0
+        } else {
+            self.upper_window_lines
+        }
+    }
+}
+
+impl Interface for Curses {
+    fn print(&mut self, text: &str) {
+        self.screen.transcribe(text);
+
+        // Unbuffered output is printed as-is, wrapping mid-word at the screen edge
+        if !self.buffered {
+            self.window.print(text);
+            self.refresh_unbuffered();
+            return;
+        }
+
+        let words: Vec<&str> = text.split(' ').collect();
+        debug!("{:?}", words);
+        let (rows, cols) = self.window.get_row_col_count();
+        debug!("{} {}", rows, cols);
+        for (i, word) in words.iter().enumerate() {
+            let (r,c) = self.window.get_cursor_rc();
+            let right = cols - self.margins().1;
+            debug!("{},{} => {} :: {}", r, c, word, right - c);
+            if !fits(word, c, right) {
+                self.line_break();
+                // if r == rows - 1 {
+                //     self.window.move_rc(0, 0);
+                //     self.window.delete_line();
+                //     self.window.move_rc(rows - 1, 0);
+                // } else {
+                //     self.window.move_rc(r + 1 , 0);
+                // }
+            }
+            for (j, line) in word.split('\n').enumerate() {
+                if j > 0 {
+                    self.line_break();
+                }
+                self.window.print(line);
+            }
+            if i < words.len() - 1 {
+                self.window.print_char(' ');
+            }
+        }
+        self.refresh_unbuffered();
+    }
+
+    fn new_line(&mut self) {
+        self.screen.transcribe("\n");
+        self.line_break();
+        self.refresh_unbuffered();
+    }
+
+    fn read_char(&mut self) -> u16 {
+        self.flush();
+        loop {
+            if let Some(e) = self.window.get_input() {
+                match e {
+                    easycurses::Input::Character(c) => match c {
+                        '\n' | '\r' => return 13,
+                        _ => return c as u16
+                    },
+                    easycurses::Input::KeyEnter => return 13,
+                    _ => if let Some(z) = function_key(&e) {
+                        return z
+                    }
+                }
+            }
+        }
+    }
+
+    fn read(&mut self, terminating_characters: &HashSet<u16>, max_chars: usize) -> (String, u16) {
+        // Output is batched in the curses back buffer until input is needed
+        self.flush();
+        let script = if self.input_stream == 1 {
+            self.read_script(max_chars)
+        } else {
+            None
+        };
+
+        let (result, terminator) = match script {
+            Some(command) => (command, 13),
+            None => self.read_keyboard(terminating_characters, max_chars)
+        };
+
+        self.lines_since_input = 0;
+        self.screen.transcribe_command(&result);
+        self.screen.record_command(&result);
+        (result, terminator)
     }
 
     fn status_line(&mut self, name: &str, format: StatusLineFormat, v1: i16, v2: u16) {
@@ -136,8 +541,7 @@ impl Interface for Curses {
             }
         };
 
-        let padding = width as usize - 1 - name.len() - left_str.len();
-        for i in 0..padding {
+        for i in 0..status_padding(width as usize, name, &left_str) {
             self.window.print_char(' ');
         }
         self.window.print(left_str);
@@ -146,4 +550,639 @@ impl Interface for Curses {
         self.window.move_rc(r, c);
         self.window.refresh();
     }
-}
\ No newline at end of file
+
+    fn split_window(&mut self, lines: u16) {
+        let rows = self.window.get_row_col_count().0;
+        self.upper_window_lines = lines as i32;
+        debug!("{:?}", self.window.set_scroll_region(lines as i32, rows - 1));
+
+        // Keep the lower window cursor out of the upper window
+        let (r, c) = self.lower_window_cursor;
+        if r < self.upper_window_lines {
+            self.lower_window_cursor = (self.upper_window_lines, c);
+        }
+        if self.screen.current_window() == 0 {
+            let (r, c) = self.window.get_cursor_rc();
+            if r < self.upper_window_lines {
+                self.window.move_rc(self.upper_window_lines, c);
+            }
+        }
+        self.window.refresh();
+    }
+
+    fn set_window(&mut self, window: u16) {
+        if window == self.screen.current_window() {
+            return;
+        }
+
+        if window == 1 {
+            self.lower_window_cursor = self.window.get_cursor_rc();
+            self.screen.set_current_window(1);
+            self.window.move_rc(0, 0);
+        } else {
+            let (r, c) = self.lower_window_cursor;
+            self.screen.set_current_window(0);
+            self.window.move_rc(r, c);
+        }
+        self.window.refresh();
+    }
+
+    fn get_window(&self) -> u16 {
+        self.screen.current_window()
+    }
+
+    fn set_cursor(&mut self, line: u16, column: u16) {
+        let origin = self.window_origin();
+        self.window.move_rc(origin + line as i32 - 1, column as i32 - 1);
+        self.window.refresh();
+    }
+
+    fn get_cursor(&mut self) -> (u16, u16) {
+        let (r, c) = self.window.get_cursor_rc();
+        ((r - self.window_origin() + 1) as u16, (c + 1) as u16)
+    }
+
+    fn erase_line(&mut self, columns: Option<u16>) {
+        let (r, c) = self.window.get_cursor_rc();
+        let remaining = self.window.get_row_col_count().1 - c;
+        let count = match columns {
+            Some(n) => std::cmp::min(n as i32, remaining),
+            None => remaining
+        };
+        for _ in 0..count {
+            self.window.print_char(' ');
+        }
+        self.window.move_rc(r, c);
+    }
+
+    fn erase_window(&mut self, window: i16) {
+        let rows = self.window.get_row_col_count().0;
+        let (from, to) = match window {
+            -1 => {
+                self.split_window(0);
+                self.screen.set_current_window(0);
+                (0, rows)
+            },
+            -2 => (0, rows),
+            0 => (self.upper_window_lines, rows),
+            1 => (0, self.upper_window_lines),
+            _ => {
+                warn!("Invalid window {}", window);
+                return
+            }
+        };
+        self.clear_rows(from, to);
+
+        // Erased windows have their cursor at the top left
+        if window != 1 {
+            self.lower_window_cursor = (self.upper_window_lines, 0);
+            self.lines_since_input = 0;
+        }
+        let origin = self.window_origin();
+        if window < 0 || window as u16 == self.screen.current_window() {
+            self.window.move_rc(origin, 0);
+        } else if self.screen.current_window() == 0 {
+            let (r, c) = self.lower_window_cursor;
+            self.window.move_rc(r, c);
+        }
+        self.refresh_unbuffered();
+    }
+
+    /// Only reverse video can be shown, so bold, italic and fixed pitch are ignored
+    fn set_text_style(&mut self, style: u16) {
+        if style == 0 {
+            self.window.set_color_pair(colorpair!(White on Black));
+        } else if style & 0x01 == 0x01 {
+            self.window.set_color_pair(colorpair!(Black on White));
+        }
+    }
+
+    fn buffer_mode(&mut self, flag: u16) {
+        self.buffered = flag != 0;
+    }
+
+    fn buffer_screen(&mut self, mode: u16) -> u16 {
+        let previous = self.screen.buffer_screen(mode);
+        // Redraw on request, and show anything batched once buffering is turned off
+        if mode as i16 == -1 || mode == 0 {
+            self.window.refresh();
+        }
+        previous
+    }
+
+    fn input_stream(&mut self, stream: u16) {
+        self.input_stream = stream;
+    }
+
+    fn record_commands(&mut self, enabled: bool) {
+        self.screen.record_commands(enabled);
+    }
+
+    fn recorded_commands(&self) -> &[String] {
+        self.screen.recorded_commands()
+    }
+
+    fn set_font(&mut self, font: u16) -> u16 {
+        self.screen.set_font(font)
+    }
+
+    fn get_window_property(&self, window: u16, property: u16) -> u16 {
+        self.screen.get_window_property(window, property)
+    }
+
+    fn set_window_property(&mut self, window: u16, property: u16, value: u16) {
+        self.screen.set_window_property(window, property, value);
+    }
+
+    fn has_input(&self) -> bool {
+        // The keyboard is always available
+        true
+    }
+
+    fn on_resize(&mut self) -> Option<(u16, u16)> {
+        let (rows, columns) = self.window.get_row_col_count();
+        if (rows, columns) == self.size {
+            return None
+        }
+
+        debug!("Terminal resized from {:?} to {:?}", self.size, (rows, columns));
+        self.size = (rows, columns);
+
+        // Reset the scroll region for the new height and keep the cursors on screen
+        self.split_window(self.upper_window_lines as u16);
+        let (r, c) = self.lower_window_cursor;
+        self.lower_window_cursor = (std::cmp::min(r, rows - 1), std::cmp::min(c, columns - 1));
+        let (r, c) = self.window.get_cursor_rc();
+        self.window.move_rc(std::cmp::min(r, rows - 1), std::cmp::min(c, columns - 1));
+        self.window.refresh();
+
+        Some((rows as u16, columns as u16))
+    }
+
+    fn flush(&mut self) {
+        self.window.refresh();
+    }
+
+    fn can_output(&self, code_point: u32) -> bool {
+        match std::char::from_u32(code_point) {
+            Some(c) => if self.unicode { !c.is_control() } else { c.is_ascii() && !c.is_ascii_control() },
+            None => false
+        }
+    }
+
+    fn can_input(&self, code_point: u32) -> bool {
+        // Keyboard input accepts the same characters as read_keyboard
+        match std::char::from_u32(code_point) {
+            Some(c) => self.can_output(code_point) && (c.is_alphabetic() || c.is_ascii()),
+            None => false
+        }
+    }
+
+    fn beep(&mut self, _high: bool) {
+        self.window.beep();
+    }
+
+    #[cfg(feature = "sound")]
+    fn play_sound(&mut self, number: u16, data: &[u8]) {
+        let cursor = std::io::Cursor::new(data.to_vec());
+        match rodio::default_output_device() {
+            Some(device) => match rodio::play_once(&device, cursor) {
+                Ok(sink) => sink.detach(),
+                Err(e) => error!("Error playing sound {}: {}", number, e)
+            },
+            None => warn!("No audio device for sound {}", number)
+        }
+    }
+}
+
+/// A screenless interface for the REST runner.  Output is collected in a buffer to be
+/// returned in the response, and input is read from a queue supplied by the request.
+pub struct Buffered {
+    output: String,
+    input: VecDeque<String>,
+    screen: Screen,
+    cursor: (u16, u16)
+}
+
+impl Buffered {
+    pub fn new(input: VecDeque<String>) -> Buffered {
+        Buffered { output: String::new(), input, screen: Screen::new(), cursor: (1, 1) }
+    }
+
+    /// Record all commands and output to a transcript file
+    pub fn set_transcript(&mut self, file: File) {
+        self.screen.set_transcript(file);
+    }
+
+    /// Take the output collected so far
+    pub fn take_output(&mut self) -> String {
+        std::mem::replace(&mut self.output, String::new())
+    }
+}
+
+impl Interface for Buffered {
+    fn print(&mut self, text: &str) {
+        self.screen.transcribe(text);
+        self.output.push_str(text);
+    }
+
+    fn new_line(&mut self) {
+        self.screen.transcribe("\n");
+        self.output.push('\n');
+    }
+
+    fn read(&mut self, _terminating_characters: &HashSet<u16>, max_chars: usize) -> (String, u16) {
+        match self.input.pop_front() {
+            Some(command) => {
+                let result:String = command.chars().take(max_chars).collect();
+                self.output.push_str(&format!("{}\n", result));
+                self.screen.transcribe_command(&result);
+                self.screen.record_command(&result);
+                (result, 13)
+            },
+            None => {
+                warn!("Read with no input queued");
+                (String::new(), 13)
+            }
+        }
+    }
+
+    /// A keypress is the first character of the next queued command, or Enter if the
+    /// command is empty
+    fn read_char(&mut self) -> u16 {
+        match self.input.front_mut() {
+            Some(command) => if command.is_empty() {
+                self.input.pop_front();
+                13
+            } else {
+                command.remove(0) as u16
+            },
+            None => {
+                warn!("Read with no input queued");
+                13
+            }
+        }
+    }
+
+    fn status_line(&mut self, name: &str, _format: StatusLineFormat, v1: i16, v2: u16) {
+        debug!("Status line: {} {} {}", name, v1, v2);
+    }
+
+    fn split_window(&mut self, lines: u16) {
+        debug!("split_window {} ignored", lines);
+    }
+
+    fn set_window(&mut self, window: u16) {
+        self.screen.set_current_window(window);
+    }
+
+    fn get_window(&self) -> u16 {
+        self.screen.current_window()
+    }
+
+    fn set_cursor(&mut self, line: u16, column: u16) {
+        self.cursor = (line, column);
+    }
+
+    fn get_cursor(&mut self) -> (u16, u16) {
+        self.cursor
+    }
+
+    fn erase_line(&mut self, columns: Option<u16>) {
+        debug!("erase_line {:?} ignored", columns);
+    }
+
+    fn erase_window(&mut self, window: i16) {
+        debug!("erase_window {} ignored", window);
+    }
+
+    fn set_text_style(&mut self, style: u16) {
+        debug!("set_text_style {} ignored", style);
+    }
+
+    fn buffer_mode(&mut self, _flag: u16) {}
+
+    fn buffer_screen(&mut self, mode: u16) -> u16 {
+        self.screen.buffer_screen(mode)
+    }
+
+    fn input_stream(&mut self, stream: u16) {
+        debug!("input_stream {} ignored", stream);
+    }
+
+    fn record_commands(&mut self, enabled: bool) {
+        self.screen.record_commands(enabled);
+    }
+
+    fn recorded_commands(&self) -> &[String] {
+        self.screen.recorded_commands()
+    }
+
+    fn set_font(&mut self, font: u16) -> u16 {
+        self.screen.set_font(font)
+    }
+
+    fn get_window_property(&self, window: u16, property: u16) -> u16 {
+        self.screen.get_window_property(window, property)
+    }
+
+    fn set_window_property(&mut self, window: u16, property: u16, value: u16) {
+        self.screen.set_window_property(window, property, value);
+    }
+
+    fn has_input(&self) -> bool {
+        !self.input.is_empty()
+    }
+
+    fn flush(&mut self) {}
+
+    fn beep(&mut self, high: bool) {
+        debug!("{} beep ignored", if high { "High" } else { "Low" });
+    }
+}
+
+/// A line-based interface for terminals where curses isn't available, such as pipes
+/// and SSH sessions.  It reads from stdin and writes to stdout unless other streams
+/// are supplied, in which case input lines are echoed so the output reads as a
+/// transcript.  Only the lower window is shown.
+pub struct StdioInterface<R = BufReader<Stdin>, W = Stdout> {
+    reader: R,
+    writer: W,
+    screen: Screen,
+    echo: bool,
+    eof: bool
+}
+
+impl StdioInterface {
+    pub fn new() -> StdioInterface {
+        // The terminal shows what is typed
+        StdioInterface { echo: false, ..StdioInterface::with_io(BufReader::new(io::stdin()), io::stdout()) }
+    }
+}
+
+impl<R: BufRead, W: Write> StdioInterface<R, W> {
+    pub fn with_io(reader: R, writer: W) -> StdioInterface<R, W> {
+        StdioInterface { reader, writer, screen: Screen::new(), echo: true, eof: false }
+    }
+
+    /// Record all commands and output to a transcript file
+    pub fn set_transcript(&mut self, file: File) {
+        self.screen.set_transcript(file);
+    }
+
+    /// The output stream, for reading back output written to an in-memory writer
+    pub fn writer(&self) -> &W {
+        &self.writer
+    }
+
+    fn write(&mut self, text: &str) {
+        if let Err(e) = self.writer.write_all(text.as_bytes()) {
+            error!("Error writing output: {}", e);
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Interface for StdioInterface<R, W> {
+    fn print(&mut self, text: &str) {
+        // Upper window text such as status bars would be jumbled into the transcript
+        if self.screen.current_window() == 0 {
+            self.screen.transcribe(text);
+            self.write(text);
+        }
+    }
+
+    fn new_line(&mut self) {
+        if self.screen.current_window() == 0 {
+            self.screen.transcribe("\n");
+            self.write("\n");
+        }
+    }
+
+    fn read(&mut self, _terminating_characters: &HashSet<u16>, max_chars: usize) -> (String, u16) {
+        self.flush();
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => {
+                debug!("End of input");
+                self.eof = true;
+            },
+            Ok(_) => {},
+            Err(e) => {
+                error!("Error reading input: {}", e);
+                self.eof = true;
+            }
+        }
+
+        let result:String = line.trim_end_matches(|c| c == '\n' || c == '\r').chars().take(max_chars).collect();
+        if !self.eof {
+            if self.echo {
+                self.write(&format!("{}\n", result));
+            }
+            self.screen.transcribe_command(&result);
+        }
+        self.screen.record_command(&result);
+        (result, 13)
+    }
+
+    fn read_char(&mut self) -> u16 {
+        self.flush();
+        let mut byte = [0];
+        match self.reader.read(&mut byte) {
+            Ok(0) => {
+                debug!("End of input");
+                self.eof = true;
+                13
+            },
+            Ok(_) => match byte[0] {
+                b'\n' | b'\r' => 13,
+                b => b as u16
+            },
+            Err(e) => {
+                error!("Error reading input: {}", e);
+                self.eof = true;
+                13
+            }
+        }
+    }
+
+    /// The status line is printed as a banner before the prompt
+    fn status_line(&mut self, name: &str, format: StatusLineFormat, v1: i16, v2: u16) {
+        let status = match format {
+            StatusLineFormat::SCORED => format!("Score: {} Turn: {}", v1, v2),
+            StatusLineFormat::TIMED => format!("{}:{:02} {}", v1.rem_euclid(12), v2, if v1 > 11 { "PM" } else { "AM" })
+        };
+        self.write(&format!("[{} | {}]\n", name, status));
+    }
+
+    fn split_window(&mut self, lines: u16) {
+        debug!("split_window {} ignored", lines);
+    }
+
+    fn set_window(&mut self, window: u16) {
+        self.screen.set_current_window(window);
+    }
+
+    fn get_window(&self) -> u16 {
+        self.screen.current_window()
+    }
+
+    fn set_cursor(&mut self, line: u16, column: u16) {
+        debug!("set_cursor {},{} ignored", line, column);
+    }
+
+    fn get_cursor(&mut self) -> (u16, u16) {
+        (1, 1)
+    }
+
+    fn erase_line(&mut self, columns: Option<u16>) {
+        debug!("erase_line {:?} ignored", columns);
+    }
+
+    fn erase_window(&mut self, window: i16) {
+        debug!("erase_window {} ignored", window);
+    }
+
+    fn set_text_style(&mut self, style: u16) {
+        debug!("set_text_style {} ignored", style);
+    }
+
+    fn buffer_mode(&mut self, _flag: u16) {}
+
+    fn buffer_screen(&mut self, mode: u16) -> u16 {
+        self.screen.buffer_screen(mode)
+    }
+
+    fn input_stream(&mut self, stream: u16) {
+        debug!("input_stream {} ignored", stream);
+    }
+
+    fn record_commands(&mut self, enabled: bool) {
+        self.screen.record_commands(enabled);
+    }
+
+    fn recorded_commands(&self) -> &[String] {
+        self.screen.recorded_commands()
+    }
+
+    fn set_font(&mut self, font: u16) -> u16 {
+        self.screen.set_font(font)
+    }
+
+    fn get_window_property(&self, window: u16, property: u16) -> u16 {
+        self.screen.get_window_property(window, property)
+    }
+
+    fn set_window_property(&mut self, window: u16, property: u16, value: u16) {
+        self.screen.set_window_property(window, property, value);
+    }
+
+    fn has_input(&self) -> bool {
+        // Input blocks until a line is available, until the end of input is reached
+        !self.eof
+    }
+
+    fn flush(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            error!("Error flushing output: {}", e);
+        }
+    }
+
+    fn beep(&mut self, _high: bool) {
+        self.write("\x07");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_properties_address_the_current_window_as_minus_3() {
+        let mut screen = Screen::new();
+        screen.set_current_window(2);
+        screen.set_window_property(-3i16 as u16, 10, 0x0F);
+        assert_eq!(screen.get_window_property(2, 10), 0x0F);
+        assert_eq!(screen.get_window_property(0, 10), 0);
+
+        // Unknown windows and properties read as 0 and ignore writes
+        screen.set_window_property(8, 0, 1);
+        screen.set_window_property(0, 16, 1);
+        assert_eq!(screen.get_window_property(8, 0), 0);
+        assert_eq!(screen.get_window_property(0, 16), 0);
+    }
+
+    #[test]
+    fn buffered_shares_the_screen_model() {
+        let mut interface = Buffered::new(VecDeque::from(vec![String::from("look")]));
+        assert_eq!(interface.set_font(4), 1);
+        assert_eq!(interface.set_font(3), 0);
+        assert_eq!(interface.set_font(0), 4);
+        assert_eq!(interface.buffer_screen(0), 1);
+        assert_eq!(interface.buffer_screen(-1i16 as u16), 0);
+
+        interface.record_commands(true);
+        interface.read(&HashSet::new(), 80);
+        assert_eq!(interface.recorded_commands(), &[String::from("look")]);
+    }
+
+    #[test]
+    fn stdio_echoes_scripted_input_to_the_writer() {
+        let input = io::Cursor::new(b"open mailbox\nread leaflet\n".to_vec());
+        let mut interface = StdioInterface::with_io(input, Vec::new());
+
+        interface.print(">");
+        assert_eq!(interface.read(&HashSet::new(), 80), (String::from("open mailbox"), 13));
+        interface.print("Opening the small mailbox reveals a leaflet.");
+        interface.new_line();
+
+        // The upper window is not shown
+        interface.set_window(1);
+        interface.print("West of House");
+        interface.set_window(0);
+
+        interface.print(">");
+        assert_eq!(interface.read(&HashSet::new(), 4).0, "read");
+        assert!(interface.has_input());
+        interface.read(&HashSet::new(), 80);
+        assert!(!interface.has_input());
+
+        assert_eq!(String::from_utf8(interface.writer().clone()).unwrap(),
+                   ">open mailbox\nOpening the small mailbox reveals a leaflet.\n>read\n");
+    }
+
+    #[test]
+    fn accented_characters_are_one_column_wide() {
+        // "café" is 5 bytes but 4 columns, so it fits in the last 4 columns of the line
+        assert!(fits("café", 76, 80));
+        assert!(!fits("cafés", 76, 80));
+        assert!(fits("", 80, 80));
+
+        assert_eq!(status_padding(80, "Château", "Score: 0"), 64);
+        assert_eq!(status_padding(10, "Château", "Score: 0"), 0);
+    }
+
+    #[test]
+    fn more_prompts_allow_for_the_upper_window() {
+        // Count the [MORE] prompts while printing 100 lines on a 40 line screen, as
+        // `Curses::line_break` does
+        let prompts = |upper_window_lines| {
+            let (mut lines, mut prompts) = (0, 0);
+            for _ in 0..100 {
+                lines += 1;
+                if page_full(lines, 40, upper_window_lines) {
+                    prompts += 1;
+                    lines = 0;
+                }
+            }
+            prompts
+        };
+        assert_eq!(prompts(0), 2);
+        assert_eq!(prompts(3), 2);
+        assert_eq!(prompts(10), 3);
+
+        // A 3 line upper window leaves 36 lines before the prompt
+        assert!(!page_full(35, 40, 3));
+        assert!(page_full(36, 40, 3));
+        assert!(page_full(39, 40, 0));
+    }
+}
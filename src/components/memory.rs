@@ -4,7 +4,9 @@ use std::convert::TryFrom;
 use log::{error};
 use redis::{FromRedisValue, ToRedisArgs, RedisResult, Value};
 
-use super::redis_connection::{RedisConnection};
+use super::blorb::Blorb;
+use super::header::{FLAGS2_TRANSCRIPTING, FLAGS2_FORCE_FIXED};
+use super::redis_connection::{RedisPool};
 use super::InfocomError;
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
@@ -16,6 +18,13 @@ impl Default for Version {
     fn default() -> Self { Version::V(0) }
 }
 
+impl Version {
+    pub fn number(&self) -> u8 {
+        let Version::V(v) = self;
+        *v
+    }
+}
+
 pub trait ZValue {
     fn new(values: &[u8]) -> Self;
     fn size() -> usize;
@@ -66,26 +75,51 @@ impl TryFrom<Vec<u8>> for MemoryMap {
     type Error = InfocomError;
 
     fn try_from(data: Vec<u8>) -> Result<MemoryMap, Self::Error> {
-        if data.len() > 0 {
-            let high:u16 = data[0xe].into();
-            let low:u16 = data[0xf].into();
-            let mark:usize = (((high << 8) & 0xFF00) | (low & 0xFF)).into();  
-            let dynamic_restore = data[0..mark].to_vec();  
-            Ok(MemoryMap { version: Version::V(data[0]),
-                           memory_map: data,
-                           dynamic_restore,
-                           static_mark: mark})
+        // A .zblorb wraps the story image in a ZCOD chunk
+        let data = if Blorb::is_blorb(&data) {
+            match Blorb::from_bytes(data)?.executable() {
+                Some(story) => story.to_vec(),
+                None => {
+                    error!("Blorb file has no ZCOD executable");
+                    return Err(InfocomError::Memory(format!("Invalid memory map data: Blorb file has no Z-code story")))
+                }
+            }
         } else {
-            Err(InfocomError::Memory(format!("Invalid memory map data")))
+            data
+        };
+
+        if data.len() < 0x40 {
+            error!("Memory map data is smaller than the header: {} bytes", data.len());
+            return Err(InfocomError::Memory(format!("Invalid memory map data: {} bytes is smaller than the header", data.len())))
         }
+
+        let version = data[0];
+        if version < 1 || version > 8 {
+            error!("Unsupported version: {}", version);
+            return Err(InfocomError::Version(Version::V(version)))
+        }
+
+        let high:u16 = data[0xe].into();
+        let low:u16 = data[0xf].into();
+        let mark:usize = (((high << 8) & 0xFF00) | (low & 0xFF)).into();  
+        if mark > data.len() {
+            error!("Static memory mark ${:04x} beyond end of data ${:06x}", mark, data.len());
+            return Err(InfocomError::Memory(format!("Invalid memory map data: static memory mark ${:04x} is beyond the end of data", mark)))
+        }
+
+        let dynamic_restore = data[0..mark].to_vec();  
+        Ok(MemoryMap { version: Version::V(version),
+                       memory_map: data,
+                       dynamic_restore,
+                       static_mark: mark})
     }
 }
 
-impl TryFrom<&String> for MemoryMap {
+impl TryFrom<(&RedisPool, &String)> for MemoryMap {
     type Error = InfocomError;
 
-    fn try_from(id: &String) -> Result<MemoryMap, InfocomError> {
-        let mut con = RedisConnection::new("redis://localhost")?;
+    fn try_from((pool, id): (&RedisPool, &String)) -> Result<MemoryMap, InfocomError> {
+        let mut con = pool.get()?;
         let mem: MemoryMap = con.get(id)?;
         if let Err(e) = con.touch(id) {
             error!("Error updating expiration for key {}: {}", id, e);
@@ -97,7 +131,15 @@ impl TryFrom<&String> for MemoryMap {
 impl FromRedisValue for MemoryMap {
     fn from_redis_value(v: &Value) -> RedisResult<MemoryMap> {
         match *v {
-            Value::Data(ref bytes) => Ok(serde_json::from_str(&String::from_utf8(bytes.to_vec()).unwrap()).unwrap()),
+            Value::Data(ref bytes) => match serde_json::from_slice(bytes) {
+                Ok(mem) => Ok(mem),
+                Err(e) => {
+                    error!("Unable to deserialize MemoryMap: {}", e);
+                    Err(redis::RedisError::from((redis::ErrorKind::TypeError,
+                                                    "Invalid MemoryMap data",
+                                                    format!("{}", e))))
+                }
+            },
             _ => {
                 error!("Unable to read MemoryMap from redis value: {:?}", v);
                 Err(redis::RedisError::from((redis::ErrorKind::TypeError, 
@@ -119,10 +161,32 @@ impl ToRedisArgs for &MemoryMap {
 }
 
 impl MemoryMap {
-    fn len(&self) -> usize {
+    pub fn len(&self) -> usize {
         self.memory_map.len()
     }
 
+    /// Address of the first byte of static memory
+    pub fn static_mark(&self) -> usize {
+        self.static_mark
+    }
+
+    pub fn version_number(&self) -> u8 {
+        self.version.number()
+    }
+
+    /// Versions 1-3: 32 attributes, 255 objects, 2 byte dictionary text
+    pub fn is_v3_or_earlier(&self) -> bool {
+        self.version_number() <= 3
+    }
+
+    pub fn is_v4_or_later(&self) -> bool {
+        self.version_number() >= 4
+    }
+
+    pub fn is_v5_or_later(&self) -> bool {
+        self.version_number() >= 5
+    }
+
     /// Gets a (read-only) copy of the memory map
     /// 
     /// # Examples
@@ -168,6 +232,19 @@ impl MemoryMap {
         Ok((((high as u16) << 8) & 0xFF00) | ((low as u16) & 0xFF))
     }
 
+    /// Read a signed word from the memory map, restricted to the bottom 64k of memory.
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use memory::Memory;
+    /// 
+    /// let w = mem.get_signed_word(0x12).unwrap();
+    /// ```
+    pub fn get_signed_word(&self, address: usize) -> Result<i16, InfocomError> {
+        Ok(self.get_word(address)? as i16)
+    }
+
     /// Write a byte to the dynamic region of memory.
     /// 
     /// # Examples
@@ -200,4 +277,237 @@ impl MemoryMap {
         self.set_byte(address, (value >> 8) as u8 & 0xFF)?;
         self.set_byte(address + 1, value as u8 & 0xFF)
     }
+
+    /// Compress dynamic memory as a Quetzal CMem delta against the original story data.
+    ///
+    /// Each byte is XORed with its original value; runs of unchanged (zero) bytes are
+    /// encoded as `0x00` followed by the run length minus 1.  Trailing zeros are omitted.
+    pub fn dynamic_diff(&self) -> Vec<u8> {
+        let mut diff = Vec::new();
+        let mut run = 0;
+        for (i, original) in self.dynamic_restore.iter().enumerate() {
+            let b = self.memory_map[i] ^ original;
+            if b == 0 {
+                run = run + 1;
+            } else {
+                // Runs are only written once a change follows, so trailing zeros are dropped
+                while run > 0 {
+                    let length = std::cmp::min(run, 256);
+                    diff.push(0);
+                    diff.push((length - 1) as u8);
+                    run = run - length;
+                }
+                diff.push(b);
+            }
+        }
+
+        diff
+    }
+
+    /// Replace dynamic memory with an uncompressed snapshot, keeping the current
+    /// transcripting and fixed-pitch bits of Flags 2, which must survive a restore.
+    pub fn reload_dynamic(&mut self, data: &[u8]) -> Result<(), InfocomError> {
+        if data.len() != self.static_mark {
+            return Err(InfocomError::Memory(format!("Uncompressed memory is ${:04x} bytes, expected ${:04x}", data.len(), self.static_mark)))
+        }
+
+        let preserve = (FLAGS2_TRANSCRIPTING | FLAGS2_FORCE_FIXED) as u8;
+        let flags2 = self.memory_map[0x11] & preserve;
+        self.memory_map[0..data.len()].copy_from_slice(data);
+        self.memory_map[0x11] = (self.memory_map[0x11] & !preserve) | flags2;
+        Ok(())
+    }
+
+    /// Reset dynamic memory to its state when the story was loaded, as for `restart`
+    pub fn reset_dynamic(&mut self) -> Result<(), InfocomError> {
+        let data = self.dynamic_restore.clone();
+        self.reload_dynamic(&data)
+    }
+
+    /// Sum of the bytes of the original story data from $0040 up to `length`, modulo $10000
+    pub fn checksum(&self, length: usize) -> u16 {
+        let mut sum:u16 = 0;
+        for address in 0x40..std::cmp::min(length, self.len()) {
+            let b = match self.dynamic_restore.get(address) {
+                Some(b) => *b,
+                None => self.memory_map[address]
+            };
+            sum = sum.wrapping_add(b as u16);
+        }
+        sum
+    }
+
+    /// Restore dynamic memory from a delta produced by `dynamic_diff`
+    pub fn apply_diff(&mut self, diff: &[u8]) -> Result<(), InfocomError> {
+        let mut data = self.dynamic_restore.clone();
+        let mut address = 0;
+        let mut i = diff.iter();
+        while let Some(b) = i.next() {
+            if *b == 0 {
+                match i.next() {
+                    Some(n) => address = address + *n as usize + 1,
+                    None => return Err(InfocomError::Memory(format!("Compressed memory ended on an incomplete run")))
+                }
+            } else {
+                if address >= data.len() {
+                    return Err(InfocomError::Memory(format!("Compressed memory extends beyond dynamic memory at ${:04x}", address)))
+                }
+                data[address] = data[address] ^ b;
+                address = address + 1;
+            }
+        }
+
+        if address > data.len() {
+            return Err(InfocomError::Memory(format!("Compressed memory extends beyond dynamic memory at ${:04x}", address)))
+        }
+
+        self.reload_dynamic(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_util::{blorb, StoryBuilder, STATIC};
+
+    #[test]
+    fn reload_dynamic_restores_a_snapshot() {
+        let mut mem = StoryBuilder::new(5).byte(0x700, 1).byte(0x900, 2).build();
+        let snapshot = mem.get_memory()[0..STATIC].to_vec();
+        mem.set_byte(0x700, 0xFF).unwrap();
+        mem.set_byte(0x11, FLAGS2_TRANSCRIPTING as u8).unwrap();
+
+        mem.reload_dynamic(&snapshot).unwrap();
+        assert_eq!(mem.get_byte(0x700).unwrap(), 1);
+        assert_eq!(mem.get_byte(0x900).unwrap(), 2);
+        // Transcripting survives the reload
+        assert_eq!(mem.get_byte(0x11).unwrap(), FLAGS2_TRANSCRIPTING as u8);
+
+        // Snapshots must cover exactly the dynamic memory
+        assert!(mem.reload_dynamic(&snapshot[1..]).is_err());
+        assert!(mem.reload_dynamic(&mem.get_memory()).is_err());
+    }
+
+    #[test]
+    fn version_predicates_split_at_v3_v4_and_v5() {
+        for version in 1..=8 {
+            let mem = StoryBuilder::new(version).build();
+            assert_eq!(mem.version_number(), version);
+            assert_eq!(mem.is_v3_or_earlier(), version <= 3, "V{}", version);
+            assert_eq!(mem.is_v4_or_later(), version >= 4, "V{}", version);
+            assert_eq!(mem.is_v5_or_later(), version >= 5, "V{}", version);
+        }
+    }
+
+    #[test]
+    fn invalid_redis_data_is_an_error() {
+        assert!(MemoryMap::from_redis_value(&Value::Data(b"{\"version\": ".to_vec())).is_err());
+        assert!(MemoryMap::from_redis_value(&Value::Data(b"not json".to_vec())).is_err());
+        assert!(MemoryMap::from_redis_value(&Value::Nil).is_err());
+
+        let mem = StoryBuilder::new(3).build();
+        let data = serde_json::to_vec(&mem).unwrap();
+        assert_eq!(MemoryMap::from_redis_value(&Value::Data(data)).unwrap().get_memory(), mem.get_memory());
+    }
+
+    #[test]
+    fn try_from_rejects_an_empty_image() {
+        assert!(MemoryMap::try_from(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn try_from_rejects_an_image_smaller_than_the_header() {
+        let mut data = vec![0; 16];
+        data[0] = 3;
+        assert!(MemoryMap::try_from(data).is_err());
+    }
+
+    #[test]
+    fn try_from_rejects_unsupported_versions() {
+        let mut data = vec![0; 0x100];
+        data[0] = 9;
+        match MemoryMap::try_from(data) {
+            Err(InfocomError::Version(v)) => assert_eq!(v.number(), 9),
+            _ => panic!("version 9 was accepted")
+        }
+    }
+
+    #[test]
+    fn try_from_rejects_a_static_mark_beyond_the_data() {
+        let mut data = vec![0; 0x100];
+        data[0] = 3;
+        data[0x0E] = 0x01;
+        data[0x0F] = 0x01;
+        assert!(MemoryMap::try_from(data).is_err());
+    }
+
+    #[test]
+    fn get_signed_word_is_twos_complement() {
+        let mut data = vec![0; 0x100];
+        data[0] = 3;
+        data[0xF] = 0x40;
+        data[0x80] = 0xFF;
+        data[0x81] = 0xF6;
+        data[0x82] = 0x7F;
+        data[0x83] = 0xFF;
+        let mem = MemoryMap::try_from(data).unwrap();
+        assert_eq!(mem.get_signed_word(0x80).unwrap(), -10);
+        assert_eq!(mem.get_signed_word(0x82).unwrap(), 0x7FFF);
+        assert!(mem.get_signed_word(0xFF).is_err());
+    }
+
+    #[test]
+    fn dynamic_diff_round_trips_through_apply_diff() {
+        let mut mem = StoryBuilder::new(5).build();
+        let mut saved = StoryBuilder::new(5).build();
+
+        // Changes separated by runs of 1, 256, 257 and 600 unchanged bytes
+        let changes = [(0x40, 0x01), (0x42, 0xFF), (0x143, 0x10), (0x245, 0x20), (0x49E, 0x30)];
+        for (a, v) in changes.iter() {
+            mem.set_byte(*a, *v).unwrap();
+        }
+
+        let diff = mem.dynamic_diff();
+        assert_eq!(&diff[0..3], &[0, 0x3F, 0x01]);
+        assert_eq!(&diff[3..6], &[0, 0, 0xFF]);
+        // 256 unchanged bytes fill a single run
+        assert_eq!(&diff[6..9], &[0, 0xFF, 0x10]);
+        // 257 need a full run and a run of 1
+        assert_eq!(&diff[9..14], &[0, 0xFF, 0, 0, 0x20]);
+        // Trailing unchanged bytes are omitted
+        assert_eq!(diff.last(), Some(&0x30));
+
+        saved.apply_diff(&diff).unwrap();
+        assert_eq!(saved.get_memory()[0..saved.static_mark], mem.get_memory()[0..mem.static_mark]);
+    }
+
+    #[test]
+    fn apply_diff_rejects_runs_beyond_dynamic_memory() {
+        let mut mem = StoryBuilder::new(5).build();
+        let mut diff = Vec::new();
+        for _ in 0..(mem.static_mark / 256) {
+            diff.extend_from_slice(&[0, 0xFF]);
+        }
+        assert!(mem.apply_diff(&diff).is_ok());
+        diff.push(1);
+        assert!(mem.apply_diff(&diff).is_err());
+        assert!(mem.apply_diff(&[0]).is_err());
+    }
+
+    #[test]
+    fn zblorb_data_loads_the_embedded_story() {
+        let story = StoryBuilder::new(5).data();
+        let data = blorb(&[(b"Pict", 1, 0), (b"Exec", 0, 1)], &[(b"PNG ", &[0; 5]), (b"ZCOD", &story)]);
+        let mem = MemoryMap::try_from(data.clone()).unwrap();
+        assert_eq!(mem.version_number(), 5);
+        assert_eq!(mem.get_memory(), story);
+
+        // Truncated files are errors rather than panics
+        for length in 0..data.len() {
+            assert!(MemoryMap::try_from(data[..length].to_vec()).is_err(), "{} bytes", length);
+        }
+
+        let no_story = blorb(&[(b"Pict", 1, 0)], &[(b"PNG ", &[0; 5])]);
+        assert!(MemoryMap::try_from(no_story).is_err());
+    }
 }
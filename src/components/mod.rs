@@ -2,7 +2,9 @@ use std::error;
 use std::fmt;
 use redis::RedisError;
 
-mod redis_connection;
+pub mod redis_connection;
+
+pub mod config;
 
 pub mod memory;
 pub mod session;
@@ -12,6 +14,12 @@ pub mod state;
 pub mod instruction;
 pub mod interface;
 pub mod dictionary;
+pub mod header;
+pub mod quetzal;
+pub mod blorb;
+pub mod story;
+#[cfg(test)]
+pub mod test_util;
 
 #[derive(Debug)]
 pub enum InfocomError {
@@ -22,7 +30,9 @@ pub enum InfocomError {
     API(String),
     Session(String),
     Version(memory::Version),
-    Redis(RedisError)
+    Redis(RedisError),
+    Unimplemented { opcode: String, address: usize },
+    OperandCount { opcode: String, address: usize, count: usize }
 }
 
 impl fmt::Display for InfocomError {
@@ -35,7 +45,9 @@ impl fmt::Display for InfocomError {
             InfocomError::Memory(ref e) => e.fmt(f),
             InfocomError::Text(ref e) => e.fmt(f),
             InfocomError::API(ref e) => e.fmt(f),
-            InfocomError::Session(ref e) => e.fmt(f)
+            InfocomError::Session(ref e) => e.fmt(f),
+            InfocomError::Unimplemented { ref opcode, ref address } => f.write_fmt(format_args!("Unimplemented instruction {} at ${:06x}", opcode, address)),
+            InfocomError::OperandCount { ref opcode, ref address, ref count } => f.write_fmt(format_args!("Instruction {} at ${:06x} has {} operands", opcode, address, count))
         }
     }
 }
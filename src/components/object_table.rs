@@ -2,6 +2,7 @@ use log::{debug, error, warn};
 use serde::{Deserialize, Serialize};
 
 use super::InfocomError;
+use super::header::Header;
 use super::memory::{MemoryMap, Version};
 use super::state::FrameStack;
 use super::text::Decoder;
@@ -22,32 +23,28 @@ impl Property {
             return Ok(None);
         }
 
-        let (number, size, skip) = match mem.version {
-            Version::V(1) | Version::V(2) | Version::V(3) => {
-                let size = (size_byte as u16 / 32) + 1;
-                let number = size_byte as usize & 0x1F;
-                (number, size, 1)
-            },
-            Version::V(4) | Version::V(5) | Version::V(6) | Version::V(7) | Version::V(8) => {
-                if size_byte & 0x80 == 0x80 {
-                    let size_byte_2 = mem.get_byte(prop_addr + 1)? as u16;
-                    let size:u16 = if size_byte_2 & 0x3F == 0 {
-                        64
-                    } else {
-                        size_byte_2 & 0x3F
-                    };
-                    let number = size_byte as usize & 0x3F;
-                    (number, size, 2)
+        let (number, size, skip) = if mem.is_v3_or_earlier() {
+            let size = (size_byte as u16 / 32) + 1;
+            let number = size_byte as usize & 0x1F;
+            (number, size, 1)
+        } else {
+            if size_byte & 0x80 == 0x80 {
+                let size_byte_2 = mem.get_byte(prop_addr + 1)? as u16;
+                let size:u16 = if size_byte_2 & 0x3F == 0 {
+                    64
                 } else {
-                    let size = match size_byte & 0x40 {
-                        0x40 => 2,
-                        _ => 1
-                    };
-                    let number = size_byte as usize & 0x3F;
-                    (number, size, 1)
-                }
-            },
-            _ => return Err(InfocomError::Version(mem.version))
+                    size_byte_2 & 0x3F
+                };
+                let number = size_byte as usize & 0x3F;
+                (number, size, 2)
+            } else {
+                let size = match size_byte & 0x40 {
+                    0x40 => 2,
+                    _ => 1
+                };
+                let number = size_byte as usize & 0x3F;
+                (number, size, 1)
+            }
         };
 
         let mut data:Vec<u8> = Vec::new();
@@ -171,81 +168,72 @@ impl PropertyTable {
 
 impl Object {
     fn load(mem: &MemoryMap, number: usize, address: usize) -> Result<Object, InfocomError> {
-        match mem.version {
-            Version::V(1) | Version::V(2) | Version::V(3) => {
-                let attr_1 = mem.get_word(address)?;
-                let attr_2 = mem.get_word(address + 2)?;
-                let attributes:u64 = (((attr_1 as u64) << 16) & 0xFFFF0000) | ((attr_2 as u64) & 0xFFFF);
-                let parent = mem.get_byte(address + 4)? as u16;
-                let sibling = mem.get_byte(address + 5)? as u16;
-                let child = mem.get_byte(address + 6)? as u16;
-                let prop_addr = mem.get_word(address + 7)? as usize;
-                let property_table = PropertyTable::load(mem, prop_addr)?;
-                Ok(Object{ number,
-                           address,
-                           attribute_count: 32,
-                           attributes, 
-                           parent,
-                           sibling,
-                           child,
-                           property_table})
-            },
-            _ => {
-                let attr_1 = mem.get_word(address)?;
-                let attr_2 = mem.get_word(address + 2)?;
-                let attr_3 = mem.get_word(address + 4)?;
-                let attributes:u64 = (((attr_1 as u64) << 32) & 0xFFFF00000000) | (((attr_2 as u64) << 16)& 0xFFFF0000) | (attr_3 as u64) & 0xFFFF;
-                let parent = mem.get_word(address + 6)? as u16;
-                let sibling = mem.get_word(address + 8)? as u16;
-                let child = mem.get_word(address + 10)? as u16;
-                let prop_addr = mem.get_word(address + 12)? as usize;
-                let property_table = PropertyTable::load(mem, prop_addr)?;
-                Ok(Object{ number,
-                           address,
-                           attribute_count: 48,
-                           attributes, 
-                           parent,
-                           sibling,
-                           child,
-                           property_table})
-            }
+        if mem.is_v3_or_earlier() {
+            let attr_1 = mem.get_word(address)?;
+            let attr_2 = mem.get_word(address + 2)?;
+            let attributes:u64 = (((attr_1 as u64) << 16) & 0xFFFF0000) | ((attr_2 as u64) & 0xFFFF);
+            let parent = mem.get_byte(address + 4)? as u16;
+            let sibling = mem.get_byte(address + 5)? as u16;
+            let child = mem.get_byte(address + 6)? as u16;
+            let prop_addr = mem.get_word(address + 7)? as usize;
+            let property_table = PropertyTable::load(mem, prop_addr)?;
+            Ok(Object{ number,
+                       address,
+                       attribute_count: 32,
+                       attributes, 
+                       parent,
+                       sibling,
+                       child,
+                       property_table})
+        } else {
+            let attr_1 = mem.get_word(address)?;
+            let attr_2 = mem.get_word(address + 2)?;
+            let attr_3 = mem.get_word(address + 4)?;
+            let attributes:u64 = (((attr_1 as u64) << 32) & 0xFFFF00000000) | (((attr_2 as u64) << 16)& 0xFFFF0000) | (attr_3 as u64) & 0xFFFF;
+            let parent = mem.get_word(address + 6)? as u16;
+            let sibling = mem.get_word(address + 8)? as u16;
+            let child = mem.get_word(address + 10)? as u16;
+            let prop_addr = mem.get_word(address + 12)? as usize;
+            let property_table = PropertyTable::load(mem, prop_addr)?;
+            Ok(Object{ number,
+                       address,
+                       attribute_count: 48,
+                       attributes, 
+                       parent,
+                       sibling,
+                       child,
+                       property_table})
         }
     }
 
     pub fn save_family(&self, state: &mut FrameStack) -> Result<(), InfocomError>
     {
-        match state.get_memory().version {
-            Version::V(1) | Version::V(2) | Version::V(3) => {
-                state.set_byte(self.address + 4, self.parent as u8)?;
-                state.set_byte(self.address + 5, self.sibling as u8)?;
-                state.set_byte(self.address + 6, self.child as u8)?;
-            },
-            _ => {
-                state.set_word(self.address + 6, self.parent)?;
-                state.set_word(self.address + 8, self.sibling)?;
-                state.set_word(self.address + 10, self.child)?;
-            }
+        if state.get_memory().is_v3_or_earlier() {
+            state.set_byte(self.address + 4, self.parent as u8)?;
+            state.set_byte(self.address + 5, self.sibling as u8)?;
+            state.set_byte(self.address + 6, self.child as u8)?;
+        } else {
+            state.set_word(self.address + 6, self.parent)?;
+            state.set_word(self.address + 8, self.sibling)?;
+            state.set_word(self.address + 10, self.child)?;
         }
 
         Ok(())
     }
 
     pub fn save_attributes(&self, state: &mut FrameStack) -> Result<(), InfocomError> {
-        match state.get_memory().version {
-            Version::V(1) | Version::V(2) | Version::V(3) => {
-                let attr_1:u16 = ((self.attributes >> 16) & 0xFFFF) as u16;
-                let attr_2:u16 = (self.attributes & 0xFFFF) as u16;
-                state.set_word(self.address, attr_1)?;
-                state.set_word(self.address + 2, attr_2)?;
-            },
-            _ => {
-                let attr_1 = ((self.attributes >> 32) & 0xFFFF) as u16;
-                let attr_2 = ((self.attributes >> 16) & 0xFFFF) as u16;
-                let attr_3 = (self.attributes & 0xFFFF) as u16;
-                state.set_word(self.address, attr_1)?;
-                state.set_word(self.address + 2, attr_2)?;
-                state.set_word(self.address + 4, attr_3)?;
-            }
+        if state.get_memory().is_v3_or_earlier() {
+            let attr_1:u16 = ((self.attributes >> 16) & 0xFFFF) as u16;
+            let attr_2:u16 = (self.attributes & 0xFFFF) as u16;
+            state.set_word(self.address, attr_1)?;
+            state.set_word(self.address + 2, attr_2)?;
+        } else {
+            let attr_1 = ((self.attributes >> 32) & 0xFFFF) as u16;
+            let attr_2 = ((self.attributes >> 16) & 0xFFFF) as u16;
+            let attr_3 = (self.attributes & 0xFFFF) as u16;
+            state.set_word(self.address, attr_1)?;
+            state.set_word(self.address + 2, attr_2)?;
+            state.set_word(self.address + 4, attr_3)?;
         }
 
         Ok(())
@@ -272,15 +260,20 @@ impl Object {
     }
 
     pub fn has_attribute(&self, attribute: usize) -> Result<bool, InfocomError> {
-        if attribute <= self.attribute_count {
+        if attribute < self.attribute_count {
             Ok(self.attributes >> (self.attribute_count - attribute - 1) & 0x1 == 0x1)
         } else {
             Err(InfocomError::Memory(format!("Invalid attribute ${:02x}", attribute)))
         }
     }
 
+    /// The numbers of the attributes that are set, in ascending order
+    pub fn attributes_as_vec(&self) -> Vec<usize> {
+        (0..self.attribute_count).filter(|a| self.attributes >> (self.attribute_count - a - 1) & 0x1 == 0x1).collect()
+    }
+
     pub fn set_attribute(&mut self, attribute: usize) -> Result<u64, InfocomError> {
-        if attribute <= self.attribute_count {
+        if attribute < self.attribute_count {
             let mask:u64 = 1 << (self.attribute_count - attribute - 1);
             let attributes = self.attributes | mask;
             self.attributes = attributes;
@@ -292,7 +285,7 @@ impl Object {
     }
 
     pub fn clear_attribute(&mut self, attribute: usize) -> Result<u64, InfocomError> {
-        if attribute <= self.attribute_count {
+        if attribute < self.attribute_count {
             let mut mask:u64 = 0;
             for _ in 0..(self.attribute_count / 8) {
                 mask = mask << 8 | 0xFF;
@@ -303,7 +296,7 @@ impl Object {
             self.attributes = attributes;
             Ok(attributes)
         } else {
-            warn!("Attempt to set an invalid attribute: ${:02x}", attribute);
+            warn!("Attempt to clear an invalid attribute: ${:02x}", attribute);
             Ok(self.attributes)
         }
     }
@@ -319,12 +312,9 @@ impl Object {
     fn next_property_number(&self, property: usize) -> Result<u8, InfocomError> {
         let mut i = self.property_table.properties.iter();
 
+        // Property 0 starts the walk, and an object with no properties has no first property
         if property == 0 {
-            if let Some(r) = i.next() {
-                return Ok(r.number as u8);
-            } else {
-                return Ok(0);
-            }
+            return Ok(i.next().map_or(0, |p| p.number as u8))
         }
         while let Some(p) = i.next() {
             if p.number == property {
@@ -341,24 +331,28 @@ impl Object {
 }
 
 impl ObjectTable {
+    /// Object and property layouts are only checked against V3 or earlier here and in
+    /// `Object::load`/`Property::load`, so versions outside 1-8 are rejected up front.
     pub fn new(mem: &MemoryMap) -> Result<ObjectTable, InfocomError> {
-        let address = mem.get_word(0x0a)? as usize;
+        let version = mem.version_number();
+        if version < 1 || version > 8 {
+            error!("Object table for unsupported version: {}", version);
+            return Err(InfocomError::Version(Version::V(version)))
+        }
+
+        let address = Header::new(mem)?.object_table();
         let mut default_properties:Vec<u16> = Vec::new();
 
-        match mem.version {
-            Version::V(1) | Version::V(2) | Version::V(3) => {
-                for i in 0..31 {
-                    let v = mem.get_word(address + (2 * i))?;
-                    default_properties.push(v);
-                }
-            },
-            Version::V(4) | Version::V(5) | Version::V(6) | Version::V(7) | Version::V(8) => {
-                for i in 0..63 {
-                    let v = mem.get_word(address + (2 * i))?;
-                    default_properties.push(v);
-                }
-            },
-            _ => return Err(InfocomError::Version(mem.version))
+        if mem.is_v3_or_earlier() {
+            for i in 0..31 {
+                let v = mem.get_word(address + (2 * i))?;
+                default_properties.push(v);
+            }
+        } else {
+            for i in 0..63 {
+                let v = mem.get_word(address + (2 * i))?;
+                default_properties.push(v);
+            }
         }
 
         debug!("${:04x}, default properties: {:?}", address, default_properties);
@@ -367,12 +361,20 @@ impl ObjectTable {
     }
 
     pub fn get_object(&self, memory: &MemoryMap, object_number: usize) -> Result<Object, InfocomError> {
-        let object_address = match memory.version {
-            Version::V(1) | Version::V(2) | Version::V(3) => {
-                self.address + 62 + ((object_number - 1) * 9)
-            },
-            _ => self.address + 126 + ((object_number - 1) * 14)
+        // Objects are numbered from 1 up to 255 in V1-3 and 65535 in V4+
+        let (max_object, entry_size, table_start) = if memory.is_v3_or_earlier() {
+            (255, 9, 62)
+        } else {
+            (65535, 14, 126)
         };
+        if object_number == 0 || object_number > max_object {
+            return Err(InfocomError::Memory(format!("Invalid object number: {}", object_number)))
+        }
+
+        let object_address = self.address + table_start + ((object_number - 1) * entry_size);
+        if object_address + entry_size > memory.len() {
+            return Err(InfocomError::Memory(format!("Object {} at ${:06x} is beyond the end of memory", object_number, object_address)))
+        }
 
         let o = Object::load(memory, object_number, object_address)?;
         Ok(o)
@@ -467,8 +469,21 @@ impl ObjectTable {
         }
     }
 
+    /// The value of a 1 or 2 byte property, falling back to the default property table
+    /// when the object doesn't have the property.  Longer properties are an error.
     pub fn get_property_value(&self, memory: &MemoryMap, object_number: usize, property_number: usize) -> Result<u16, InfocomError> {
-        match self.get_object(memory, object_number)?.get_property(property_number) {
+        if property_number == 0 {
+            return Err(InfocomError::Memory(format!("Invalid property number: $00")))
+        }
+
+        // Object 0 behaves as an object with no properties
+        let object = if object_number == 0 {
+            None
+        } else {
+            Some(self.get_object(memory, object_number)?)
+        };
+
+        match object.as_ref().and_then(|o| o.get_property(property_number)) {
             Some(p) => if p.size == 1 {
                 Ok(p.data[0] as u16)
             } else if p.size == 2 {
@@ -503,11 +518,38 @@ impl ObjectTable {
         } 
     }
 
+    /// Overwrite all of a property's data, which may be longer than the 2 bytes `put_prop` allows
+    pub fn write_property_bytes(&mut self, state: &mut FrameStack, object_number: usize, property_number: usize, data: &[u8]) -> Result<Object, InfocomError> {
+        let o = self.get_object(state.get_memory(), object_number)?;
+        match o.get_property(property_number) {
+            Some(p) => {
+                if p.size as usize != data.len() {
+                    return Err(InfocomError::Memory(format!("Attempt to write {} bytes to property ${:02x} on object ${:04x} with length ${:02x}", data.len(), property_number, object_number, p.size)))
+                }
+
+                for (i, b) in data.iter().enumerate() {
+                    state.set_byte(p.data_address + i, *b)?;
+                }
+                self.get_object(state.get_memory(), object_number)
+            },
+            None => Err(InfocomError::Memory(format!("Set property ${:02x} on object ${:04x} that doesn't have the specified property", property_number, object_number)))
+        }
+    }
+
+    /// The number of the property following `property_number` on the object, the first
+    /// property if `property_number` is 0, or 0 after the last property.  It is an error
+    /// to start from a property the object does not have.
     pub fn get_next_property(&self, memory: &MemoryMap, object_number: usize, property_number: usize) -> Result<u8, InfocomError> {
+        if object_number == 0 {
+            return Ok(0)
+        }
         self.get_object(memory, object_number)?.next_property_number(property_number)
     }
 
     pub fn get_property_address(&self, memory: &MemoryMap, object_number: usize, property_number: usize) -> Result<usize, InfocomError> {
+        if object_number == 0 {
+            return Ok(0)
+        }
         match self.get_object(memory, object_number)?.get_property(property_number) {
             Some(p) => Ok(p.data_address),
             None => Ok(0)
@@ -515,27 +557,212 @@ impl ObjectTable {
     }
 
     pub fn get_property_len(&self, memory: &MemoryMap, property_address: usize) -> Result<usize, InfocomError> {
+        // get_prop_len 0 must return 0 so the result of get_prop_addr on a missing property is safe
+        if property_address == 0 {
+            return Ok(0)
+        }
+
         let b = memory.get_byte(property_address - 1)?;
-        match memory.version {
-            Version::V(1) | Version::V(2) | Version::V(3) => {
-                Ok(((b as usize / 32) & 0x7) + 1)
-            },
-            _ => {
-                if b & 0x80 == 0x80 {
-                    let l = b as usize & 0x3F;
-                    if l == 0 {
-                        Ok(64)
-                    } else {
-                        Ok(l)
-                    }
+        if memory.is_v3_or_earlier() {
+            Ok(((b as usize / 32) & 0x7) + 1)
+        } else {
+            if b & 0x80 == 0x80 {
+                let l = b as usize & 0x3F;
+                if l == 0 {
+                    Ok(64)
                 } else {
-                    if b & 0x40 == 0x40 {
-                        Ok(2)
-                    } else {
-                        Ok(1)
-                    }
+                    Ok(l)
+                }
+            } else {
+                if b & 0x40 == 0x40 {
+                    Ok(2)
+                } else {
+                    Ok(1)
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_util::{StoryBuilder, OBJECTS};
+
+    #[test]
+    fn new_rejects_an_unversioned_memory_map() {
+        match ObjectTable::new(&MemoryMap::default()) {
+            Err(InfocomError::Version(v)) => assert_eq!(v.number(), 0),
+            _ => panic!("expected a version error")
+        }
+    }
+
+    #[test]
+    fn objects_load_from_a_v8_story() {
+        let mem = StoryBuilder::new(8)
+            .object(1, &[0, 47], (0, 0, 2), "box", &[(20, &[0x12, 0x34]), (3, &[1, 2, 3, 4])])
+            .object(2, &[], (1, 0, 0), "", &[])
+            .default_property(5, 0xBEEF)
+            .build();
+        let table = ObjectTable::new(&mem).unwrap();
+
+        let o = table.get_object(&mem, 1).unwrap();
+        assert_eq!(o.get_short_name(), "box");
+        assert_eq!((o.get_parent(), o.get_sibling(), o.get_child()), (0, 0, 2));
+        assert_eq!(o.attributes_as_vec(), vec![0, 47]);
+        assert_eq!(table.get_property_value(&mem, 1, 20).unwrap(), 0x1234);
+        assert_eq!(table.read_property_data(&mem, 1, 3).unwrap(), vec![1, 2, 3, 4]);
+        assert_eq!(table.get_property_value(&mem, 1, 5).unwrap(), 0xBEEF);
+        assert_eq!(table.get_object(&mem, 2).unwrap().get_parent(), 1);
+    }
+
+    #[test]
+    fn attributes_are_bounded_by_the_version() {
+        for (version, last) in &[(3, 31), (4, 47), (5, 47)] {
+            let mut mem = StoryBuilder::new(*version).object(1, &[*last], (0, 0, 0), "box", &[]).build();
+            let mut table = ObjectTable::new(&mem).unwrap();
+            assert!(table.has_attribute(&mem, 1, *last).unwrap());
+            assert!(table.has_attribute(&mem, 1, last + 1).is_err(), "V{} attribute {}", version, last + 1);
+
+            // Setting or clearing an attribute past the last is ignored
+            let mut o = table.get_object(&mem, 1).unwrap();
+            let attributes = o.set_attribute(*last).unwrap();
+            assert_eq!(o.set_attribute(last + 1).unwrap(), attributes);
+            assert_eq!(o.clear_attribute(last + 1).unwrap(), attributes);
+            assert_eq!(o.attributes_as_vec(), vec![*last]);
+
+            let mut state = FrameStack::new(&mut mem).unwrap();
+            table.clear_attribute(&mut state, 1, *last).unwrap();
+            table.set_attribute(&mut state, 1, last - 1).unwrap();
+            assert_eq!(table.get_object(state.get_memory(), 1).unwrap().attributes_as_vec(), vec![last - 1]);
+        }
+    }
+
+    #[test]
+    fn v4_attributes_are_saved_as_three_words() {
+        let mut mem = StoryBuilder::new(5).object(1, &[], (0, 0, 0), "box", &[]).build();
+        let mut table = ObjectTable::new(&mem).unwrap();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        // Object 1 follows the 63 word default property table
+        let entry = OBJECTS + 126;
+
+        table.set_attribute(&mut state, 1, 47).unwrap();
+        table.set_attribute(&mut state, 1, 0).unwrap();
+        table.set_attribute(&mut state, 1, 17).unwrap();
+        let attributes: Vec<u8> = (0..6).map(|i| state.get_memory().get_byte(entry + i).unwrap()).collect();
+        assert_eq!(attributes, vec![0x80, 0x00, 0x40, 0x00, 0x00, 0x01]);
+        assert!(table.has_attribute(state.get_memory(), 1, 47).unwrap());
+
+        table.clear_attribute(&mut state, 1, 47).unwrap();
+        assert_eq!(state.get_memory().get_byte(entry + 5).unwrap(), 0);
+        assert!(!table.has_attribute(state.get_memory(), 1, 47).unwrap());
+        assert_eq!(table.get_object(state.get_memory(), 1).unwrap().attributes_as_vec(), vec![0, 17]);
+    }
+
+    #[test]
+    fn get_object_rejects_numbers_outside_the_table() {
+        let mem = StoryBuilder::new(3).object(1, &[], (0, 0, 0), "box", &[]).build();
+        let table = ObjectTable::new(&mem).unwrap();
+        assert_eq!(table.get_object(&mem, 1).unwrap().get_short_name(), "box");
+        for number in &[0, 256, usize::MAX] {
+            assert!(table.get_object(&mem, *number).is_err(), "object {}", number);
+        }
+
+        // Object 65535 in a V5 story would lie far past the end of memory
+        let mem = StoryBuilder::new(5).object(1, &[], (0, 0, 0), "box", &[]).build();
+        let table = ObjectTable::new(&mem).unwrap();
+        assert!(table.get_object(&mem, 1).is_ok());
+        match table.get_object(&mem, 65535) {
+            Err(InfocomError::Memory(m)) => assert!(m.contains("beyond the end of memory"), "{}", m),
+            r => panic!("expected a memory error, got {:?}", r.map(|o| o.get_short_name()))
+        }
+        assert!(table.get_object(&mem, 65536).is_err());
+    }
+
+    #[test]
+    fn get_property_value_reads_short_properties_and_defaults() {
+        let mem = StoryBuilder::new(3)
+            .object(1, &[], (0, 0, 0), "box", &[(10, &[0x42]), (9, &[0x81, 0x23]), (8, &[1, 2, 3, 4])])
+            .default_property(7, 0xBEEF)
+            .build();
+        let table = ObjectTable::new(&mem).unwrap();
+        assert_eq!(table.get_property_value(&mem, 1, 10).unwrap(), 0x42);
+        assert_eq!(table.get_property_value(&mem, 1, 9).unwrap(), 0x8123);
+        assert_eq!(table.get_property_value(&mem, 1, 7).unwrap(), 0xBEEF);
+        assert!(table.get_property_value(&mem, 1, 8).is_err());
+        // Property 0 and properties past the default table don't exist
+        assert!(table.get_property_value(&mem, 1, 0).is_err());
+        assert!(table.get_property_value(&mem, 1, 32).is_err());
+    }
+
+    #[test]
+    fn object_0_has_no_properties() {
+        let mem = StoryBuilder::new(5)
+            .object(1, &[], (0, 0, 0), "box", &[(7, &[0x12, 0x34])])
+            .default_property(7, 0xBEEF)
+            .build();
+        let table = ObjectTable::new(&mem).unwrap();
+        assert_eq!(table.get_property_value(&mem, 0, 7).unwrap(), 0xBEEF);
+        assert_eq!(table.get_property_address(&mem, 0, 7).unwrap(), 0);
+        assert_eq!(table.get_next_property(&mem, 0, 0).unwrap(), 0);
+        assert_eq!(table.get_property_len(&mem, 0).unwrap(), 0);
+        assert!(table.get_property_value(&mem, 0, 0).is_err());
+    }
+
+    #[test]
+    fn get_property_address_feeds_get_property_len() {
+        for version in &[3, 5] {
+            let mem = StoryBuilder::new(*version)
+                .object(1, &[], (0, 0, 0), "box", &[(9, &[1, 2, 3, 4, 5, 6]), (7, &[0x12, 0x34]), (4, &[1])])
+                .build();
+            let table = ObjectTable::new(&mem).unwrap();
+            for (property, length) in &[(9, 6), (7, 2), (4, 1)] {
+                let address = table.get_property_address(&mem, 1, *property).unwrap();
+                assert_ne!(address, 0);
+                assert_eq!(table.get_property_len(&mem, address).unwrap(), *length, "V{} property {}", version, property);
+            }
+
+            // A missing property has address 0, which has length 0
+            assert_eq!(table.get_property_address(&mem, 1, 8).unwrap(), 0);
+            assert_eq!(table.get_property_len(&mem, 0).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn long_properties_are_written_whole() {
+        let mut mem = StoryBuilder::new(5)
+            .object(1, &[], (0, 0, 0), "box", &[(9, &[0; 6]), (7, &[0x12, 0x34])])
+            .build();
+        let mut table = ObjectTable::new(&mem).unwrap();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+
+        table.write_property_bytes(&mut state, 1, 9, &[1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(table.read_property_data(state.get_memory(), 1, 9).unwrap(), vec![1, 2, 3, 4, 5, 6]);
+        assert!(table.write_property_bytes(&mut state, 1, 9, &[1, 2]).is_err());
+
+        // put_prop only writes 1 and 2 byte properties
+        assert!(table.put_property_data(&mut state, 1, 9, 0xFFFF).is_err());
+        table.put_property_data(&mut state, 1, 7, 0xBEEF).unwrap();
+        assert_eq!(table.get_property_value(state.get_memory(), 1, 7).unwrap(), 0xBEEF);
+        assert_eq!(table.read_property_data(state.get_memory(), 1, 9).unwrap(), vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn get_next_property_walks_the_property_list() {
+        for version in &[3, 5] {
+            let mem = StoryBuilder::new(*version)
+                .object(1, &[], (0, 0, 0), "box", &[(12, &[1]), (7, &[1, 2]), (3, &[1, 2, 3, 4])])
+                .object(2, &[], (0, 0, 0), "bag", &[])
+                .build();
+            let table = ObjectTable::new(&mem).unwrap();
+            assert_eq!(table.get_next_property(&mem, 1, 0).unwrap(), 12);
+            assert_eq!(table.get_next_property(&mem, 1, 12).unwrap(), 7);
+            assert_eq!(table.get_next_property(&mem, 1, 7).unwrap(), 3);
+            assert_eq!(table.get_next_property(&mem, 1, 3).unwrap(), 0);
+            assert!(table.get_next_property(&mem, 1, 5).is_err(), "V{}", version);
+
+            // An object without properties has no first property
+            assert_eq!(table.get_next_property(&mem, 2, 0).unwrap(), 0);
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use log::{debug, warn};
+
+use super::InfocomError;
+use super::header::Header;
+use super::memory::MemoryMap;
+
+fn push_word(data: &mut Vec<u8>, value: u16) {
+    data.push((value >> 8) as u8);
+    data.push(value as u8);
+}
+
+fn push_long(data: &mut Vec<u8>, value: u32) {
+    push_word(data, (value >> 16) as u16);
+    push_word(data, value as u16);
+}
+
+fn push_address(data: &mut Vec<u8>, address: usize) {
+    data.push((address >> 16) as u8);
+    data.push((address >> 8) as u8);
+    data.push(address as u8);
+}
+
+fn read_word(data: &[u8], offset: usize) -> u16 {
+    ((data[offset] as u16) << 8) | data[offset + 1] as u16
+}
+
+fn read_long(data: &[u8], offset: usize) -> u32 {
+    ((read_word(data, offset) as u32) << 16) | read_word(data, offset + 2) as u32
+}
+
+fn read_address(data: &[u8], offset: usize) -> usize {
+    ((data[offset] as usize) << 16) | ((data[offset + 1] as usize) << 8) | data[offset + 2] as usize
+}
+
+fn push_chunk(data: &mut Vec<u8>, id: &[u8], chunk: &[u8]) {
+    data.extend_from_slice(id);
+    push_long(data, chunk.len() as u32);
+    data.extend_from_slice(chunk);
+    // Chunks are padded to an even length
+    if chunk.len() % 2 == 1 {
+        data.push(0);
+    }
+}
+
+/// Story identification and the saved program counter
+#[derive(Clone, Debug, PartialEq)]
+pub struct IFhd {
+    pub release: u16,
+    pub serial: Vec<u8>,
+    pub checksum: u16,
+    pub pc: usize,
+}
+
+impl IFhd {
+    fn new(mem: &MemoryMap, pc: usize) -> Result<IFhd, InfocomError> {
+        let header = Header::new(mem)?;
+        Ok(IFhd { release: header.release(),
+                  serial: header.serial().chars().map(|c| c as u8).collect(),
+                  checksum: header.checksum(),
+                  pc })
+    }
+
+    fn from_bytes(chunk: &[u8]) -> Result<IFhd, InfocomError> {
+        if chunk.len() < 13 {
+            return Err(InfocomError::API(format!("IFhd chunk is {} bytes, expected 13", chunk.len())))
+        }
+
+        Ok(IFhd { release: read_word(chunk, 0),
+                  serial: chunk[2..8].to_vec(),
+                  checksum: read_word(chunk, 8),
+                  pc: read_address(chunk, 10) })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        push_word(&mut data, self.release);
+        data.extend_from_slice(&self.serial);
+        push_word(&mut data, self.checksum);
+        push_address(&mut data, self.pc);
+        data
+    }
+
+    /// True if the save was made from the story loaded in `mem`
+    pub fn matches(&self, mem: &MemoryMap) -> Result<bool, InfocomError> {
+        let story = IFhd::new(mem, 0)?;
+        Ok(self.release == story.release && self.serial == story.serial && self.checksum == story.checksum)
+    }
+}
+
+/// Dynamic memory, either compressed against the original story data or as-is
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mem {
+    CMem(Vec<u8>),
+    UMem(Vec<u8>),
+}
+
+/// A single call frame from the Stks chunk
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackFrame {
+    pub return_pc: usize,
+    pub flags: u8,
+    pub result_variable: u8,
+    pub arguments: u8,
+    pub local_variables: Vec<u16>,
+    pub stack: Vec<u16>,
+}
+
+impl StackFrame {
+    /// The dummy outermost frame of a V1-5 story with an empty evaluation stack
+    pub fn dummy() -> StackFrame {
+        StackFrame { return_pc: 0, flags: 0, result_variable: 0, arguments: 0, local_variables: Vec::new(), stack: Vec::new() }
+    }
+
+    fn to_bytes(&self, data: &mut Vec<u8>) {
+        push_address(data, self.return_pc);
+        data.push(self.flags);
+        data.push(self.result_variable);
+        data.push(self.arguments);
+        push_word(data, self.stack.len() as u16);
+        for v in self.local_variables.iter().chain(self.stack.iter()) {
+            push_word(data, *v);
+        }
+    }
+}
+
+fn read_frames(chunk: &[u8]) -> Result<Vec<StackFrame>, InfocomError> {
+    let mut frames = Vec::new();
+    let mut offset = 0;
+    while offset < chunk.len() {
+        if offset + 8 > chunk.len() {
+            return Err(InfocomError::API(format!("Incomplete Stks frame at offset {}", offset)))
+        }
+
+        let flags = chunk[offset + 3];
+        let local_count = (flags & 0xF) as usize;
+        let stack_count = read_word(chunk, offset + 6) as usize;
+        let end = offset + 8 + (2 * (local_count + stack_count));
+        if end > chunk.len() {
+            return Err(InfocomError::API(format!("Incomplete Stks frame at offset {}", offset)))
+        }
+
+        let words:Vec<u16> = (0..local_count + stack_count).map(|i| read_word(chunk, offset + 8 + (2 * i))).collect();
+        frames.push(StackFrame { return_pc: read_address(chunk, offset),
+                                 flags,
+                                 result_variable: chunk[offset + 4],
+                                 arguments: chunk[offset + 5],
+                                 local_variables: words[0..local_count].to_vec(),
+                                 stack: words[local_count..].to_vec() });
+        offset = end;
+    }
+
+    Ok(frames)
+}
+
+/// The contents of a Quetzal (FORM IFZS) save file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quetzal {
+    pub ifhd: IFhd,
+    pub mem: Mem,
+    pub stks: Vec<StackFrame>,
+}
+
+impl Quetzal {
+    /// Save the dynamic memory in `mem` with the call stack `stks` and `pc`
+    pub fn new(mem: &MemoryMap, stks: Vec<StackFrame>, pc: usize) -> Result<Quetzal, InfocomError> {
+        Ok(Quetzal { ifhd: IFhd::new(mem, pc)?, mem: Mem::CMem(mem.dynamic_diff()), stks })
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Quetzal, InfocomError> {
+        if data.len() < 12 || &data[0..4] != b"FORM" || &data[8..12] != b"IFZS" {
+            return Err(InfocomError::API(format!("Not a Quetzal save file")))
+        }
+
+        let end = std::cmp::min(data.len(), 8 + read_long(data, 4) as usize);
+        let mut ifhd = None;
+        let mut mem = None;
+        let mut stks = None;
+        let mut offset = 12;
+        while offset + 8 <= end {
+            let id = &data[offset..offset + 4];
+            let length = read_long(data, offset + 4) as usize;
+            let start = offset + 8;
+            if start + length > end {
+                return Err(InfocomError::API(format!("Quetzal chunk {} extends beyond end of file", String::from_utf8_lossy(id))))
+            }
+            let chunk = &data[start..start + length];
+
+            match id {
+                b"IFhd" => ifhd = Some(IFhd::from_bytes(chunk)?),
+                b"CMem" => mem = Some(Mem::CMem(chunk.to_vec())),
+                b"UMem" => mem = Some(Mem::UMem(chunk.to_vec())),
+                b"Stks" => stks = Some(read_frames(chunk)?),
+                _ => debug!("Skipping Quetzal chunk {}", String::from_utf8_lossy(id))
+            }
+
+            offset = start + length + (length % 2);
+        }
+
+        match (ifhd, mem, stks) {
+            (Some(ifhd), Some(mem), Some(stks)) => Ok(Quetzal { ifhd, mem, stks }),
+            (None, _, _) => Err(InfocomError::API(format!("Save file has no IFhd chunk"))),
+            (_, None, _) => Err(InfocomError::API(format!("Save file has no memory chunk"))),
+            (_, _, None) => Err(InfocomError::API(format!("Save file has no Stks chunk")))
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut form = Vec::new();
+        form.extend_from_slice(b"IFZS");
+        push_chunk(&mut form, b"IFhd", &self.ifhd.to_bytes());
+        match &self.mem {
+            Mem::CMem(m) => push_chunk(&mut form, b"CMem", m),
+            Mem::UMem(m) => push_chunk(&mut form, b"UMem", m)
+        }
+        let mut stks = Vec::new();
+        for f in self.stks.iter() {
+            f.to_bytes(&mut stks);
+        }
+        push_chunk(&mut form, b"Stks", &stks);
+
+        let mut data = Vec::new();
+        data.extend_from_slice(b"FORM");
+        push_long(&mut data, form.len() as u32);
+        data.extend_from_slice(&form);
+        data
+    }
+
+    /// Restore dynamic memory, provided the save was made from the story loaded in `mem`
+    pub fn restore_memory(&self, mem: &mut MemoryMap) -> Result<(), InfocomError> {
+        if !self.ifhd.matches(mem)? {
+            return Err(InfocomError::API(format!("Save file is for a different story")))
+        }
+
+        match &self.mem {
+            Mem::CMem(m) => mem.apply_diff(m),
+            Mem::UMem(m) => mem.reload_dynamic(m)
+        }
+    }
+}
+
+/// Build a Quetzal save file for the current dynamic memory and `pc`.
+///
+/// The REST runner does not persist the call stack between requests, so the Stks chunk
+/// holds only the dummy outer frame.
+pub fn export(mem: &MemoryMap, pc: usize) -> Result<Vec<u8>, InfocomError> {
+    Ok(Quetzal::new(mem, vec![StackFrame::dummy()], pc)?.to_bytes())
+}
+
+/// Restore dynamic memory from a Quetzal save file, returning the saved `pc`.
+pub fn import(mem: &mut MemoryMap, data: &[u8]) -> Result<usize, InfocomError> {
+    let q = Quetzal::from_bytes(data)?;
+    if q.stks.len() > 1 {
+        warn!("Ignoring {} saved call frames, call stacks are not restored", q.stks.len() - 1);
+    }
+    q.restore_memory(mem)?;
+    Ok(q.ifhd.pc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_util::StoryBuilder;
+
+    /// A save with odd length IFhd and CMem chunks and a Stks chunk holding a dummy
+    /// frame, a routine frame with 2 locals, 2 arguments, a store to L00 and 1 word on
+    /// its stack, and another dummy frame
+    fn minimal_save() -> Vec<u8> {
+        let mut data = b"FORM\0\0\0\x4CIFZS".to_vec();
+        data.extend_from_slice(b"IFhd\0\0\0\x0D\0\x58201015\x12\x34\x00\x10\x20\0");
+        data.extend_from_slice(b"CMem\0\0\0\x03\0\x3F\x01\0");
+        data.extend_from_slice(b"Stks\0\0\0\x1E");
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&[0, 0x10, 0x08, 0x02, 0x01, 0x03, 0, 1, 0xAB, 0xCD, 0, 5, 0xFF, 0xFF]);
+        data.extend_from_slice(&[0; 8]);
+        data
+    }
+
+    #[test]
+    fn from_bytes_parses_every_chunk() {
+        let q = Quetzal::from_bytes(&minimal_save()).unwrap();
+        assert_eq!(q.ifhd, IFhd { release: 0x58, serial: b"201015".to_vec(), checksum: 0x1234, pc: 0x001020 });
+        assert_eq!(q.mem, Mem::CMem(vec![0, 0x3F, 0x01]));
+        assert_eq!(q.stks.len(), 3);
+        assert_eq!(q.stks[0], StackFrame::dummy());
+        assert_eq!(q.stks[1], StackFrame { return_pc: 0x1008, flags: 0x02, result_variable: 1, arguments: 3, local_variables: vec![0xABCD, 5], stack: vec![0xFFFF] });
+        assert_eq!(q.stks[2], StackFrame::dummy());
+    }
+
+    #[test]
+    fn to_bytes_reproduces_the_parsed_save() {
+        let data = minimal_save();
+        assert_eq!(Quetzal::from_bytes(&data).unwrap().to_bytes(), data);
+    }
+
+    #[test]
+    fn from_bytes_rejects_truncated_saves() {
+        let data = minimal_save();
+        assert!(Quetzal::from_bytes(&data[0..11]).is_err());
+        assert!(Quetzal::from_bytes(&data[0..40]).is_err());
+        // A Stks frame claiming more stack than the chunk holds
+        let mut short = data.clone();
+        short[69] = 9;
+        assert!(Quetzal::from_bytes(&short).is_err());
+    }
+
+    #[test]
+    fn compressed_memory_restores_into_the_original_story() {
+        let mut mem = StoryBuilder::new(5).build();
+        mem.set_byte(0x40, 0x11).unwrap();
+        mem.set_word(0x7FE, 0xBEEF).unwrap();
+        let data = Quetzal::new(&mem, vec![StackFrame::dummy()], 0x1234).unwrap().to_bytes();
+
+        let mut restored = StoryBuilder::new(5).build();
+        let q = Quetzal::from_bytes(&data).unwrap();
+        assert_eq!(q.ifhd.pc, 0x1234);
+        q.restore_memory(&mut restored).unwrap();
+        assert_eq!(restored.get_byte(0x40).unwrap(), 0x11);
+        assert_eq!(restored.get_word(0x7FE).unwrap(), 0xBEEF);
+        assert_eq!(restored.get_memory(), mem.get_memory());
+
+        // A save from a different release is refused
+        let mut other = StoryBuilder::new(5).word(0x02, 2).build();
+        assert!(q.restore_memory(&mut other).is_err());
+    }
+
+    #[test]
+    fn export_then_import_restores_dynamic_memory() {
+        let mut mem = StoryBuilder::new(3).build();
+        let original = mem.get_memory();
+        for a in (0x100..0x200).step_by(3) {
+            mem.set_byte(a, a as u8).unwrap();
+        }
+        let data = export(&mem, 0x1010).unwrap();
+
+        let mut restored = StoryBuilder::new(3).build();
+        assert_eq!(restored.get_memory(), original);
+        assert_eq!(import(&mut restored, &data).unwrap(), 0x1010);
+        assert_eq!(restored.get_memory()[0..0x800], mem.get_memory()[0..0x800]);
+
+        // The export holds only the dummy frame
+        assert_eq!(Quetzal::from_bytes(&data).unwrap().stks, vec![StackFrame::dummy()]);
+    }
+}
@@ -1,6 +1,9 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
 
 use log::{debug,error,warn};
+use super::config::Config;
 use redis::{Client, Connection, RedisError, RedisResult, Value};
 
 struct RedisTransaction {
@@ -8,17 +11,78 @@ struct RedisTransaction {
     expectations: Vec<Value>
 }
 
-pub struct RedisConnection {
+/// A shared pool of idle Redis connections.  Connections are checked out by
+/// `get` and returned to the pool when the `RedisConnection` is dropped.
+#[derive(Clone)]
+pub struct RedisPool {
     client: Client,
-    connection: Connection,
+    idle: Arc<Mutex<Vec<Connection>>>,
+    ttl: usize,
+}
+
+impl fmt::Debug for RedisPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("RedisPool {{ client: {:?}, ttl: {} }}", self.client, self.ttl))
+    }
+}
+
+impl RedisPool {
+    pub fn new(config: &Config) -> Result<RedisPool, RedisError> {
+        let client = Client::open(config.redis_url.as_str())?;
+        Ok(RedisPool { client, idle: Arc::new(Mutex::new(Vec::new())), ttl: config.session_ttl })
+    }
+
+    pub fn get(&self) -> Result<RedisConnection, RedisError> {
+        let connection = self.checkout()?;
+        Ok(RedisConnection { pool: self.clone(), connection: Some(connection), transactions: HashMap::new() })
+    }
+
+    fn checkout(&self) -> Result<Connection, RedisError> {
+        let idle = match self.idle.lock() {
+            Ok(mut idle) => idle.pop(),
+            Err(e) => {
+                error!("Redis pool lock poisoned: {}", e);
+                None
+            }
+        };
+
+        match idle {
+            Some(c) => Ok(c),
+            None => {
+                debug!("Opening new redis connection");
+                self.client.get_connection()
+            }
+        }
+    }
+
+    fn checkin(&self, connection: Connection) {
+        match self.idle.lock() {
+            Ok(mut idle) => idle.push(connection),
+            Err(e) => error!("Redis pool lock poisoned: {}", e)
+        }
+    }
+}
+
+pub struct RedisConnection {
+    pool: RedisPool,
+    connection: Option<Connection>,
     transactions: HashMap<String,RedisTransaction>,
 }
 
+impl Drop for RedisConnection {
+    fn drop(&mut self) {
+        if let Some(c) = self.connection.take() {
+            self.pool.checkin(c);
+        }
+    }
+}
+
 impl RedisConnection {
-    pub fn new(url: &str) -> Result<RedisConnection, RedisError> {
-        let client = Client::open(url)?;
-        let connection = client.get_connection()?;
-        Ok(RedisConnection { client, connection, transactions: HashMap::new() })
+    fn connection(&mut self) -> RedisResult<&mut Connection> {
+        match self.connection.as_mut() {
+            Some(c) => Ok(c),
+            None => Err(RedisError::from((redis::ErrorKind::ClientError, "No connection", String::from("Connection already returned to pool"))))
+        }
     }
 
     pub fn open_transaction(&mut self, key: &str) -> RedisResult<Value> {
@@ -26,7 +90,7 @@ impl RedisConnection {
             warn!("Transaction already opened for {}", key);
             return Ok(Value::Okay)
         } else {
-            let con = self.client.get_connection()?;
+            let con = self.pool.checkout()?;
             self.transactions.insert(String::from(key), RedisTransaction { connection: con, expectations: Vec::new() });
             let txn = self.transactions.get_mut(key).unwrap();
             redis::cmd("WATCH").arg(key).query(&mut txn.connection)?;
@@ -47,6 +111,8 @@ impl RedisConnection {
                                 }
                             }
 
+                            // EXEC clears the WATCH, so the connection can be reused
+                            self.pool.checkin(txn.connection);
                             Ok(v)
                         }
                         _ => Err(RedisError::from((redis::ErrorKind::ClientError, "Transaction failure", format!("Expected Value::Bulk, got {:?}", v))))
@@ -66,7 +132,7 @@ impl RedisConnection {
     where 
         T: redis::FromRedisValue 
     {
-      redis::cmd("GET").arg(key).query(&mut self.connection)
+      redis::cmd("GET").arg(key).query(self.connection()?)
     }
 
     pub fn set<T>(&mut self, txn_key: &str, key: &str, value: T) -> RedisResult<Value> 
@@ -77,7 +143,7 @@ impl RedisConnection {
             txn.expectations.push(Value::Okay);
             redis::cmd("SET")
                 .arg(key).arg(value)
-                .arg("EX").arg(3600)
+                .arg("EX").arg(self.pool.ttl)
                 .query(&mut txn.connection)
         } else {
             Err(RedisError::from((redis::ErrorKind::ClientError, "No transcation", format!("No open transaction for key {}", txn_key))))
@@ -92,7 +158,7 @@ impl RedisConnection {
             txn.expectations.push(Value::Okay);
             redis::cmd("SET")
                 .arg(key).arg(value)
-                .arg("EX").arg(3600)
+                .arg("EX").arg(self.pool.ttl)
                 .arg("NX")
                 .query(&mut txn.connection)
         } else {
@@ -108,7 +174,7 @@ impl RedisConnection {
             txn.expectations.push(Value::Okay);
             redis::cmd("SET")
                 .arg(key).arg(value)
-                .arg("EX").arg(3600)
+                .arg("EX").arg(self.pool.ttl)
                 .arg("XX")
                 .query(&mut txn.connection)
         } else {
@@ -118,7 +184,48 @@ impl RedisConnection {
 
     pub fn touch(&mut self, key: &str) -> RedisResult<Value> {
         redis::cmd("EXPIRE")
-            .arg(key).arg(3600)
-            .query(&mut self.connection)
+            .arg(key).arg(self.pool.ttl)
+            .query(self.connection()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn idle_connections(pool: &RedisPool) -> usize {
+        pool.idle.lock().unwrap().len()
+    }
+
+    #[test]
+    #[ignore = "needs a redis server"]
+    fn connections_are_returned_to_the_pool() {
+        let pool = RedisPool::new(&Config::from_env()).unwrap();
+        drop(pool.get().unwrap());
+
+        // The first connection is reused for every later load
+        assert_eq!(idle_connections(&pool), 1);
+        for _ in 0..100 {
+            let mut connection = pool.get().unwrap();
+            let _: Option<String> = connection.get("no such key").unwrap();
+            assert_eq!(idle_connections(&pool), 0);
+        }
+        assert_eq!(idle_connections(&pool), 1);
+    }
+
+    #[test]
+    fn pools_use_the_configured_url_and_ttl() {
+        let config = Config { redis_url: String::from("redis://127.0.0.1:6390/2"), session_ttl: 60, ..Config::default() };
+        let pool = RedisPool::new(&config).unwrap();
+        let description = format!("{:?}", pool);
+        assert!(description.contains("6390"), "{}", description);
+        assert!(description.contains("db: 2"), "{}", description);
+        assert_eq!(pool.ttl, 60);
+
+        // Nothing listens there, so connecting fails instead of falling back to the default
+        assert!(pool.get().is_err());
+
+        let config = Config { redis_url: String::from("not a url"), ..Config::default() };
+        assert!(RedisPool::new(&config).is_err());
     }
 }
@@ -7,19 +7,29 @@ use uuid::Uuid;
 use redis::{FromRedisValue, RedisResult, ToRedisArgs, Value};
 
 use super::memory;
-use super::redis_connection::RedisConnection;
+use super::redis_connection::RedisPool;
+use super::state::Frame;
 use super::InfocomError;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Session {
     pub id: String,
-    stories: HashMap<String, String>
+    stories: HashMap<String, String>,
+    /// The call stack left by the last run of each story
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    frames: HashMap<String, Vec<Frame>>
 }
 
 impl FromRedisValue for Session {
     fn from_redis_value(v: &Value) -> RedisResult<Session> {
         match *v {
-            Value::Data(ref bytes) => Ok(serde_json::from_str(&String::from_utf8(bytes.to_vec()).unwrap()).unwrap()),
+            Value::Data(ref bytes) => match serde_json::from_slice(bytes) {
+                Ok(session) => Ok(session),
+                Err(e) => {
+                    error!("Unable to deserialize Session: {}", e);
+                    Err(redis::RedisError::from((redis::ErrorKind::TypeError, "Invalid Session data", format!("{}", e))))
+                }
+            },
             _ => Err(redis::RedisError::from((redis::ErrorKind::TypeError, "Response was of incompatible type", format!("{:?} (response was {:?})", "response not Session compatible", v))))       
         }
     }
@@ -45,37 +55,41 @@ impl ToRedisArgs for &&mut Session {
     }
 }
 
-impl TryFrom<&str> for Session {
+impl TryFrom<(&RedisPool, &str)> for Session {
     type Error = InfocomError;
 
-    fn try_from(id: &str) -> Result<Session, InfocomError> {
-        let mut con = RedisConnection::new("redis://localhost")?;
-        let session:Session = con.get(id)?;
-        con.touch(id)?;
-        Ok(session)
+    fn try_from((pool, id): (&RedisPool, &str)) -> Result<Session, InfocomError> {
+        let mut con = pool.get()?;
+        match con.get::<Option<Session>>(id)? {
+            Some(session) => {
+                con.touch(id)?;
+                Ok(session)
+            },
+            None => Err(InfocomError::Session(format!("No session '{}'", id)))
+        }
     }
 }
 
 impl Session {
-    pub fn new() -> Result<Session, InfocomError> {
+    pub fn new(pool: &RedisPool) -> Result<Session, InfocomError> {
         let id = Uuid::new_v4().to_string();
         let stories = HashMap::new();
-        let session = Session { id: String::from(&id), stories };
-        let mut con = RedisConnection::new("redis://localhost")?;
+        let session = Session { id: String::from(&id), stories, frames: HashMap::new() };
+        let mut con = pool.get()?;
         con.open_transaction(&id)?;
         con.set_new(&id, &id, &session)?;
         con.commit_transaction(&id)?;
         Ok(session)
     }
 
-    pub fn add_story(&mut self, name: String, mem: memory::MemoryMap) -> Result<(), InfocomError> {
+    pub fn add_story(&mut self, pool: &RedisPool, name: String, mem: memory::MemoryMap) -> Result<(), InfocomError> {
         if self.stories.contains_key(&name) {
             error!("Story '{}' already exists.", name);
-            Err(InfocomError::Session(format!("Story '{}' already exists.", name)))
+            Err(InfocomError::API(format!("Story '{}' already exists.", name)))
         } else {
             let id = Uuid::new_v4().to_string();
             self.stories.insert(name, String::from(&id));
-            let mut con = RedisConnection::new("redis://localhost")?;
+            let mut con = pool.get()?;
             con.open_transaction(&self.id)?;
             con.set_new(&self.id, &id, &mem)?;
             con.set_replace(&self.id, &self.id, &self)?;
@@ -84,17 +98,75 @@ impl Session {
         }
     }
 
-    pub fn load(&mut self, name: &str) -> Result<memory::MemoryMap, InfocomError> {
-        let id = self.stories.get(name).unwrap();
-        memory::MemoryMap::try_from(id)
+    fn story_id(&self, name: &str) -> Result<&String, InfocomError> {
+        match self.stories.get(name) {
+            Some(id) => Ok(id),
+            None => Err(InfocomError::Session(format!("No story named '{}'", name)))
+        }
+    }
+
+    pub fn load(&mut self, pool: &RedisPool, name: &str) -> Result<memory::MemoryMap, InfocomError> {
+        let id = self.story_id(name)?;
+        memory::MemoryMap::try_from((pool, id))
     }
 
-    pub fn save(&mut self, name: &str, mem: memory::MemoryMap) -> Result<(), InfocomError> {
-        let id = self.stories.get(name).unwrap();
-        let mut con = RedisConnection::new("redis://localhost")?;
+    pub fn save(&mut self, pool: &RedisPool, name: &str, mem: memory::MemoryMap) -> Result<(), InfocomError> {
+        let id = self.story_id(name)?;
+        let mut con = pool.get()?;
         con.open_transaction(&id)?;
         con.set_replace(&id, &id, &mem)?;
         con.commit_transaction(&id)?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// The call stack persisted by `save_state`, outermost frame first
+    pub fn state(&self, name: &str) -> Option<&Vec<Frame>> {
+        self.frames.get(name)
+    }
+
+    /// Save memory along with the call stack, or discard the call stack when `frames` is None
+    pub fn save_state(&mut self, pool: &RedisPool, name: &str, mem: memory::MemoryMap, frames: Option<Vec<Frame>>) -> Result<(), InfocomError> {
+        let id = String::from(self.story_id(name)?);
+        match frames {
+            Some(f) => self.frames.insert(String::from(name), f),
+            None => self.frames.remove(name)
+        };
+        let mut con = pool.get()?;
+        con.open_transaction(&self.id)?;
+        con.set_replace(&self.id, &id, &mem)?;
+        con.set_replace(&self.id, &self.id, &self)?;
+        con.commit_transaction(&self.id)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::config::Config;
+    use super::super::test_util::StoryBuilder;
+
+    #[test]
+    fn invalid_redis_data_is_an_error() {
+        assert!(Session::from_redis_value(&Value::Data(b"{\"id\": ".to_vec())).is_err());
+        assert!(Session::from_redis_value(&Value::Data(b"[]".to_vec())).is_err());
+        assert!(Session::from_redis_value(&Value::Nil).is_err());
+
+        let session = Session::from_redis_value(&Value::Data(b"{\"id\": \"1\", \"stories\": {}}".to_vec())).unwrap();
+        assert_eq!(session.id, "1");
+        assert!(session.state("zork").is_none());
+    }
+
+    #[test]
+    fn missing_story_names_are_an_error() {
+        // The name is checked before redis is used, so the pool is never connected to
+        let pool = RedisPool::new(&Config::default()).unwrap();
+        let mut session = Session::from_redis_value(&Value::Data(b"{\"id\": \"1\", \"stories\": {}}".to_vec())).unwrap();
+        match session.load(&pool, "zork") {
+            Err(InfocomError::Session(message)) => assert_eq!(message, "No story named 'zork'"),
+            _ => panic!("expected a session error")
+        }
+        assert!(session.save(&pool, "zork", StoryBuilder::new(3).build()).is_err());
+        assert!(session.save_state(&pool, "zork", StoryBuilder::new(3).build(), None).is_err());
+    }
+}
@@ -1,13 +1,19 @@
 use super::memory::{ MemoryMap, Version };
 use super::InfocomError;
+use super::header::Header;
 use super::dictionary::Dictionary;
+use super::quetzal::{Quetzal, StackFrame};
+use super::blorb::Blorb;
+use super::instruction::{self, Instruction, LoopGuard};
+use super::interface::Interface;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use log::debug;
-use rand::rngs::ThreadRng;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Routine {
     address: usize,
     default_variables: Vec<u16>,
@@ -19,22 +25,22 @@ impl Routine {
         let variable_count = read_byte(mem, address) as usize;
         let mut default_variables:Vec<u16> = vec![0; variable_count];
 
-        let instruction_address = match mem.version {
-            Version::V(1) | Version::V(2) | Version::V(3) | Version::V(4) => {
-                for i in 0..variable_count {
-                    default_variables[i] = read_word(mem, address + 1 + (i * 2));
-                }
+        // Default values for local variables are only stored before version 5
+        let instruction_address = if mem.is_v5_or_later() {
+            address + 1
+        } else {
+            for i in 0..variable_count {
+                default_variables[i] = read_word(mem, address + 1 + (i * 2));
+            }
 
-                address + 1 + (2 * variable_count)
-            },
-            _ => address + 1
+            address + 1 + (2 * variable_count)
         };
 
         Ok(Routine { address, default_variables, instruction_address })
     }
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Frame {
     routine: Routine,
     local_variables: Vec<u16>,
@@ -42,6 +48,7 @@ pub struct Frame {
     pc: usize,
     return_variable: Option<u8>,
     return_address: usize,
+    argument_count: usize,
 }
 
 
@@ -59,15 +66,17 @@ fn read_word(mem: &MemoryMap, address: usize) -> u16 {
 impl Frame {
     pub fn new(routine: Routine, arguments: Vec<u16>, return_variable: Option<u8>, return_address: usize) -> Result<Frame, InfocomError> {
         let mut local_variables:Vec<u16> = routine.default_variables.clone();
+        let argument_count = arguments.len();
 
-        for (i, arg) in arguments.iter().enumerate() {
+        // Arguments beyond the routine's local variables are discarded
+        for (i, arg) in arguments.iter().take(local_variables.len()).enumerate() {
             local_variables[i] = *arg;
         }
 
         let pc = routine.instruction_address;
 
         debug!("Frame: ${:06x} {:?}, @ ${:06x}, S->{:?}, ret @ ${:06x}", routine.address, local_variables, routine.instruction_address, return_variable, return_address);
-        Ok(Frame { routine, local_variables, stack: Vec::new(), pc, return_variable, return_address })
+        Ok(Frame { routine, local_variables, stack: Vec::new(), pc, return_variable, return_address, argument_count })
     }
 
     pub fn push(&mut self, value: u16) {  
@@ -81,36 +90,89 @@ impl Frame {
         }
     }
 
+    /// Pop the top of the routine stack.  Stack underflow is an error.
     pub fn pop(&mut self) -> Result<u16, InfocomError> {
-        if self.stack.len() > 0 {
-            Ok(self.stack.remove(self.stack.len() - 1))
-        } else {
-            Err(InfocomError::Memory(format!("Pop from empty stack")))
+        match self.stack.pop() {
+            Some(v) => Ok(v),
+            None => Err(InfocomError::Memory(format!("Pop from empty stack")))
         }
     }
 }
 
+fn terminating_characters(mem: &MemoryMap) -> Result<HashSet<u16>, InfocomError> {
+    let mut terminators = HashSet::new();
+    terminators.insert(13);
+
+    if mem.is_v5_or_later() {
+        let mut address = mem.get_word(0x2E)? as usize;
+        if address != 0 {
+            loop {
+                let z = mem.get_byte(address)? as u16;
+                match z {
+                    0 => break,
+                    129..=154 | 252..=255 => { terminators.insert(z); },
+                    _ => debug!("Invalid terminating character: {}", z)
+                }
+                address += 1;
+            }
+        }
+    }
+
+    Ok(terminators)
+}
+
+/// The frame execution starts in, which has no routine header or locals
+fn initial_frame(header: &Header) -> Result<Frame, InfocomError> {
+    let pc = header.initial_pc();
+    let r = Routine { address: pc, default_variables: Vec::new(), instruction_address: pc };
+    Frame::new(r, Vec::new(), None, 0)
+}
+
 pub struct FrameStack<'a> {
     memory: &'a mut MemoryMap,
     global_variable_table_address: usize,
     stack: Vec<Frame>,
     pub current_frame: Frame,
-    rng: ThreadRng,
+    rng: Box<dyn RngCore>,
     pub dictionary: Dictionary,
+    pub terminating_characters: HashSet<u16>,
+    instruction_cache: Option<HashMap<usize, Instruction>>,
+    loop_guard: LoopGuard,
+    resources: Option<Blorb>,
+}
+
+/// What happened on a single `FrameStack::step`
+#[derive(Debug, PartialEq)]
+pub enum StepOutcome {
+    /// An instruction was executed and execution can continue
+    Running,
+    /// The next instruction reads input and the interface has none available
+    AwaitingInput,
+    /// The next instruction is `quit`
+    Halted,
 }
 
 impl<'a> FrameStack<'a> {
     pub fn new(mem: &'a mut MemoryMap) -> Result<FrameStack, InfocomError> {
-        let pc = mem.get_word(0x06)? as usize;
-        let global_variable_table_address = mem.get_word(0x0C)? as usize;
-        let r = Routine { address: pc, default_variables: Vec::new(), instruction_address: pc };
-        let f = Frame::new(r, Vec::new(), None, 0)?;
+        FrameStack::with_rng(mem, Box::new(rand::thread_rng()))
+    }
+
+    /// Create a FrameStack with a seeded random number generator so `random` is repeatable
+    pub fn new_seeded(mem: &'a mut MemoryMap, seed: u64) -> Result<FrameStack, InfocomError> {
+        FrameStack::with_rng(mem, Box::new(StdRng::seed_from_u64(seed)))
+    }
+
+    fn with_rng(mem: &'a mut MemoryMap, rng: Box<dyn RngCore>) -> Result<FrameStack, InfocomError> {
+        let mut header = Header::new(mem)?;
+        header.set_interpreter_capabilities(mem)?;
+        let global_variable_table_address = header.global_variables();
+        let f = initial_frame(&header)?;
         let stack = Vec::new();
-        let rng = rand::thread_rng();
         let dictionary = Dictionary::new(&mem)?;
         //debug!("dictionary: {:?}", dictionary);
+        let terminating_characters = terminating_characters(mem)?;
 
-        Ok(FrameStack { memory: mem, global_variable_table_address, stack, current_frame: f, rng, dictionary })
+        Ok(FrameStack { memory: mem, global_variable_table_address, stack, current_frame: f, rng, dictionary, terminating_characters, instruction_cache: None, loop_guard: LoopGuard::default(), resources: None })
     }
 
     // pub fn analyze_text(&mut self, text: &String, parse_table_address: usize) -> Result<(),InfocomError> {
@@ -121,11 +183,65 @@ impl<'a> FrameStack<'a> {
         self.current_frame.pc
     }
 
+    pub fn set_pc(&mut self, pc: usize) {
+        self.current_frame.pc = pc;
+    }
+
+    /// Stop with an error after `limit` instructions without input
+    pub fn set_loop_guard(&mut self, limit: Option<usize>) {
+        self.loop_guard = LoopGuard::new(limit);
+    }
+
+    /// Load sounds and other resources from a Blorb file supplied with the story
+    pub fn set_resources(&mut self, resources: Blorb) {
+        self.resources = Some(resources);
+    }
+
+    /// Sound resource `number`, if a resource file with that sound has been loaded
+    pub fn sound(&self, number: u16) -> Option<&[u8]> {
+        self.resources.as_ref().and_then(|r| r.sound(number as u32))
+    }
+
+    /// Decode and execute the instruction at the current pc, then advance the pc
+    pub fn step<T>(&mut self, interface: &mut T) -> Result<StepOutcome, InfocomError>
+    where
+        T: Interface
+    {
+        let pc = self.pc();
+        let mut i = instruction::decode_cached(self, pc)?;
+        if i.is_quit() {
+            return Ok(StepOutcome::Halted)
+        }
+        if i.is_input() && !interface.has_input() {
+            return Ok(StepOutcome::AwaitingInput)
+        }
+
+        self.loop_guard.check(&i)?;
+        let next_pc = i.execute(self, interface)?;
+        self.set_pc(next_pc);
+        Ok(StepOutcome::Running)
+    }
+
+    /// A random number from 1 to `range`, or 0 if `range` is 0
     pub fn random(&mut self, range: u16) -> Result<u16,InfocomError> {
-        // TODO: Handle "predictable mode"
+        if range == 0 {
+            return Ok(0)
+        }
+
         Ok(self.rng.gen_range(0, range) as u16 + 1)
     }
 
+    /// Reseed the random number generator, predictably for a non-zero seed
+    pub fn seed(&mut self, seed: u16) {
+        if seed == 0 {
+            debug!("Reseeding random number generator");
+            self.rng = Box::new(StdRng::from_entropy());
+        } else {
+            debug!("Seeding random number generator with {}", seed);
+            self.rng = Box::new(StdRng::seed_from_u64(seed as u64));
+        }
+    }
+
     pub fn get_memory(&self) -> &MemoryMap {
         self.memory
     }
@@ -140,15 +256,28 @@ impl<'a> FrameStack<'a> {
         self.memory.set_word(address, value)
     }
 
-    pub fn unpack_address(&self, packed_address: u16) -> Result<usize,InfocomError> {
+    fn unpack_address(&self, packed_address: u16, offset_address: usize) -> Result<usize,InfocomError> {
         match self.memory.version {
             Version::V(1) | Version::V(2) | Version::V(3) => Ok(packed_address as usize * 2),
             Version::V(4) | Version::V(5) => Ok(packed_address as usize * 4),
+            // V6 and V7 add an offset from the header, stored divided by 8
+            Version::V(6) | Version::V(7) => {
+                let offset = self.memory.get_word(offset_address)? as usize;
+                Ok((packed_address as usize * 4) + (offset * 8))
+            },
             Version::V(8) => Ok(packed_address as usize * 8),
             _ => return Err(InfocomError::Memory(format!("Unimplemented version: {:?}", self.memory.version)))
         }
     }
 
+    pub fn unpack_routine_address(&self, packed_address: u16) -> Result<usize,InfocomError> {
+        self.unpack_address(packed_address, 0x28)
+    }
+
+    pub fn unpack_string_address(&self, packed_address: u16) -> Result<usize,InfocomError> {
+        self.unpack_address(packed_address, 0x2A)
+    }
+
     pub fn call(&mut self, packed_address: u16, arguments: Vec<u16>, return_variable: Option<u8>, return_address: usize) -> Result<usize, InfocomError> {
         if packed_address == 0 {
             if let Some(v) = return_variable {
@@ -157,7 +286,7 @@ impl<'a> FrameStack<'a> {
 
             Ok(return_address)
         } else {
-            let address = self.unpack_address(packed_address)?;
+            let address = self.unpack_routine_address(packed_address)?;
             let routine = Routine::new(self.memory, address)?;
             self.stack.push(self.current_frame.clone());
             self.current_frame = Frame::new(routine, arguments, return_variable, return_address)?;
@@ -170,7 +299,10 @@ impl<'a> FrameStack<'a> {
         debug!("Return");
         let return_address = self.current_frame.return_address;
         debug!("From {:?}", self.current_frame);
-        self.current_frame = self.stack.remove(self.stack.len() - 1);
+        self.current_frame = match self.stack.pop() {
+            Some(f) => f,
+            None => return Err(InfocomError::Memory(format!("Return from the top level routine")))
+        };
         debug!("To {:?}", self.current_frame);
         match return_variable {
             Some(v) => self.set_variable(v, return_value, false)?,
@@ -180,31 +312,164 @@ impl<'a> FrameStack<'a> {
         Ok(return_address)
     }
 
+    /// The call stack as Quetzal frames, outermost first
+    pub fn quetzal_frames(&self) -> Vec<StackFrame> {
+        self.stack.iter().chain(std::iter::once(&self.current_frame)).map(|f| {
+            let discard = if f.return_variable.is_none() { 0x10 } else { 0 };
+            StackFrame { return_pc: f.return_address,
+                         flags: (f.local_variables.len() as u8 & 0xF) | discard,
+                         result_variable: f.return_variable.unwrap_or(0),
+                         // One bit per supplied argument, starting from bit 0
+                         arguments: ((1u16 << f.argument_count.min(7)) - 1) as u8,
+                         local_variables: f.local_variables.clone(),
+                         stack: f.stack.clone() }
+        }).collect()
+    }
+
+    /// Replace the call stack with frames restored from a Quetzal save
+    pub fn restore_quetzal_frames(&mut self, frames: &[StackFrame]) -> Result<(), InfocomError> {
+        let restored:Vec<Frame> = frames.iter().map(|f| {
+            let routine = Routine { address: 0, default_variables: f.local_variables.clone(), instruction_address: 0 };
+            Frame { routine,
+                    local_variables: f.local_variables.clone(),
+                    stack: f.stack.clone(),
+                    pc: 0,
+                    return_variable: if f.flags & 0x10 == 0x10 { None } else { Some(f.result_variable) },
+                    return_address: f.return_pc,
+                    argument_count: (f.arguments & 0x7F).trailing_ones() as usize }
+        }).collect();
+
+        self.set_frames(restored)
+    }
+
+    /// Replace the call stack with frames saved from `frames`, outermost first and ending
+    /// with the current frame
+    pub fn set_frames(&mut self, mut frames: Vec<Frame>) -> Result<(), InfocomError> {
+        match frames.pop() {
+            Some(f) => {
+                self.current_frame = f;
+                self.stack = frames;
+                Ok(())
+            },
+            None => Err(InfocomError::Memory(format!("Restore with no call frames")))
+        }
+    }
+
+    /// Enable or disable memoizing decoded instructions by address
+    pub fn set_instruction_cache(&mut self, enabled: bool) {
+        self.instruction_cache = if enabled { Some(HashMap::new()) } else { None };
+    }
+
+    pub fn cached_instruction(&self, address: usize) -> Option<&Instruction> {
+        self.instruction_cache.as_ref().and_then(|c| c.get(&address))
+    }
+
+    pub fn cache_instruction(&mut self, address: usize, instruction: &Instruction) {
+        if let Some(c) = self.instruction_cache.as_mut() {
+            c.insert(address, instruction.clone());
+        }
+    }
+
+    fn clear_instruction_cache(&mut self) {
+        if let Some(c) = self.instruction_cache.as_mut() {
+            c.clear();
+        }
+    }
+
+    /// Restore dynamic memory and the call stack from a Quetzal save
+    pub fn restore(&mut self, quetzal: &Quetzal) -> Result<(), InfocomError> {
+        self.clear_instruction_cache();
+        quetzal.restore_memory(self.memory)?;
+        self.restore_quetzal_frames(&quetzal.stks)?;
+        Header::new(self.memory)?.set_interpreter_capabilities(self.memory)
+    }
+
+    /// Record a new screen size in the header
+    pub fn set_screen_size(&mut self, rows: u16, columns: u16) -> Result<(), InfocomError> {
+        Header::new(self.memory)?.set_screen_size(self.memory, rows, columns)
+    }
+
+    /// Reset dynamic memory and the call stack to their state when the story was loaded,
+    /// returning the initial pc
+    pub fn restart(&mut self) -> Result<usize, InfocomError> {
+        self.clear_instruction_cache();
+        self.memory.reset_dynamic()?;
+        let mut header = Header::new(self.memory)?;
+        header.set_interpreter_capabilities(self.memory)?;
+        self.stack.clear();
+        self.current_frame = initial_frame(&header)?;
+        Ok(self.current_frame.pc)
+    }
+
+    /// The number of arguments passed to the current routine
+    pub fn argument_count(&self) -> usize {
+        self.current_frame.argument_count
+    }
+
+    pub fn frame_depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Every frame on the call stack, outermost first and ending with the current frame
+    pub fn frames(&self) -> Vec<&Frame> {
+        self.stack.iter().chain(std::iter::once(&self.current_frame)).collect()
+    }
+
+    /// Return `return_value` from the routine that executed the `catch` producing
+    /// `frame_token`.  The frames called from that routine are discarded along with
+    /// their pending store variables, so only the catching routine's caller receives
+    /// the value.
+    pub fn throw(&mut self, return_value: u16, frame_token: u16) -> Result<usize, InfocomError> {
+        let depth = frame_token as usize;
+        if depth > self.stack.len() {
+            return Err(InfocomError::Memory(format!("Throw to frame {} beyond current depth {}", depth, self.stack.len())))
+        }
+
+        debug!("Throw to frame {}", depth);
+        while self.stack.len() > depth {
+            if let Some(f) = self.stack.pop() {
+                debug!("Discard {:?}", self.current_frame);
+                self.current_frame = f;
+            }
+        }
+
+        self.return_from(return_value)
+    }
+
+    /// Read a variable.  An `indirect` read of the stack (variable 0) peeks at the top
+    /// of the stack rather than popping it, so that `inc`, `dec`, `load` and friends
+    /// operate on the stack in place.
     pub fn get_variable(&mut self, variable_number: u8, indirect: bool) -> Result<u16, InfocomError> {
         match variable_number {
             0 => {
-                debug!("Read fron stack => ${:04x}", self.current_frame.peek()?);
-                if indirect {
-                    self.current_frame.peek()
+                let value = if indirect {
+                    self.current_frame.peek()?
                 } else {
-                    self.current_frame.pop()
-                }
+                    self.current_frame.pop()?
+                };
+                debug!("Read from stack => ${:04x}", value);
+                Ok(value)
             },
             1..=15 => {
-                debug!("Read local variable ${:02x} from {:?} => ${:04x}", variable_number - 1, self.current_frame.local_variables, self.current_frame.local_variables.get(variable_number as usize - 1).unwrap());
                 match self.current_frame.local_variables.get(variable_number as usize - 1) {
-                    Some(v) => Ok(*v),
+                    Some(v) => {
+                        debug!("Read local variable ${:02x} => ${:04x}", variable_number - 1, v);
+                        Ok(*v)
+                    },
                     None => Err(InfocomError::Memory(format!("Read of local variable ${:02x} that does not exist", variable_number - 1)))
                 }
             }
             16..=255 => {
                 let addr = self.global_variable_table_address + ((variable_number as usize - 16) * 2);
-                debug!("Read global variable ${:02x} from ${:04x} => ${:04x}", variable_number - 16, addr, self.memory.get_word(addr)?);
-                self.memory.get_word(addr)
+                let value = self.memory.get_word(addr)?;
+                debug!("Read global variable ${:02x} from ${:04x} => ${:04x}", variable_number - 16, addr, value);
+                Ok(value)
             }
         }
     }
 
+    /// Write a variable.  An `indirect` write to the stack (variable 0) replaces the top
+    /// of the stack rather than pushing a new value.
     pub fn set_variable(&mut self, variable_number: u8, value: u16, indirect: bool) -> Result<(), InfocomError> {
         match variable_number {
             0 => {
@@ -233,4 +498,222 @@ impl<'a> FrameStack<'a> {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_util::{StoryBuilder, TestInterface, CODE};
+
+    #[test]
+    fn throw_returns_from_the_catching_routine() {
+        // main calls A at depth 1, A calls B, B catches at depth 2 and calls C, which throws 5
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xE0, 0x3F, 0x04, 0x40, 0x10, 0xBA])
+            .routine(0x1100, &[], &[0xE0, 0x3F, 0x04, 0x80, 0x11, 0xBA])
+            .routine(0x1200, &[0], &[0xB9, 0x01, 0xE0, 0x2F, 0x04, 0xC0, 0x01, 0x12, 0xB0])
+            .routine(0x1300, &[0], &[0x3C, 0x05, 0x01])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+
+        for _ in 0..3 {
+            assert_eq!(state.step(&mut interface).unwrap(), StepOutcome::Running);
+        }
+        assert_eq!(state.get_variable(1, false).unwrap(), 2);
+        assert_eq!(state.frame_depth(), 2);
+
+        state.step(&mut interface).unwrap();
+        assert_eq!(state.frame_depth(), 3);
+        state.step(&mut interface).unwrap();
+
+        // Execution resumes in A after its call to B, with B's result stored
+        assert_eq!(state.pc(), 0x1106);
+        assert_eq!(state.frame_depth(), 1);
+        assert_eq!(state.get_variable(0x11, false).unwrap(), 5);
+        assert_eq!(state.get_variable(0x12, false).unwrap(), 0);
+    }
+
+    #[test]
+    fn v6_and_v7_packed_addresses_add_the_header_offsets() {
+        for version in &[6, 7] {
+            // Routine offset $100 and string offset $200, both stored divided by 8
+            let mut mem = StoryBuilder::new(*version).word(0x28, 0x100).word(0x2A, 0x200).build();
+            let state = FrameStack::new(&mut mem).unwrap();
+            assert_eq!(state.unpack_routine_address(0x100).unwrap(), 0xC00, "V{}", version);
+            assert_eq!(state.unpack_string_address(0x100).unwrap(), 0x1400, "V{}", version);
+        }
+
+        let mut mem = StoryBuilder::new(5).word(0x28, 0x100).word(0x2A, 0x200).build();
+        let state = FrameStack::new(&mut mem).unwrap();
+        assert_eq!(state.unpack_routine_address(0x100).unwrap(), 0x400);
+        assert_eq!(state.unpack_string_address(0x100).unwrap(), 0x400);
+    }
+
+    #[test]
+    fn returning_from_the_top_level_is_an_error() {
+        // rtrue in the initial frame
+        let mut mem = StoryBuilder::new(3).code(&[0xB0]).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        match state.step(&mut interface) {
+            Err(InfocomError::Memory(m)) => assert_eq!(m, "Return from the top level routine"),
+            _ => panic!("expected a memory error")
+        }
+        assert_eq!(state.frame_depth(), 0);
+        assert!(state.return_from(1).is_err());
+    }
+
+    #[test]
+    fn seeded_frame_stacks_draw_a_known_sequence() {
+        let draw = |seed| {
+            let mut mem = StoryBuilder::new(3).build();
+            let mut state = FrameStack::new_seeded(&mut mem, seed).unwrap();
+            (0..20).map(|_| state.random(6).unwrap()).collect::<Vec<u16>>()
+        };
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let expected: Vec<u16> = (0..20).map(|_| rng.gen_range(0, 6u16) + 1).collect();
+        assert_eq!(draw(42), expected);
+        assert!(expected.iter().all(|d| *d >= 1 && *d <= 6));
+        assert_ne!(draw(43), expected);
+
+        let mut mem = StoryBuilder::new(3).build();
+        let mut state = FrameStack::new_seeded(&mut mem, 42).unwrap();
+        assert_eq!(state.random(0).unwrap(), 0);
+    }
+
+    #[test]
+    fn inc_changes_the_top_of_the_stack_in_place() {
+        // push 5, push 7, inc sp, inc G00
+        let mut mem = StoryBuilder::new(3)
+            .code(&[0xE8, 0x7F, 0x05, 0xE8, 0x7F, 0x07, 0x95, 0x00, 0x95, 0x10])
+            .global(0, 0xFFFF)
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        for _ in 0..4 {
+            state.step(&mut interface).unwrap();
+        }
+        assert_eq!(state.current_frame.stack, vec![5, 8]);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 0);
+    }
+
+    #[test]
+    fn step_executes_one_instruction() {
+        // add 2 3 -> G00, aread $700 $780 -> G01, quit
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0x14, 0x02, 0x03, 0x10, 0xE4, 0x0F, 0x07, 0x00, 0x07, 0x80, 0x11, 0xBA])
+            .byte(0x700, 20)
+            .byte(0x780, 5)
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        assert_eq!(state.step(&mut interface).unwrap(), StepOutcome::Running);
+        assert_eq!(state.pc(), CODE + 4);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 5);
+
+        // Input instructions wait without moving the pc until input is available
+        assert_eq!(state.step(&mut interface).unwrap(), StepOutcome::AwaitingInput);
+        assert_eq!(state.pc(), CODE + 4);
+        interface.input.push_back(String::from("look"));
+        assert_eq!(state.step(&mut interface).unwrap(), StepOutcome::Running);
+        assert_eq!(state.get_variable(0x11, false).unwrap(), 13);
+        assert_eq!(state.step(&mut interface).unwrap(), StepOutcome::Halted);
+        assert_eq!(state.pc(), CODE + 11);
+    }
+
+    #[test]
+    fn pop_and_ret_popped_take_the_top_of_the_stack() {
+        // call $1100 -> G00, to a routine that does push 5, push 7, pop, ret_popped
+        let mut mem = StoryBuilder::new(3)
+            .code(&[0xE0, 0x3F, 0x08, 0x80, 0x10, 0xB9, 0xB8])
+            .routine(0x1100, &[], &[0xE8, 0x7F, 0x05, 0xE8, 0x7F, 0x07, 0xB9, 0xB8])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        for _ in 0..3 {
+            state.step(&mut interface).unwrap();
+        }
+        assert_eq!(state.current_frame.stack, vec![5, 7]);
+        state.step(&mut interface).unwrap();
+        assert_eq!(state.current_frame.stack, vec![5]);
+        state.step(&mut interface).unwrap();
+        assert_eq!(state.pc(), CODE + 5);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 5);
+
+        // Both are errors on an empty stack
+        assert!(state.current_frame.stack.is_empty());
+        assert!(state.step(&mut interface).is_err());
+        state.set_pc(CODE + 6);
+        assert!(state.step(&mut interface).is_err());
+    }
+
+    #[test]
+    fn store_to_the_stack_replaces_the_top_value() {
+        // push 5, push 7, store sp 9
+        let mut mem = StoryBuilder::new(3)
+            .code(&[0xE8, 0x7F, 0x05, 0xE8, 0x7F, 0x07, 0x0D, 0x00, 0x09])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        for _ in 0..3 {
+            state.step(&mut interface).unwrap();
+        }
+        assert_eq!(state.current_frame.stack, vec![5, 9]);
+    }
+
+    #[test]
+    fn extra_arguments_are_discarded() {
+        // call_vs2 $1100 1 2 3 4 -> G00, to a routine with 2 locals
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xEC, 0x15, 0x7F, 0x04, 0x40, 0x01, 0x02, 0x03, 0x04, 0x10])
+            .routine(0x1100, &[0, 0], &[0xB0])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        state.step(&mut interface).unwrap();
+        assert_eq!(state.pc(), 0x1101);
+        assert_eq!(state.current_frame.local_variables, vec![1, 2]);
+        assert_eq!(state.argument_count(), 4);
+
+        // Calling address 0 does nothing and returns false
+        let mut mem = StoryBuilder::new(5).code(&[0xE0, 0x3F, 0x00, 0x00, 0x10]).global(0, 9).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        state.step(&mut interface).unwrap();
+        assert_eq!(state.pc(), CODE + 5);
+        assert_eq!(state.frame_depth(), 0);
+        assert_eq!(state.get_variable(0x10, false).unwrap(), 0);
+    }
+
+    #[test]
+    fn throw_beyond_the_current_depth_is_an_error() {
+        let mut mem = StoryBuilder::new(5).build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        assert!(state.throw(1, 3).is_err());
+    }
+
+    #[test]
+    fn quetzal_frames_keep_the_supplied_arguments() {
+        // call_vs $1100 1 2 -> G00, to a routine with 3 locals
+        let mut mem = StoryBuilder::new(5)
+            .code(&[0xE0, 0x17, 0x04, 0x40, 0x01, 0x02, 0x10])
+            .routine(0x1100, &[0, 0, 0], &[0xB0])
+            .build();
+        let mut state = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        state.step(&mut interface).unwrap();
+        assert_eq!(state.argument_count(), 2);
+
+        let frames = state.quetzal_frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].arguments, 0x03);
+        assert_eq!(frames[1].local_variables, vec![1, 2, 0]);
+
+        let mut other = StoryBuilder::new(5).build();
+        let mut restored = FrameStack::new(&mut other).unwrap();
+        restored.restore_quetzal_frames(&frames).unwrap();
+        assert_eq!(restored.argument_count(), 2);
+        assert_eq!(restored.frame_depth(), 1);
+        assert_eq!(restored.quetzal_frames(), frames);
+    }
+}
@@ -0,0 +1,77 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::path::PathBuf;
+
+use log::error;
+
+use super::InfocomError;
+use super::header::Header;
+use super::memory::MemoryMap;
+use super::redis_connection::RedisPool;
+use super::session::Session;
+
+/// Where to load a story from
+pub enum StorySource<'a> {
+    /// A story file on disk
+    File(PathBuf),
+    /// A story previously added to a Redis session
+    Session { pool: &'a RedisPool, id: String, name: String },
+}
+
+pub struct Story;
+
+impl Story {
+    /// Load a story and validate its version and header
+    pub fn open(source: StorySource) -> Result<MemoryMap, InfocomError> {
+        let mem = match source {
+            StorySource::File(path) => match fs::read(&path) {
+                Ok(bytes) => MemoryMap::try_from(bytes)?,
+                Err(e) => {
+                    error!("Unable to read story file {:?}: {}", path, e);
+                    return Err(InfocomError::API(format!("Unable to read story file {:?}: {}", path, e)))
+                }
+            },
+            StorySource::Session { pool, id, name } => Session::try_from((pool, id.as_str()))?.load(pool, &name)?
+        };
+
+        Header::new(&mem)?;
+        Ok(mem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use super::super::config::Config;
+    use super::super::test_util::StoryBuilder;
+
+    #[test]
+    fn stories_are_opened_from_a_file() {
+        let data = StoryBuilder::new(5).byte(0x1000, 0xBA).data();
+        let path = env::temp_dir().join(format!("infocom-{}-story.z5", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        let mem = Story::open(StorySource::File(path.clone())).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mem.version_number(), 5);
+        assert_eq!(mem.get_memory(), data);
+
+        // A missing file is an error rather than a panic
+        assert!(Story::open(StorySource::File(path)).is_err());
+    }
+
+    #[test]
+    #[ignore = "needs a redis server"]
+    fn stories_are_opened_from_a_session() {
+        let pool = RedisPool::new(&Config::from_env()).unwrap();
+
+        let data = StoryBuilder::new(3).byte(0x1000, 0xBA).data();
+        let mut session = Session::new(&pool).unwrap();
+        session.add_story(&pool, String::from("test"), MemoryMap::try_from(data.clone()).unwrap()).unwrap();
+        let mem = Story::open(StorySource::Session { pool: &pool, id: session.id.clone(), name: String::from("test") }).unwrap();
+        assert_eq!(mem.get_memory(), data);
+
+        assert!(Story::open(StorySource::Session { pool: &pool, id: session.id, name: String::from("missing") }).is_err());
+    }
+}
@@ -0,0 +1,472 @@
+//! Helpers shared by the unit tests: an interface that records what the interpreter
+//! asks of it, and a builder for small story images.
+#![allow(dead_code)]
+
+use std::collections::{HashSet, VecDeque};
+use std::convert::TryFrom;
+
+use super::interface::{Interface, StatusLineFormat, Window};
+use super::memory::MemoryMap;
+use super::text::Encoder;
+
+/// Address of the global variable table in a built story
+pub const GLOBALS: usize = 0x100;
+/// Address of the object table in a built story
+pub const OBJECTS: usize = 0x300;
+/// Property tables are allocated upwards from here
+pub const PROPERTIES: usize = 0x500;
+/// Address of the dictionary in a built story
+pub const DICTIONARY: usize = 0x600;
+/// Start of static memory
+pub const STATIC: usize = 0x800;
+/// Start of high memory and the initial pc
+pub const CODE: usize = 0x1000;
+/// Size of a built story
+pub const SIZE: usize = 0x2000;
+
+/// An interface driven by queued input that records output and screen requests
+pub struct TestInterface {
+    pub output: String,
+    pub input: VecDeque<String>,
+    /// Terminating character for each line of input, 13 if none is queued
+    pub terminators: VecDeque<u16>,
+    pub keys: VecDeque<u16>,
+    pub status: Vec<String>,
+    pub window: u16,
+    pub split: u16,
+    pub cursor: (u16, u16),
+    pub font: u16,
+    pub buffer_mode: Option<u16>,
+    pub buffer_screen: u16,
+    pub input_stream: u16,
+    pub erased: Vec<Option<u16>>,
+    pub erased_windows: Vec<i16>,
+    pub text_style: u16,
+    pub windows: [Window; 8],
+    pub recording: bool,
+    pub commands: Vec<String>,
+    pub resize: Option<(u16, u16)>,
+    pub flushes: usize,
+    pub unsupported: HashSet<u32>,
+    pub beeps: Vec<bool>,
+    pub sounds: Vec<(u16, usize)>,
+}
+
+impl TestInterface {
+    pub fn new() -> TestInterface {
+        TestInterface::with_input(&[])
+    }
+
+    pub fn with_input(lines: &[&str]) -> TestInterface {
+        TestInterface { output: String::new(),
+                        input: lines.iter().map(|l| String::from(*l)).collect(),
+                        terminators: VecDeque::new(),
+                        keys: VecDeque::new(),
+                        status: Vec::new(),
+                        window: 0,
+                        split: 0,
+                        cursor: (1, 1),
+                        font: 1,
+                        buffer_mode: None,
+                        buffer_screen: 1,
+                        input_stream: 0,
+                        erased: Vec::new(),
+                        erased_windows: Vec::new(),
+                        text_style: 0,
+                        windows: [Window::default(); 8],
+                        recording: false,
+                        commands: Vec::new(),
+                        resize: None,
+                        flushes: 0,
+                        unsupported: HashSet::new(),
+                        beeps: Vec::new(),
+                        sounds: Vec::new() }
+    }
+
+    fn window_index(&self, window: u16) -> usize {
+        (if window as i16 == -3 { self.window } else { window }) as usize
+    }
+}
+
+impl Interface for TestInterface {
+    fn print(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    fn new_line(&mut self) {
+        self.output.push('\n');
+    }
+
+    fn read(&mut self, terminating_characters: &HashSet<u16>, max_chars: usize) -> (String, u16) {
+        let line:String = self.input.pop_front().unwrap_or_default().chars().take(max_chars).collect();
+        let terminator = match self.terminators.pop_front() {
+            Some(t) if terminating_characters.contains(&t) => t,
+            _ => 13
+        };
+        if self.recording {
+            self.commands.push(line.clone());
+        }
+        (line, terminator)
+    }
+
+    fn read_char(&mut self) -> u16 {
+        self.keys.pop_front().unwrap_or(13)
+    }
+
+    fn status_line(&mut self, name: &str, format: StatusLineFormat, v1: i16, v2: u16) {
+        let status = match format {
+            StatusLineFormat::SCORED => format!("{} {}/{}", name, v1, v2),
+            StatusLineFormat::TIMED => format!("{} {}:{:02}", name, v1, v2)
+        };
+        self.status.push(status);
+    }
+
+    fn split_window(&mut self, lines: u16) {
+        self.split = lines;
+    }
+
+    fn set_window(&mut self, window: u16) {
+        self.window = window;
+    }
+
+    fn get_window(&self) -> u16 {
+        self.window
+    }
+
+    fn set_cursor(&mut self, line: u16, column: u16) {
+        self.cursor = (line, column);
+    }
+
+    fn get_cursor(&mut self) -> (u16, u16) {
+        self.cursor
+    }
+
+    fn erase_line(&mut self, columns: Option<u16>) {
+        self.erased.push(columns);
+    }
+
+    fn erase_window(&mut self, window: i16) {
+        self.erased_windows.push(window);
+    }
+
+    fn set_text_style(&mut self, style: u16) {
+        self.text_style = style;
+    }
+
+    fn buffer_mode(&mut self, flag: u16) {
+        self.buffer_mode = Some(flag);
+    }
+
+    fn buffer_screen(&mut self, mode: u16) -> u16 {
+        let previous = self.buffer_screen;
+        if mode as i16 != -1 {
+            self.buffer_screen = mode;
+        }
+        previous
+    }
+
+    fn input_stream(&mut self, stream: u16) {
+        self.input_stream = stream;
+    }
+
+    fn record_commands(&mut self, enabled: bool) {
+        self.recording = enabled;
+    }
+
+    fn recorded_commands(&self) -> &[String] {
+        &self.commands
+    }
+
+    fn set_font(&mut self, font: u16) -> u16 {
+        match font {
+            0 => self.font,
+            1 | 4 => std::mem::replace(&mut self.font, font),
+            _ => 0
+        }
+    }
+
+    fn get_window_property(&self, window: u16, property: u16) -> u16 {
+        self.windows[self.window_index(window)].get_property(property).unwrap_or(0)
+    }
+
+    fn set_window_property(&mut self, window: u16, property: u16, value: u16) {
+        let index = self.window_index(window);
+        self.windows[index].set_property(property, value);
+    }
+
+    fn has_input(&self) -> bool {
+        !self.input.is_empty() || !self.keys.is_empty()
+    }
+
+    fn on_resize(&mut self) -> Option<(u16, u16)> {
+        self.resize.take()
+    }
+
+    fn flush(&mut self) {
+        self.flushes = self.flushes + 1;
+    }
+
+    fn can_output(&self, code_point: u32) -> bool {
+        !self.unsupported.contains(&code_point)
+    }
+
+    fn can_input(&self, code_point: u32) -> bool {
+        code_point < 0x80 && !self.unsupported.contains(&code_point)
+    }
+
+    fn beep(&mut self, high: bool) {
+        self.beeps.push(high);
+    }
+
+    fn play_sound(&mut self, number: u16, data: &[u8]) {
+        self.sounds.push((number, data.len()));
+    }
+}
+
+/// Encode lowercase text as a Z-string.  Upper case letters are shifted and anything
+/// else is written as a ZSCII escape.
+pub fn zstring(version: u8, text: &str) -> Vec<u8> {
+    // V1 and V2 single shift with 2 and 3, later versions with 4 and 5
+    let (a1, a2) = if version < 3 { (2, 3) } else { (4, 5) };
+    let mut zchars:Vec<u8> = Vec::new();
+    for c in text.chars() {
+        match c {
+            ' ' => zchars.push(0),
+            'a'..='z' => zchars.push(c as u8 - b'a' + 6),
+            'A'..='Z' => zchars.extend_from_slice(&[a1, c as u8 - b'A' + 6]),
+            _ => {
+                let z = c as u16;
+                zchars.extend_from_slice(&[a2, 6, (z >> 5) as u8 & 0x1F, z as u8 & 0x1F]);
+            }
+        }
+    }
+    while zchars.is_empty() || zchars.len() % 3 != 0 {
+        zchars.push(5);
+    }
+    pack_zchars(&zchars)
+}
+
+/// Pack Z-characters 3 to a word, setting the end bit on the last word.  The length
+/// must be a multiple of 3.
+pub fn pack_zchars(zchars: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (i, c) in zchars.chunks(3).enumerate() {
+        let mut w = ((c[0] as u16) << 10) | ((c[1] as u16) << 5) | c[2] as u16;
+        if i == (zchars.len() / 3) - 1 {
+            w = w | 0x8000;
+        }
+        data.push((w >> 8) as u8);
+        data.push(w as u8);
+    }
+    data
+}
+
+/// Builds a story image with the header, globals, object table and dictionary laid out
+/// at the fixed addresses above and code starting at `CODE`
+pub struct StoryBuilder {
+    data: Vec<u8>,
+    version: u8,
+    next_property_table: usize,
+}
+
+impl StoryBuilder {
+    pub fn new(version: u8) -> StoryBuilder {
+        let mut b = StoryBuilder { data: vec![0; SIZE], version, next_property_table: PROPERTIES };
+        b.data[0] = version;
+        b.set_word(0x04, CODE as u16);
+        b.set_word(0x06, CODE as u16);
+        b.set_word(0x08, DICTIONARY as u16);
+        b.set_word(0x0A, OBJECTS as u16);
+        b.set_word(0x0C, GLOBALS as u16);
+        b.set_word(0x0E, STATIC as u16);
+        b.data[0x12..0x18].copy_from_slice(b"200101");
+        b.set_length();
+        b
+    }
+
+    fn set_word(&mut self, address: usize, value: u16) {
+        self.data[address] = (value >> 8) as u8;
+        self.data[address + 1] = value as u8;
+    }
+
+    fn set_length(&mut self) {
+        let divisor = match self.version {
+            1 | 2 | 3 => 2,
+            4 | 5 => 4,
+            _ => 8
+        };
+        let length = (self.data.len() / divisor) as u16;
+        self.set_word(0x1A, length);
+    }
+
+    fn grow(&mut self, end: usize) {
+        if end > self.data.len() {
+            self.data.resize(end, 0);
+            self.set_length();
+        }
+    }
+
+    pub fn byte(mut self, address: usize, value: u8) -> StoryBuilder {
+        self.grow(address + 1);
+        self.data[address] = value;
+        self
+    }
+
+    pub fn word(mut self, address: usize, value: u16) -> StoryBuilder {
+        self.grow(address + 2);
+        self.set_word(address, value);
+        self
+    }
+
+    pub fn bytes(mut self, address: usize, data: &[u8]) -> StoryBuilder {
+        self.grow(address + data.len());
+        self.data[address..address + data.len()].copy_from_slice(data);
+        self
+    }
+
+    /// Instructions to run from the initial pc
+    pub fn code(self, code: &[u8]) -> StoryBuilder {
+        self.bytes(CODE, code)
+    }
+
+    pub fn global(self, number: u8, value: u16) -> StoryBuilder {
+        self.word(GLOBALS + (2 * number as usize), value)
+    }
+
+    /// A routine header with `locals`, whose initial values are only stored before V5,
+    /// followed by `code`
+    pub fn routine(self, address: usize, locals: &[u16], code: &[u8]) -> StoryBuilder {
+        let mut data = vec![locals.len() as u8];
+        if self.version < 5 {
+            for l in locals {
+                data.push((l >> 8) as u8);
+                data.push(*l as u8);
+            }
+        }
+        data.extend_from_slice(code);
+        self.bytes(address, &data)
+    }
+
+    /// An object with `properties` given as (number, data), in descending order
+    pub fn object(mut self, number: usize, attributes: &[usize], (parent, sibling, child): (u16, u16, u16), name: &str, properties: &[(u8, &[u8])]) -> StoryBuilder {
+        let v3 = self.version < 4;
+        let (defaults, entry_size, attribute_bytes) = if v3 { (62, 9, 4) } else { (126, 14, 6) };
+        let address = OBJECTS + defaults + ((number - 1) * entry_size);
+
+        let mut entry = vec![0; attribute_bytes];
+        for a in attributes {
+            entry[a / 8] = entry[a / 8] | (0x80 >> (a % 8));
+        }
+        if v3 {
+            entry.extend_from_slice(&[parent as u8, sibling as u8, child as u8]);
+        } else {
+            for r in &[parent, sibling, child] {
+                entry.push((r >> 8) as u8);
+                entry.push(*r as u8);
+            }
+        }
+        let table = self.next_property_table;
+        entry.push((table >> 8) as u8);
+        entry.push(table as u8);
+
+        let mut data = Vec::new();
+        if name.is_empty() {
+            data.push(0);
+        } else {
+            let text = zstring(self.version, name);
+            data.push((text.len() / 2) as u8);
+            data.extend_from_slice(&text);
+        }
+        for (p, d) in properties {
+            if v3 {
+                data.push((((d.len() - 1) as u8) << 5) | p);
+            } else if d.len() > 2 {
+                data.push(0x80 | p);
+                data.push(0x80 | (d.len() as u8 & 0x3F));
+            } else {
+                data.push(if d.len() == 2 { 0x40 | p } else { *p });
+            }
+            data.extend_from_slice(d);
+        }
+        data.push(0);
+
+        self.next_property_table = table + data.len();
+        self.bytes(address, &entry).bytes(table, &data)
+    }
+
+    /// The default value of `property`
+    pub fn default_property(self, property: u8, value: u16) -> StoryBuilder {
+        self.word(OBJECTS + (2 * (property as usize - 1)), value)
+    }
+
+    /// A dictionary at `address` with the given separators and words
+    pub fn dictionary_at(self, address: usize, separators: &[char], words: &[&str]) -> StoryBuilder {
+        let mem = MemoryMap::try_from(self.data.clone()).unwrap();
+        let encoder = Encoder::new(&mem).unwrap();
+        let (text_length, entry_length) = if self.version < 4 { (4, 7) } else { (6, 9) };
+
+        let mut entries:Vec<Vec<u8>> = words.iter().map(|w| {
+            let mut e = Vec::new();
+            for z in encoder.encode(w).unwrap() {
+                e.push((z >> 8) as u8);
+                e.push(z as u8);
+            }
+            e.resize(entry_length, 0);
+            e
+        }).collect();
+        entries.sort_by(|a, b| a[0..text_length].cmp(&b[0..text_length]));
+
+        let mut data = vec![separators.len() as u8];
+        data.extend(separators.iter().map(|c| *c as u8));
+        data.push(entry_length as u8);
+        data.push((entries.len() >> 8) as u8);
+        data.push(entries.len() as u8);
+        for e in entries {
+            data.extend_from_slice(&e);
+        }
+        self.bytes(address, &data)
+    }
+
+    pub fn dictionary(self, separators: &[char], words: &[&str]) -> StoryBuilder {
+        self.dictionary_at(DICTIONARY, separators, words)
+    }
+
+    pub fn data(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn build(self) -> MemoryMap {
+        MemoryMap::try_from(self.data).unwrap()
+    }
+}
+
+/// A Blorb file with a resource index listing `resources` as (usage, number, chunk),
+/// followed by `chunks`
+pub fn blorb(resources: &[(&[u8; 4], u32, usize)], chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+    let mut offsets = Vec::new();
+    let mut offset = 12 + 8 + 4 + (resources.len() * 12);
+    for (_, data) in chunks {
+        offsets.push(offset);
+        offset = offset + 8 + data.len() + (data.len() % 2);
+    }
+
+    let mut index = (resources.len() as u32).to_be_bytes().to_vec();
+    for (usage, number, chunk) in resources {
+        index.extend_from_slice(*usage);
+        index.extend_from_slice(&number.to_be_bytes());
+        index.extend_from_slice(&(offsets[*chunk] as u32).to_be_bytes());
+    }
+
+    let mut data = b"FORM".to_vec();
+    data.extend_from_slice(&(offset as u32 - 8).to_be_bytes());
+    data.extend_from_slice(b"IFRS");
+    for (id, chunk) in std::iter::once((b"RIdx", &index[..])).chain(chunks.iter().map(|(id, c)| (*id, *c))) {
+        data.extend_from_slice(id);
+        data.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+        data.extend_from_slice(chunk);
+        if chunk.len() % 2 == 1 {
+            data.push(0);
+        }
+    }
+    data
+}
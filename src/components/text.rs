@@ -4,6 +4,7 @@ use log::{debug, error};
 
 use super::InfocomError;
 use super::memory::{MemoryMap, Version};
+use super::header::{Header, EXTENSION_UNICODE_TABLE};
 
 struct Alphabet {
     alphabet: [[char; 26]; 3],
@@ -11,12 +12,22 @@ struct Alphabet {
 }
 
 impl Alphabet {
+    /// The character for an extra ZSCII code (155 to 251) from the standard or custom
+    /// Unicode translation table.  The standard table defines codes 155 to 223.
+    fn extra_char(&self, z: u16) -> Option<char> {
+        if z >= 155 && z <= 251 {
+            self.zscii_table.get(z as usize - 155).cloned()
+        } else {
+            None
+        }
+    }
+
     pub fn new (mem: &MemoryMap) -> Result<Alphabet,InfocomError> {
         let mut zscii_table = vec!['ä', 'ö', 'ü', 'Ä', 'Ö', 'Ü', 'ß', '»', '«', 'ë', 'ï', 'ÿ', 'Ë', 'Ï', 'á', 'é',
                                    'í', 'ó', 'ú', 'ý', 'Á', 'É', 'Í', 'Ó', 'Ú', 'Ý', 'à', 'è', 'ì', 'ò', 'ù', 'À',
-                                   'È', 'Ì', 'Ò', 'Ù', 'â', 'ê', 'î', 'ô', 'û', 'Â', 'Ê', 'Ô', 'Û', 'å', 'Å', 'ø',
-                                   'Ø', 'ã', 'ñ', 'õ', 'Ã', 'Ñ', 'Õ', 'æ', 'Æ', 'ç', 'Ç', 'þ', 'ð', 'Þ', 'Ð', '£',
-                                   'œ', 'Œ', '¡', '¿'];
+                                   'È', 'Ì', 'Ò', 'Ù', 'â', 'ê', 'î', 'ô', 'û', 'Â', 'Ê', 'Î', 'Ô', 'Û', 'å', 'Å',
+                                   'ø', 'Ø', 'ã', 'ñ', 'õ', 'Ã', 'Ñ', 'Õ', 'æ', 'Æ', 'ç', 'Ç', 'þ', 'ð', 'Þ', 'Ð',
+                                   '£', 'œ', 'Œ', '¡', '¿'];
         let m = mem.get_memory();
         match mem.version {
             Version::V(1) => Ok(Alphabet { zscii_table,
@@ -56,20 +67,16 @@ impl Alphabet {
                 };
 
                 // Untested: get unicode translation table from header extension table and convert UTF-16 values to UTF-8 
-                let extension_table_addr = mem.get_word(0x36)? as usize;
-                if extension_table_addr != 0  {
-                    let entries = mem.get_word(extension_table_addr)?;
-                    if entries >= 3  {
-                        let zscii_table_address = mem.get_word(extension_table_addr + 6)? as usize;
-                        if zscii_table_address != 0  {
-                            let count = mem.get_byte(zscii_table_address)?;
-                            let mut utf16:Vec<u16> = Vec::new();
-                            for i in 0 .. count {
-                                utf16.push(mem.get_word(zscii_table_address + 1 + (2 * i as usize))?);
-                            }
-                            zscii_table = char::decode_utf16(utf16.iter().cloned()).map(|r| r.unwrap_or(char::REPLACEMENT_CHARACTER))
-                                                    .collect::<Vec<_>>();
+                if let Some(address) = Header::new(mem)?.extension_word(mem, EXTENSION_UNICODE_TABLE)? {
+                    let zscii_table_address = address as usize;
+                    if zscii_table_address != 0  {
+                        let count = mem.get_byte(zscii_table_address)?;
+                        let mut utf16:Vec<u16> = Vec::new();
+                        for i in 0 .. count {
+                            utf16.push(mem.get_word(zscii_table_address + 1 + (2 * i as usize))?);
                         }
+                        zscii_table = char::decode_utf16(utf16.iter().cloned()).map(|r| r.unwrap_or(char::REPLACEMENT_CHARACTER))
+                                                .collect::<Vec<_>>();
                     }
                 }
 
@@ -93,28 +100,81 @@ impl Decoder {
         Ok(Decoder { memory: mem.get_memory(), version: mem.version, alphabet })
     }
 
+    /// Decode the string at `address`, which may be anywhere in memory including high
+    /// memory above 64k, as for `print_paddr`
     pub fn decode(&self, address: usize) -> Result<String, InfocomError> {
+        if address + 1 >= self.memory.len() {
+            return Err(InfocomError::ReadViolation(address, self.memory.len()))
+        }
+
         match self.version {
             Version::V(1) => DecoderV1::decode(&self.memory, &self.alphabet, address, true),
             Version::V(2) => DecoderV2::decode(&self.memory, &self.alphabet, address, true),
             _ => DecoderV3::decode(&self.memory, &self.alphabet, address, true)
         }
     }
+
+    /// Decode the string at `address`, also returning the length in bytes of the
+    /// encoded string.  Expanded abbreviations do not count towards the length.
+    pub fn decode_with_len(&self, address: usize) -> Result<(String, usize), InfocomError> {
+        let text = self.decode(address)?;
+        Ok((text, self.zchars(address).len() / 3 * 2))
+    }
+
+    /// Decode a fixed length dictionary entry.  Entries never contain abbreviations and
+    /// the encoded text ends at the first word with the high bit set, or the end of the entry.
+    pub fn decode_dictionary_entry(&self, entry: &[u8]) -> Result<String, InfocomError> {
+        let mut data = entry[0..entry.len() & !1].to_vec();
+        let len = data.len();
+        if len == 0 {
+            return Ok(String::new())
+        }
+        data[len - 2] = data[len - 2] | 0x80;
+
+        match self.version {
+            Version::V(1) => DecoderV1::decode(&data, &self.alphabet, 0, false),
+            Version::V(2) => DecoderV2::decode(&data, &self.alphabet, 0, false),
+            _ => DecoderV3::decode(&data, &self.alphabet, 0, false)
+        }
+    }
+
+    /// Decode every abbreviation as (table, index, text).  V1 has no abbreviations and
+    /// V2 has only the first table of 32.
+    pub fn abbreviations(&self) -> Result<Vec<(usize, usize, String)>, InfocomError> {
+        let tables = match self.version {
+            Version::V(1) => 0,
+            Version::V(2) => 1,
+            _ => 3
+        };
+        if tables == 0 || read_word(&self.memory, 0x18) == 0 {
+            return Ok(Vec::new())
+        }
+
+        let mut abbreviations = Vec::new();
+        for table in 1..=tables {
+            for index in 0..32 {
+                let address = abbreviation_address(&self.memory, table, index);
+                let text = match self.version {
+                    Version::V(2) => DecoderV2::decode(&self.memory, &self.alphabet, address, false)?,
+                    _ => DecoderV3::decode(&self.memory, &self.alphabet, address, false)?
+                };
+                abbreviations.push((table, index, text));
+            }
+        }
+
+        Ok(abbreviations)
+    }
+
+    /// The raw Z-characters of the encoded string at `address`
+    pub fn zchars(&self, address: usize) -> Vec<u8> {
+        read_zbytes(&self.memory, address)
+    }
    
     pub fn zscii_to_char(&self, z: u16) -> Result<char,InfocomError> {
         if z > 1023 {
             return Err(InfocomError::Text(format!("Invalid character code ${:04x}", z)))
         } else {
-            match z {
-                0 => Ok('\0'),
-                13 => Ok('\n'),
-                32..=126 => Ok(z as u8 as char),
-                _ => if z >= 155 && z < 155 + self.alphabet.zscii_table.len() as u16{
-                    Ok(self.alphabet.zscii_table[z as usize - 155])
-                } else {
-                    Ok('@')
-                }
-            }  
+            Ok(zscii_char(&self.alphabet, z))
         }
     }
 }
@@ -130,7 +190,8 @@ fn read_word(map: &Vec<u8>, address: usize) -> u16 {
 fn read_zbytes(map: &Vec<u8>, address: usize) -> Vec<u8> {
     let mut b:Vec<u8> = Vec::new();
     let mut i = address;
-    loop {
+    // An unterminated string ends with the end of memory
+    while i + 1 < map.len() {
         let v = read_word(map, i);
         let b1:u8 = ((&v >> 10) & 0x1F) as u8;
         let b2:u8 = ((&v >> 5) & 0x1F) as u8;
@@ -143,6 +204,7 @@ fn read_zbytes(map: &Vec<u8>, address: usize) -> Vec<u8> {
         }
         i = i + 2;
     }
+    b
 }
 
 fn shift(a: usize, direction: isize) -> usize {
@@ -154,20 +216,22 @@ fn shift(a: usize, direction: isize) -> usize {
     }
 }
 
-fn decode_zscii(alphabet: &Alphabet, b1: u8, b2: u8) -> char {
-    let z:usize = (((b1 as usize) << 5) & 0x3E) | ((b2 as usize) & 0x1F);
+/// Map a ZSCII output code to a character; shared by `print_char` and Z-string decoding
+fn zscii_char(alphabet: &Alphabet, z: u16) -> char {
     match z {
         0 => '\0',
         13 => '\n',
         32..=126 => z as u8 as char,
-        _ => if z >= 155 && z <= 155 + alphabet.zscii_table.len() {
-            alphabet.zscii_table[z - 155]
-        } else {
-            '@'
-        }
+        _ => alphabet.extra_char(z).unwrap_or('@')
     }
 }
 
+/// A 10-bit ZSCII code from the two 5-bit z-characters following the escape
+fn decode_zscii(alphabet: &Alphabet, b1: u8, b2: u8) -> char {
+    let z:u16 = (((b1 as u16) & 0x1F) << 5) | ((b2 as u16) & 0x1F);
+    zscii_char(alphabet, z)
+}
+
 fn abbreviation_address(map: &Vec<u8>, table: usize, index: usize) -> usize {
     let table_address:usize = read_word(map, 0x18).into();
     let entry_address = table_address + (64 * (table - 1)) + (2 * index);
@@ -199,7 +263,8 @@ impl TextDecoder for DecoderV1 {
                     3 => { current_a = shift(a, -1); continue }
                     4 => a = shift(a, 1),
                     5 => a = shift(a, -1),
-                    6 => if a == 2 {
+                    // Z-char 6 in A2, whether single shifted or locked, starts a ZSCII escape
+                    6 => if current_a == 2 {
                         if let Some(b1) = i.next() {
                             if let Some(b2) = i.next() {
                                 string.push(decode_zscii(alphabet, *b1, *b2))
@@ -257,7 +322,8 @@ impl TextDecoder for DecoderV2 {
                     3 => { current_a = shift(a, -1); continue }
                     4 => a = shift(a, 1),
                     5 => a = shift(a, -1),
-                    6 => if a == 2 {
+                    // Z-char 6 in A2, whether single shifted or locked, starts a ZSCII escape
+                    6 => if current_a == 2 {
                         if let Some(b1) = i.next() {
                             if let Some(b2) = i.next() {
                                 string.push(decode_zscii(alphabet, *b1, *b2))
@@ -358,9 +424,9 @@ impl Encoder {
 
     pub fn encode(&self, text: &str) -> Result<Vec<u16>, InfocomError> {
         let s = String::from(text).to_lowercase();
-        match self.version {
-            Version::V(1) | Version::V(2) => self.encode_text(&s, 6, true),
-            Version::V(3) => self.encode_text(&s, 6, false),
+        match self.version.number() {
+            1 | 2 => self.encode_text(&s, 6, true),
+            3 => self.encode_text(&s, 6, false),
             _ => self.encode_text(&s, 9, false)
         }
     }
@@ -384,9 +450,18 @@ impl Encoder {
         Ok(result)
     }
 
+    /// Find the alphabet and Z-character for `c` in the loaded (possibly custom) alphabet table
     fn map_char(&self, c: char) -> Option<(u8, u8)> {
+        if c == ' ' {
+            return Some((0, 0));
+        }
+
         for i in 0..3 {
             for j in 0..self.alphabet.alphabet[i].len() {
+                // A2 position 0 is the ZSCII escape, not a printable character
+                if i == 2 && j == 0 {
+                    continue;
+                }
                 if c == self.alphabet.alphabet[i][j] {
                     return Some((i as u8, j as u8 + 6));
                 }
@@ -445,6 +520,13 @@ impl Encoder {
                         result.push(i);
                         shift_locked = false;
                         result.push(4);
+                        } else if a == 1 {
+                            // Custom alphabets may place characters in A1, which needs a shift
+                            if shift_lock {
+                                result.push(2);
+                            } else {
+                                result.push(4);
+                            }
                         } else if a == 2 {
                             // If no shift-locking, push a shift
                             if !shift_lock {
@@ -478,4 +560,139 @@ impl Encoder {
         result.truncate(length);
         result
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use super::super::test_util::{pack_zchars, StoryBuilder};
+
+    /// A V5 story whose header extension table points to a Unicode translation table
+    fn story_with_unicode_table(chars: &[char]) -> MemoryMap {
+        let mut builder = StoryBuilder::new(5)
+            .word(0x36, 0x700)
+            .word(0x700, 3)
+            .word(0x706, 0x710)
+            .byte(0x710, chars.len() as u8);
+        for (i, c) in chars.iter().enumerate() {
+            builder = builder.word(0x711 + (2 * i), *c as u16);
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn extra_zscii_codes_use_the_unicode_table() {
+        // The standard table defines 155 to 223
+        let decoder = Decoder::new(&StoryBuilder::new(5).build()).unwrap();
+        assert_eq!(decoder.zscii_to_char(155).unwrap(), 'ä');
+        assert_eq!(decoder.zscii_to_char(198).unwrap(), 'Î');
+        assert_eq!(decoder.zscii_to_char(223).unwrap(), '¿');
+        assert_eq!(decoder.zscii_to_char(224).unwrap(), '@');
+        assert_eq!(decoder.zscii_to_char(251).unwrap(), '@');
+
+        // A custom table can define every code up to 251
+        let chars:Vec<char> = (0..97).map(|i| char::from_u32(0x410 + i).unwrap()).collect();
+        let decoder = Decoder::new(&story_with_unicode_table(&chars)).unwrap();
+        assert_eq!(decoder.zscii_to_char(155).unwrap(), 'А');
+        assert_eq!(decoder.zscii_to_char(223).unwrap(), chars[68]);
+        assert_eq!(decoder.zscii_to_char(251).unwrap(), chars[96]);
+        assert_eq!(decoder.zscii_to_char(252).unwrap(), '@');
+    }
+
+    #[test]
+    fn the_standard_table_matches_the_standard_from_i_circumflex_on() {
+        // Codes 198 to 223, which were one place early while Î was missing
+        let expected:Vec<char> = "ÎÔÛåÅøØãñõÃÑÕæÆçÇþðÞÐ£œŒ¡¿".chars().collect();
+        let mut data = Vec::new();
+        for z in 198..=223u16 {
+            data.extend(pack_zchars(&[5, 6, (z >> 5) as u8, (z & 0x1F) as u8, 5, 5]));
+        }
+        let decoder = Decoder::new(&StoryBuilder::new(5).bytes(0x900, &data).build()).unwrap();
+        for (i, c) in expected.iter().enumerate() {
+            assert_eq!(decoder.zscii_to_char(198 + i as u16).unwrap(), *c, "ZSCII {}", 198 + i);
+            assert_eq!(decoder.decode(0x900 + (4 * i)).unwrap(), c.to_string(), "ZSCII {}", 198 + i);
+        }
+        // The codes before the gap were unaffected
+        assert_eq!(decoder.zscii_to_char(197).unwrap(), 'Ê');
+    }
+
+    #[test]
+    fn both_zscii_paths_agree_at_the_end_of_the_table() {
+        // A ZSCII escape in A2 followed by the two 5-bit halves of the code, at 0x900
+        // for the last code in the table and at 0x904 for the one after it
+        let strings = |last: u16| {
+            let mut data = Vec::new();
+            for z in &[last, last + 1] {
+                data.extend(pack_zchars(&[5, 6, (z >> 5) as u8, (z & 0x1F) as u8, 5, 5]));
+            }
+            data
+        };
+
+        let standard = StoryBuilder::new(5).bytes(0x900, &strings(223)).build();
+        let mut custom = story_with_unicode_table(&['α', 'β', 'γ']).get_memory();
+        custom[0x900..0x908].copy_from_slice(&strings(157));
+        let custom = MemoryMap::try_from(custom).unwrap();
+
+        for (mem, last) in &[(standard, 223), (custom, 157)] {
+            let decoder = Decoder::new(mem).unwrap();
+            assert_ne!(decoder.zscii_to_char(*last).unwrap(), '@', "ZSCII {}", last);
+            assert_eq!(decoder.decode(0x900).unwrap(), decoder.zscii_to_char(*last).unwrap().to_string());
+            assert_eq!(decoder.zscii_to_char(last + 1).unwrap(), '@', "ZSCII {}", last + 1);
+            assert_eq!(decoder.decode(0x904).unwrap(), "@");
+        }
+    }
+
+    #[test]
+    fn v1_shifts_at_the_end_of_a_string_and_across_spaces() {
+        let decode = |zchars: &[u8]| {
+            let mem = StoryBuilder::new(1).bytes(0x900, &pack_zchars(zchars)).build();
+            Decoder::new(&mem).unwrap().decode(0x900).unwrap()
+        };
+
+        // Single shifts and shift locks with nothing after them
+        assert_eq!(decode(&[6, 7, 2]), "ab");
+        assert_eq!(decode(&[6, 7, 3]), "ab");
+        assert_eq!(decode(&[6, 7, 4]), "ab");
+        assert_eq!(decode(&[6, 7, 5]), "ab");
+
+        // A shift lock lasts across a space, and single shifts are relative to it
+        assert_eq!(decode(&[4, 6, 7, 0, 8, 5]), "AB C");
+        assert_eq!(decode(&[4, 2, 13, 6, 5, 6]), "6Aa");
+        assert_eq!(decode(&[5, 7, 0, 3, 8, 8]), "0 C1");
+    }
+
+    #[test]
+    fn encoding_uses_a_custom_alphabet() {
+        // Both letter rows reversed, and '@' where A2 normally has '0'
+        let table: Vec<u8> = "zyxwvutsrqponmlkjihgfedcba".bytes()
+            .chain("ZYXWVUTSRQPONMLKJIHGFEDCBA".bytes())
+            .chain("  @0123456789.,!?_#'\"/\\-:(".bytes())
+            .collect();
+        assert_eq!(table.len(), 78);
+        let mem = StoryBuilder::new(5).word(0x34, 0x700).bytes(0x700, &table).build();
+
+        let encoder = Encoder::new(&mem).unwrap();
+        assert_eq!(encoder.map_char('z'), Some((0, 6)));
+        assert_eq!(encoder.map_char('a'), Some((0, 31)));
+        assert_eq!(encoder.map_char('@'), Some((2, 8)));
+        assert_eq!(encoder.map_char('0'), Some((2, 9)));
+
+        let decoder = Decoder::new(&mem).unwrap();
+        for word in &["zap", "a@0", "mazes"] {
+            let entry: Vec<u8> = encoder.encode(word).unwrap().iter().flat_map(|w| w.to_be_bytes().to_vec()).collect();
+            assert_eq!(decoder.decode_dictionary_entry(&entry).unwrap(), *word);
+        }
+    }
+
+    #[test]
+    fn dictionary_entries_decode_without_padding() {
+        for (version, word, decoded) in &[(3, "north", "north"), (3, "ne", "ne"), (3, "northeast", "northe"),
+                                          (5, "northeast", "northeast"), (5, "ne", "ne")] {
+            let mem = StoryBuilder::new(*version).build();
+            let encoded: Vec<u8> = Encoder::new(&mem).unwrap().encode(word).unwrap().iter().flat_map(|w| w.to_be_bytes().to_vec()).collect();
+            assert_eq!(encoded.len(), if *version < 4 { 4 } else { 6 });
+            assert_eq!(Decoder::new(&mem).unwrap().decode_dictionary_entry(&encoded).unwrap(), *decoded, "V{} {}", version, word);
+        }
+    }
+}
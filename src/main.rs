@@ -8,111 +8,235 @@ extern crate simple_logger;
 extern crate rand;
 
 use std::convert::TryFrom;
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 use actix_web::{http, web, App, HttpRequest, HttpResponse, HttpServer, Result};
 use http::StatusCode;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use listenfd::ListenFd;
-use log::{debug, error};
+use log::{debug, error, warn};
 
 mod components;
 mod middleware;
 
 use components::InfocomError;
-use components::memory::{MemoryMap, ZByte, ZWord, ZValue};
+use components::memory::{MemoryMap, Version, ZByte, ZWord, ZValue};
+use components::config::{self, Config};
+use components::dictionary::Dictionary;
+use components::header::Header;
+use components::redis_connection::RedisPool;
 use components::session::Session;
 use components::text::{Decoder,Encoder};
-use components::object_table::ObjectTable;
-use components::state::{ FrameStack, Routine };
+use components::object_table::{Object, ObjectTable};
+use components::quetzal;
+use components::blorb::Blorb;
+use components::story::{Story, StorySource};
+use components::state::{ Frame, FrameStack, Routine, StepOutcome };
 use components::instruction;
-use components::interface::{ Curses, Interface };
+use components::interface::{ Buffered, Curses, Interface, StdioInterface };
 
-async fn new_session(_req: HttpRequest) -> HttpResponse {
-    let s = Session::new().unwrap();
-    HttpResponse::Ok()
-        .cookie(http::Cookie::build("session", format!("{}", &s.id)).finish())
-        .json(s)
+fn session_id(req: &HttpRequest) -> Result<&str, InfocomError> {
+    match req.headers().get("x-session") {
+        Some(id) => match id.to_str() {
+            Ok(id) => Ok(id),
+            Err(_) => Err(InfocomError::API(format!("Invalid session id")))
+        },
+        None => Err(InfocomError::API(format!("Missing session id")))
+    }
+}
+
+/// Parse a path parameter, reporting a malformed value as an API error
+fn path_param<T: std::str::FromStr>(req: &HttpRequest, name: &str) -> Result<T, InfocomError> {
+    match req.match_info().get(name).map(|v| v.parse()) {
+        Some(Ok(v)) => Ok(v),
+        _ => Err(InfocomError::API(format!("Invalid {}", name)))
+    }
+}
+
+async fn new_session(_req: HttpRequest, pool: web::Data<RedisPool>) -> HttpResponse {
+    match Session::new(&pool) {
+        Ok(s) => HttpResponse::Ok()
+                    .cookie(http::Cookie::build("session", format!("{}", &s.id)).finish())
+                    .json(s),
+        Err(e) => error_response(e)
+    }
 }
 
-async fn get_session(req: HttpRequest) -> HttpResponse {
-    let id = req.headers().get("x-session").unwrap().to_str().unwrap();
-    match Session::try_from(id) {
+async fn get_session(req: HttpRequest, pool: web::Data<RedisPool>) -> HttpResponse {
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return error_response(e)
+    };
+    match Session::try_from((pool.get_ref(), id)) {
         Ok(session) => {
             HttpResponse::Ok().json(session)
         },
-        Err(e) => HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string())
+        Err(e) => error_response(e)
+    }
+}
+
+/// Check an uploaded story against the size limit and the file length declared in its header
+fn validate_story(config: &Config, data: Vec<u8>) -> std::result::Result<MemoryMap, HttpResponse> {
+    if data.len() > config.max_story_size {
+        let e = InfocomError::API(format!("Story is {} bytes, the limit is {}", data.len(), config.max_story_size));
+        return Err(HttpResponse::build(StatusCode::PAYLOAD_TOO_LARGE).json(ErrorResponse::from(e)))
     }
+
+    let mem = MemoryMap::try_from(data).map_err(error_response)?;
+    // Measure the story image rather than the upload, which may be a .zblorb
+    let length = mem.len();
+    let file_length = Header::new(&mem).map_err(error_response)?.file_length();
+    // Early stories may not declare a length
+    if file_length > length {
+        return Err(error_response(InfocomError::API(format!("Story is {} bytes, the header declares {}", length, file_length))))
+    }
+
+    Ok(mem)
 }
 
-async fn new_story(req: HttpRequest, data: web::Bytes) -> HttpResponse {
+async fn new_story(req: HttpRequest, pool: web::Data<RedisPool>, config: web::Data<Config>, data: web::Bytes) -> HttpResponse {
      let name = req.match_info().get("name").unwrap();
-     let id = req.headers().get("x-session").unwrap().to_str().unwrap();
-     match Session::try_from(id) {
+     let id = match session_id(&req) {
+         Ok(id) => id,
+         Err(e) => return error_response(e)
+     };
+     match Session::try_from((pool.get_ref(), id)) {
          Ok(mut session) => {
-            if let Ok(mem) = MemoryMap::try_from(data.to_vec()) {
-                if let Err(e) = session.add_story(String::from(name), mem) {
+            match validate_story(&config, data.to_vec()) {
+                Ok(mem) => if let Err(e) = session.add_story(&pool, String::from(name), mem) {
                     error!("{}", e);
-                    HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string())
+                    error_response(e)
                 } else {
                     HttpResponse::Ok().json(session)
-                }
-            } else {
-                HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).finish()
+                },
+                Err(response) => response
             }
         },
-        Err(e) => HttpResponse::build(StatusCode::NOT_FOUND).body(e.to_string())
+        Err(e) => error_response(e)
      }
 }
 
+#[derive(Serialize, Debug)]
+struct ErrorResponse {
+    kind: String,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<usize>
+}
+
+impl From<InfocomError> for ErrorResponse {
+    fn from(e: InfocomError) -> ErrorResponse {
+        let message = e.to_string();
+        let (kind, address) = match e {
+            InfocomError::Memory(_) => ("Memory", None),
+            InfocomError::ReadViolation(a, _) => ("ReadViolation", Some(a)),
+            InfocomError::WriteViolation(a, _) => ("WriteViolation", Some(a)),
+            InfocomError::Text(_) => ("Text", None),
+            InfocomError::API(_) => ("API", None),
+            InfocomError::Session(_) => ("Session", None),
+            InfocomError::Version(_) => ("Version", None),
+            InfocomError::Redis(_) => ("Redis", None),
+            InfocomError::Unimplemented { address, .. } => ("Unimplemented", Some(address)),
+            InfocomError::OperandCount { address, .. } => ("OperandCount", Some(address))
+        };
+
+        ErrorResponse { kind: String::from(kind), message, address }
+    }
+}
+
+fn error_response(e: InfocomError) -> HttpResponse {
+    let status = match e {
+        InfocomError::ReadViolation(_, _) | InfocomError::WriteViolation(_, _) | InfocomError::API(_) => StatusCode::BAD_REQUEST,
+        InfocomError::Version(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        // A session or story that isn't there, as opposed to redis being unreachable
+        InfocomError::Session(_) => StatusCode::NOT_FOUND,
+        InfocomError::Redis(_) => StatusCode::SERVICE_UNAVAILABLE,
+        InfocomError::Unimplemented { .. } => StatusCode::NOT_IMPLEMENTED,
+        _ => StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    HttpResponse::build(status).json(ErrorResponse::from(e))
+}
+
 fn error(function: &str, error: InfocomError, address: usize) -> Result<HttpResponse> {
     error!("{}", error);
     error!("{} at ${:06x} FAILED", function, address);
-    Ok(HttpResponse::build(StatusCode::BAD_REQUEST).body(format!("Invalid {} at ${:06x}", function, address)))
+    Ok(error_response(error))
 }
 
-fn load_memory(id: &str, name: &str) -> Result<MemoryMap, InfocomError> {
-    Session::try_from(id)?.load(name)
+fn load_memory(pool: &RedisPool, id: &str, name: &str) -> Result<MemoryMap, InfocomError> {
+    Story::open(StorySource::Session { pool, id: String::from(id), name: String::from(name) })
 }
 
-fn read_from_memory<T>(req: HttpRequest, address: usize) -> Result<T, InfocomError>
+fn read_from_memory<T>(pool: &RedisPool, req: HttpRequest, address: usize) -> Result<T, InfocomError>
 where 
     T: ZValue
 {
     let name = req.match_info().get("name").unwrap();
-    if let Some(id) = req.headers().get("X-Session") {
-        let mem = load_memory(id.to_str().unwrap(), name)?;
-        let mut values = Vec::<u8>::new();
-        let bytes = T::size();
-        for i in 0..bytes {
-            match mem.get_byte(address + i) {
-                Ok(value) => values.push(value),
-                Err(e) => return Err(e)
-            }
+    let mem = load_memory(pool, session_id(&req)?, name)?;
+    let mut values = Vec::<u8>::new();
+    let bytes = T::size();
+    for i in 0..bytes {
+        match mem.get_byte(address + i) {
+            Ok(value) => values.push(value),
+            Err(e) => return Err(e)
         }
-        
-        Ok(T::new(&values))
-    } else {
-        Err(InfocomError::API(format!("Missing session id")))
     }
+    
+    Ok(T::new(&values))
 }
 
-async fn read_byte(req: HttpRequest) -> Result<HttpResponse> {
-    let address: usize = req.match_info().get("address").unwrap().parse().unwrap();
-    let value:Result<ZByte, InfocomError> = read_from_memory(req, address);
+async fn read_byte(req: HttpRequest, pool: web::Data<RedisPool>) -> Result<HttpResponse> {
+    let address: usize = match path_param(&req, "address") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let value:Result<ZByte, InfocomError> = read_from_memory(&pool, req, address);
     match value {
         Ok(v) => Ok(HttpResponse::Ok().json(v)),
         Err(e) => error("read_byte", e, address)
     }
 }
 
-async fn read_word(req: HttpRequest) -> Result<HttpResponse> {
-    let address: usize = req.match_info().get("address").unwrap().parse().unwrap();
-    let value:Result<ZWord, InfocomError> = read_from_memory(req, address);
+async fn read_word(req: HttpRequest, pool: web::Data<RedisPool>) -> Result<HttpResponse> {
+    let address: usize = match path_param(&req, "address") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let value:Result<ZWord, InfocomError> = read_from_memory(&pool, req, address);
     match value {
         Ok(v) => Ok(HttpResponse::Ok().json(v)),
         Err(e) => error("read_byte", e, address)
     }    
 }
+/// The largest range returned by a single bulk read
+const MAX_MEMORY_RANGE: usize = 4096;
+
+#[derive(Serialize)]
+struct MemoryRange {
+    start: usize,
+    len: usize,
+    data: String
+}
+
+/// Read `len` bytes as hex from anywhere in memory, including high memory above 64k
+async fn read_range(req: HttpRequest, pool: web::Data<RedisPool>) -> HttpResponse {
+    let name = req.match_info().get("name").unwrap();
+    let start: usize = match path_param(&req, "start") { Ok(v) => v, Err(e) => return error_response(e) };
+    let len = match path_param::<usize>(&req, "len") { Ok(v) => std::cmp::min(v, MAX_MEMORY_RANGE), Err(e) => return error_response(e) };
+
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return error_response(e)
+    };
+    match load_memory(&pool, id, name) {
+        Ok(mem) => {
+            let memory = mem.get_memory();
+            let end = match start.checked_add(len) {
+                Some(end) if end <= memory.len() => end,
+                Some(end) => return error_response(InfocomError::ReadViolation(end, memory.len())),
+                None => return error_response(InfocomError::ReadViolation(start, memory.len()))
+            };
+            let data = memory[start..end].iter().map(|b| format!("{:02x}", b)).collect();
+            HttpResponse::Ok().json(MemoryRange { start, len, data })
+        },
+        Err(e) => error_response(e)
+    }
+}
+
 fn type_from_values(values: &[u8]) -> &str {
     match values.len() {
         1 => "byte",
@@ -121,343 +245,364 @@ fn type_from_values(values: &[u8]) -> &str {
     }      
 }
 
-fn write_to_memory(req: HttpRequest, values: &[u8]) -> Result<HttpResponse> {
-    let address: usize = req.match_info().get("address").unwrap().parse().unwrap();
+fn write_to_memory(pool: &RedisPool, req: HttpRequest, values: &[u8]) -> Result<HttpResponse> {
+    let address: usize = match path_param(&req, "address") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
     let func = &format!("write_{}", type_from_values(values));
     let name = req.match_info().get("name").unwrap();
-    if let Some(id) = req.headers().get("X-Session") {
-        match Session::try_from(id.to_str().unwrap()) {
-            Ok(mut session) => {
-                match session.load(name) {
-                    Ok(mut mem) => {
-                        let mut index = address;
-                        for value in values {
-                            match mem.set_byte(index, *value) {
-                                Ok(_) => {
-                                    index = index + 1;
-                                },
-                                Err(e) => return error(func, e, address)
-                            }
-                        }
-                        match session.save(name, mem) {
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return Ok(error_response(e))
+    };
+    match Session::try_from((pool, id)) {
+        Ok(mut session) => {
+            match session.load(pool, name) {
+                Ok(mut mem) => {
+                    let mut index = address;
+                    for value in values {
+                        match mem.set_byte(index, *value) {
                             Ok(_) => {
-                                debug!("{}: ${:?} to ${:06x}", func, values, address);
-                                Ok(HttpResponse::Ok().finish()) 
+                                index = index + 1;
                             },
-                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
+                            Err(e) => return error(func, e, address)
                         }
-                    },
-                    Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                }
-            },
-            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-        }
-    } else {
-        Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
+                    }
+                    match session.save(pool, name, mem) {
+                        Ok(_) => {
+                            debug!("{}: ${:?} to ${:06x}", func, values, address);
+                            Ok(HttpResponse::Ok().finish()) 
+                        },
+                        Err(e) => Ok(error_response(e))
+                    }
+                },
+                Err(e) => Ok(error_response(e))
+            }
+        },
+        Err(e) => Ok(error_response(e))
     }
 }
 
-async fn write_byte(req: HttpRequest) -> Result<HttpResponse> {
-    let values: &[u8] = &vec![req.match_info().get("value").unwrap().parse().unwrap()];
-    write_to_memory(req, values)
+async fn write_byte(req: HttpRequest, pool: web::Data<RedisPool>) -> Result<HttpResponse> {
+    let value: u8 = match path_param(&req, "value") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let values: &[u8] = &vec![value];
+    write_to_memory(&pool, req, values)
 }
 
-async fn write_word(req: HttpRequest) -> Result<HttpResponse> {
-    let value: u16 = req.match_info().get("value").unwrap().parse().unwrap();
+async fn write_word(req: HttpRequest, pool: web::Data<RedisPool>) -> Result<HttpResponse> {
+    let value: u16 = match path_param(&req, "value") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
     let values = &vec![(value >> 8 & 0xFF) as u8, (value & 0xFF) as u8];
-    write_to_memory(req, values)
+    write_to_memory(&pool, req, values)
+}
+
+#[derive(Deserialize)]
+struct ReadTextQuery {
+    detail: Option<bool>
 }
 
-async fn read_text(req: HttpRequest) -> Result<HttpResponse> {
+#[derive(Serialize)]
+struct DecodedText {
+    text: String,
+    byte_length: usize,
+    zchars: Vec<u8>
+}
+
+async fn read_text(req: HttpRequest, pool: web::Data<RedisPool>, query: web::Query<ReadTextQuery>) -> Result<HttpResponse> {
     let name = req.match_info().get("name").unwrap();
-    let address:usize = req.match_info().get("address").unwrap().parse().unwrap();
-    match req.headers().get("X-Session") {
-        Some(id) => {
-            match load_memory(id.to_str().unwrap(), name) {
-                Ok(mem) => {
-                    match Decoder::new(&mem) {
-                        Ok(decoder) => match decoder.decode(address) {
-                            Ok(text) => Ok(HttpResponse::Ok().json(text)),
-                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                        },
-                        Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
+    let address: usize = match path_param(&req, "address") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return Ok(error_response(e))
+    };
+    match load_memory(&pool, id, name) {
+        Ok(mem) => {
+            match Decoder::new(&mem) {
+                Ok(decoder) => if query.detail.unwrap_or(false) {
+                    match decoder.decode_with_len(address) {
+                        Ok((text, byte_length)) => Ok(HttpResponse::Ok().json(DecodedText { text, byte_length, zchars: decoder.zchars(address) })),
+                        Err(e) => Ok(error_response(e))
+                    }
+                } else {
+                    match decoder.decode(address) {
+                        Ok(text) => Ok(HttpResponse::Ok().json(text)),
+                        Err(e) => Ok(error_response(e))
                     }
                 },
-                Err(_) => Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
+                Err(e) => Ok(error_response(e))
             }
         },
-        None => {
-            Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
-        }
+        Err(e) => Ok(error_response(e))
     }
 }
 
-async fn encode_text(req: HttpRequest) -> Result<HttpResponse> {
+async fn encode_text(req: HttpRequest, pool: web::Data<RedisPool>) -> Result<HttpResponse> {
     let name = req.match_info().get("name").unwrap();
     let string = req.match_info().get("string").unwrap();
-    match req.headers().get("X-Session") {
-        Some(id) => {
-            match load_memory(id.to_str().unwrap(), name) {
-                Ok(mem) => {
-                    match Encoder::new(&mem) {
-                        Ok(encoder) => match encoder.encode(string) {
-                            Ok(text) => Ok(HttpResponse::Ok().json(text)),
-                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                        },
-                        Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                    }
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return Ok(error_response(e))
+    };
+    match load_memory(&pool, id, name) {
+        Ok(mem) => {
+            match Encoder::new(&mem) {
+                Ok(encoder) => match encoder.encode(string) {
+                    Ok(text) => Ok(HttpResponse::Ok().json(text)),
+                    Err(e) => Ok(error_response(e))
                 },
-                Err(_) => Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
+                Err(e) => Ok(error_response(e))
             }
         },
-        None => {
-            Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
-        }
+        Err(e) => Ok(error_response(e))
     }
 }
 
-async fn get_object(req: HttpRequest) -> Result<HttpResponse> {
+/// An object with its attribute bitfield also listed as attribute numbers
+#[derive(Serialize)]
+struct ObjectView<'a> {
+    #[serde(flatten)]
+    object: &'a Object,
+    attribute_list: Vec<usize>
+}
+
+async fn get_object(req: HttpRequest, pool: web::Data<RedisPool>) -> Result<HttpResponse> {
     let name = req.match_info().get("name").unwrap();
-    let number:usize = req.match_info().get("number").unwrap().parse().unwrap();
-    match req.headers().get("X-Session") {
-        Some(id) => {
-            match load_memory(id.to_str().unwrap(), name) {
-                Ok(mut mem) => {
-                    match ObjectTable::new(&mut mem) {
-                        Ok(ot) => match ot.get_object(&mem, number) {
-                            Ok(obj) => Ok(HttpResponse::Ok().json(obj)),
-                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                        },
-                        Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                    }
+    let number: usize = match path_param(&req, "number") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return Ok(error_response(e))
+    };
+    match load_memory(&pool, id, name) {
+        Ok(mut mem) => {
+            match ObjectTable::new(&mut mem) {
+                Ok(ot) => match ot.get_object(&mem, number) {
+                    Ok(obj) => Ok(HttpResponse::Ok().json(ObjectView { object: &obj, attribute_list: obj.attributes_as_vec() })),
+                    Err(e) => Ok(error_response(e))
                 },
-                Err(_) => Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
+                Err(e) => Ok(error_response(e))
             }
         },
-        None => {
-            Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
-        }
+        Err(e) => Ok(error_response(e))
     }
 }
 
-async fn has_object_attribute(req: HttpRequest) -> Result<HttpResponse> {
+async fn has_object_attribute(req: HttpRequest, pool: web::Data<RedisPool>) -> Result<HttpResponse> {
     let name = req.match_info().get("name").unwrap();
-    let number:usize = req.match_info().get("number").unwrap().parse().unwrap();
-    let attribute:usize = req.match_info().get("attribute").unwrap().parse().unwrap();
-    match req.headers().get("X-Session") {
-        Some(id) => match load_memory(id.to_str().unwrap(), name) {
-                        Ok(mut mem) => {
-                            match ObjectTable::new(&mut mem) {
-                                Ok(ot) => match ot.has_attribute(&mem, number, attribute) {
-                                    Ok(r) => Ok(HttpResponse::Ok().json(r)),
-                                    Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                                },
-                                Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                            }
-                        },
-                        Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                    },
-        None => Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
+    let number: usize = match path_param(&req, "number") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let attribute: usize = match path_param(&req, "attribute") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return Ok(error_response(e))
+    };
+    match load_memory(&pool, id, name) {
+        Ok(mut mem) => {
+            match ObjectTable::new(&mut mem) {
+                Ok(ot) => match ot.has_attribute(&mem, number, attribute) {
+                    Ok(r) => Ok(HttpResponse::Ok().json(r)),
+                    Err(e) => Ok(error_response(e))
+                },
+                Err(e) => Ok(error_response(e))
+            }
+        },
+        Err(e) => Ok(error_response(e))
     }
 }
 
-async fn set_object_attribute(req: HttpRequest) -> Result<HttpResponse> {
+async fn set_object_attribute(req: HttpRequest, pool: web::Data<RedisPool>) -> Result<HttpResponse> {
     let name = req.match_info().get("name").unwrap();
-    let number:usize = req.match_info().get("number").unwrap().parse().unwrap();
-    let attribute:usize = req.match_info().get("attribute").unwrap().parse().unwrap();
-    if let Some(id) = req.headers().get("X-Session") {
-        match Session::try_from(id.to_str().unwrap()) {
-            Ok(mut session) => {
-                match session.load(name) {
-                    Ok(mut mem) => {
-                        match FrameStack::new(&mut mem) {
-                            Ok(mut f) => {
-                                match ObjectTable::new(f.get_memory()) {
-                                    Ok(mut ot) => match ot.set_attribute(&mut f, number, attribute) {
-                                        Ok(o) => match session.save(name, mem) {
-                                            Ok(_) => {
-                                                Ok(HttpResponse::Ok().json(o)) 
-                                            },
-                                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
+    let number: usize = match path_param(&req, "number") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let attribute: usize = match path_param(&req, "attribute") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return Ok(error_response(e))
+    };
+    match Session::try_from((pool.get_ref(), id)) {
+        Ok(mut session) => {
+            match session.load(&pool, name) {
+                Ok(mut mem) => {
+                    match FrameStack::new(&mut mem) {
+                        Ok(mut f) => {
+                            match ObjectTable::new(f.get_memory()) {
+                                Ok(mut ot) => match ot.set_attribute(&mut f, number, attribute) {
+                                    Ok(o) => match session.save(&pool, name, mem) {
+                                        Ok(_) => {
+                                            Ok(HttpResponse::Ok().json(o)) 
                                         },
-                                        Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
+                                        Err(e) => Ok(error_response(e))
                                     },
-                                    Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                                }
-                            },
-                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                        }
-                    },
-                    Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                }
-            },
-            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-        }
-    } else {
-        Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
+                                    Err(e) => Ok(error_response(e))
+                                },
+                                Err(e) => Ok(error_response(e))
+                            }
+                        },
+                        Err(e) => Ok(error_response(e))
+                    }
+                },
+                Err(e) => Ok(error_response(e))
+            }
+        },
+        Err(e) => Ok(error_response(e))
     }
 }
 
-async fn clear_object_attribute(req: HttpRequest) -> Result<HttpResponse> {
+async fn clear_object_attribute(req: HttpRequest, pool: web::Data<RedisPool>) -> Result<HttpResponse> {
     let name = req.match_info().get("name").unwrap();
-    let number:usize = req.match_info().get("number").unwrap().parse().unwrap();
-    let attribute:usize = req.match_info().get("attribute").unwrap().parse().unwrap();
-    if let Some(id) = req.headers().get("X-Session") {
-        match Session::try_from(id.to_str().unwrap()) {
-            Ok(mut session) => {
-                match session.load(name) {
-                    Ok(mut mem) => {
-                        match FrameStack::new(&mut mem) {
-                            Ok(mut f) => {
-                                match ObjectTable::new(f.get_memory()) {
-                                    Ok(mut ot) => match ot.clear_attribute(&mut f, number, attribute) {
-                                        Ok(o) => match session.save(name, mem) {
-                                            Ok(_) => {
-                                                Ok(HttpResponse::Ok().json(o)) 
-                                            },
-                                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
+    let number: usize = match path_param(&req, "number") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let attribute: usize = match path_param(&req, "attribute") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return Ok(error_response(e))
+    };
+    match Session::try_from((pool.get_ref(), id)) {
+        Ok(mut session) => {
+            match session.load(&pool, name) {
+                Ok(mut mem) => {
+                    match FrameStack::new(&mut mem) {
+                        Ok(mut f) => {
+                            match ObjectTable::new(f.get_memory()) {
+                                Ok(mut ot) => match ot.clear_attribute(&mut f, number, attribute) {
+                                    Ok(o) => match session.save(&pool, name, mem) {
+                                        Ok(_) => {
+                                            Ok(HttpResponse::Ok().json(o)) 
                                         },
-                                        Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
+                                        Err(e) => Ok(error_response(e))
                                     },
-                                    Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                                }
-                            },
-                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                        }
-                    },
-                    Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                }
-            },
-            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-        }
-    } else {
-        Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
+                                    Err(e) => Ok(error_response(e))
+                                },
+                                Err(e) => Ok(error_response(e))
+                            }
+                        },
+                        Err(e) => Ok(error_response(e))
+                    }
+                },
+                Err(e) => Ok(error_response(e))
+            }
+        },
+        Err(e) => Ok(error_response(e))
     }
 }
 
-async fn get_object_property(req: HttpRequest) -> Result<HttpResponse> {
+async fn get_object_property(req: HttpRequest, pool: web::Data<RedisPool>) -> Result<HttpResponse> {
     let name = req.match_info().get("name").unwrap();
-    let number:usize = req.match_info().get("number").unwrap().parse().unwrap();
-    let property:usize = req.match_info().get("property").unwrap().parse().unwrap();
-    match req.headers().get("X-Session") {
-        Some(id) => match load_memory(id.to_str().unwrap(), name) {
-                        Ok(mut mem) => {
-                            match ObjectTable::new(&mut mem) {
-                                Ok(ot) => match ot.get_property_value(&mem, number, property) {
-                                    Ok(data) => Ok(HttpResponse::Ok().json(data)),
-                                    Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                                },
-                                Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                            }
-                        },
-                        Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                    },
-        None => Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
+    let number: usize = match path_param(&req, "number") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let property: usize = match path_param(&req, "property") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return Ok(error_response(e))
+    };
+    match load_memory(&pool, id, name) {
+        Ok(mut mem) => {
+            match ObjectTable::new(&mut mem) {
+                Ok(ot) => match ot.get_property_value(&mem, number, property) {
+                    Ok(data) => Ok(HttpResponse::Ok().json(data)),
+                    Err(e) => Ok(error_response(e))
+                },
+                Err(e) => Ok(error_response(e))
+            }
+        },
+        Err(e) => Ok(error_response(e))
     }
 }
 
-async fn put_object_property(req: HttpRequest) -> Result<HttpResponse> {
+async fn put_object_property(req: HttpRequest, pool: web::Data<RedisPool>) -> Result<HttpResponse> {
     let name = req.match_info().get("name").unwrap();
-    let number:usize = req.match_info().get("number").unwrap().parse().unwrap();
-    let property:usize = req.match_info().get("property").unwrap().parse().unwrap();
-    let value:u16 = req.match_info().get("value").unwrap().parse().unwrap();
-    if let Some(id) = req.headers().get("X-Session") {
-        match Session::try_from(id.to_str().unwrap()) {
-            Ok(mut session) => {
-                match session.load(name) {
-                    Ok(mut mem) => {
-                        match FrameStack::new(&mut mem) {
-                            Ok(mut f) => {
-                                match ObjectTable::new(f.get_memory()) {
-                                    Ok(mut ot) => match ot.put_property_data(&mut f, number, property, value) {
-                                        Ok(o) => match session.save(name, mem) {
-                                            Ok(_) => Ok(HttpResponse::Ok().json(o)), 
-                                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                                        },
-                                        Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
+    let number: usize = match path_param(&req, "number") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let property: usize = match path_param(&req, "property") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let value: u16 = match path_param(&req, "value") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return Ok(error_response(e))
+    };
+    match Session::try_from((pool.get_ref(), id)) {
+        Ok(mut session) => {
+            match session.load(&pool, name) {
+                Ok(mut mem) => {
+                    match FrameStack::new(&mut mem) {
+                        Ok(mut f) => {
+                            match ObjectTable::new(f.get_memory()) {
+                                Ok(mut ot) => match ot.put_property_data(&mut f, number, property, value) {
+                                    Ok(o) => match session.save(&pool, name, mem) {
+                                        Ok(_) => Ok(HttpResponse::Ok().json(o)), 
+                                        Err(e) => Ok(error_response(e))
                                     },
-                                    Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                                }
-                            },
-                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                        }
-                    },
-                    Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                }
-            },
-            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-        }
-    } else { 
-        Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
+                                    Err(e) => Ok(error_response(e))
+                                },
+                                Err(e) => Ok(error_response(e))
+                            }
+                        },
+                        Err(e) => Ok(error_response(e))
+                    }
+                },
+                Err(e) => Ok(error_response(e))
+            }
+        },
+        Err(e) => Ok(error_response(e))
     }
 }
 
-async fn remove_object(req: HttpRequest) -> Result<HttpResponse> {
+async fn remove_object(req: HttpRequest, pool: web::Data<RedisPool>) -> Result<HttpResponse> {
     let name = req.match_info().get("name").unwrap();
-    let number:usize = req.match_info().get("number").unwrap().parse().unwrap();
-    if let Some(id) = req.headers().get("X-Session") {
-        match Session::try_from(id.to_str().unwrap()) {
-            Ok(mut session) => {
-                match session.load(name) {
-                    Ok(mut mem) => {
-                        match FrameStack::new(&mut mem) {
-                            Ok(mut f) => {
-                                match ObjectTable::new(f.get_memory()) {
-                                    Ok(mut ot) => match ot.remove_object(&mut f, number) {
-                                        Ok(o) => match session.save(name, mem) {
-                                            Ok(_) => Ok(HttpResponse::Ok().json(o)), 
-                                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                                        },
-                                        Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
+    let number: usize = match path_param(&req, "number") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return Ok(error_response(e))
+    };
+    match Session::try_from((pool.get_ref(), id)) {
+        Ok(mut session) => {
+            match session.load(&pool, name) {
+                Ok(mut mem) => {
+                    match FrameStack::new(&mut mem) {
+                        Ok(mut f) => {
+                            match ObjectTable::new(f.get_memory()) {
+                                Ok(mut ot) => match ot.remove_object(&mut f, number) {
+                                    Ok(o) => match session.save(&pool, name, mem) {
+                                        Ok(_) => Ok(HttpResponse::Ok().json(o)), 
+                                        Err(e) => Ok(error_response(e))
                                     },
-                                    Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                                }
-                            },
-                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                        }
-                    },
-                    Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                }
-            },
-            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-        }
-    } else { 
-        Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
+                                    Err(e) => Ok(error_response(e))
+                                },
+                                Err(e) => Ok(error_response(e))
+                            }
+                        },
+                        Err(e) => Ok(error_response(e))
+                    }
+                },
+                Err(e) => Ok(error_response(e))
+            }
+        },
+        Err(e) => Ok(error_response(e))
     }
 }
 
-async fn insert_object(req: HttpRequest) -> Result<HttpResponse> {
+async fn insert_object(req: HttpRequest, pool: web::Data<RedisPool>) -> Result<HttpResponse> {
     let name = req.match_info().get("name").unwrap();
-    let parent:usize = req.match_info().get("parent").unwrap().parse().unwrap();
-    let number:usize = req.match_info().get("number").unwrap().parse().unwrap();
-    if let Some(id) = req.headers().get("X-Session") {
-        match Session::try_from(id.to_str().unwrap()) {
-            Ok(mut session) => {
-                match session.load(name) {
-                    Ok(mut mem) => {
-                        match FrameStack::new(&mut mem) {
-                            Ok(mut f) => {
-                                match ObjectTable::new(f.get_memory()) {
-                                    Ok(mut ot) => match ot.insert_object(&mut f, number, parent) {
-                                        Ok(o) => match session.save(name, mem) {
-                                            Ok(_) => Ok(HttpResponse::Ok().json(o)), 
-                                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                                        },
-                                        Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
+    let parent: usize = match path_param(&req, "parent") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let number: usize = match path_param(&req, "number") { Ok(v) => v, Err(e) => return Ok(error_response(e)) };
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return Ok(error_response(e))
+    };
+    match Session::try_from((pool.get_ref(), id)) {
+        Ok(mut session) => {
+            match session.load(&pool, name) {
+                Ok(mut mem) => {
+                    match FrameStack::new(&mut mem) {
+                        Ok(mut f) => {
+                            match ObjectTable::new(f.get_memory()) {
+                                Ok(mut ot) => match ot.insert_object(&mut f, number, parent) {
+                                    Ok(o) => match session.save(&pool, name, mem) {
+                                        Ok(_) => Ok(HttpResponse::Ok().json(o)), 
+                                        Err(e) => Ok(error_response(e))
                                     },
-                                    Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                                }
-                            },
-                            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                        }
-                    },
-                    Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-                }
-            },
-            Err(e) => Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()))
-        }
-    } else { 
-        Ok(HttpResponse::build(StatusCode::NOT_FOUND).finish())
+                                    Err(e) => Ok(error_response(e))
+                                },
+                                Err(e) => Ok(error_response(e))
+                            }
+                        },
+                        Err(e) => Ok(error_response(e))
+                    }
+                },
+                Err(e) => Ok(error_response(e))
+            }
+        },
+        Err(e) => Ok(error_response(e))
     }
 }
 
@@ -469,56 +614,354 @@ struct ObjectTreeEntry {
     children: Vec<ObjectTreeEntry>
 }
 
-fn build_tree_entry(mem: &MemoryMap, ot: &ObjectTable, number: usize) -> ObjectTreeEntry {
-    let o = ot.get_object(mem, number).unwrap();
+#[derive(Serialize, Debug)]
+struct ObjectTree {
+    tree: Vec<ObjectTreeEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>
+}
+
+#[derive(Deserialize)]
+struct ObjectTreeQuery {
+    start: Option<usize>,
+    depth: Option<usize>
+}
+
+const DEFAULT_TREE_DEPTH: usize = 32;
+
+fn build_tree_entry(mem: &MemoryMap, ot: &ObjectTable, number: usize, depth: usize, placed: &mut HashSet<usize>, warnings: &mut Vec<String>) -> Result<ObjectTreeEntry, InfocomError> {
+    let o = ot.get_object(mem, number)?;
     let mut c = o.get_child();
     let mut children = Vec::new();
 
-    while c != 0 {
-        let o_c = ot.get_object(mem, c as usize).unwrap();
-        children.push(build_tree_entry(mem, ot, c as usize));
-        c = o_c.get_sibling();
+    if depth == 0 {
+        if c != 0 {
+            warnings.push(format!("Depth limit reached at object {}", number));
+        }
+    } else {
+        while c != 0 {
+            // An object that has already been placed means the table has a cycle
+            if !placed.insert(c as usize) {
+                warn!("Cycle detected: object {} already placed in tree", c);
+                warnings.push(format!("Cycle detected at object {} under object {}", c, number));
+                break;
+            }
+            let o_c = ot.get_object(mem, c as usize)?;
+            children.push(build_tree_entry(mem, ot, c as usize, depth - 1, placed, warnings)?);
+            c = o_c.get_sibling();
+        }
     }
 
-    ObjectTreeEntry { number: number as u16, short_name: o.get_short_name(), children }
+    Ok(ObjectTreeEntry { number: number as u16, short_name: o.get_short_name(), children })
 }
 
-async fn object_tree(req: HttpRequest) -> HttpResponse {
+async fn object_tree(req: HttpRequest, pool: web::Data<RedisPool>, query: web::Query<ObjectTreeQuery>) -> HttpResponse {
     let name = req.match_info().get("name").unwrap();
-    let end:usize = req.match_info().get("end").unwrap().parse().unwrap();
-    if let Some(id) = req.headers().get("X-Session") {
-        match Session::try_from(id.to_str().unwrap()) {
-            Ok(mut session) => {
-                match session.load(name) {
-                    Ok(mut mem) => {
-                        // Find all children of the root
-                        let mut placed = HashSet::new();
-                        let mut tree = Vec::new();
-                        match ObjectTable::new(&mut mem) {
-                            Ok(ot) => {
-                                for i in 1..(end + 1) {
-                                    if let Ok(o) = ot.get_object(&mem, i) {
-                                        if !placed.contains(&i) && o.get_parent() == 0 {
-                                            placed.insert(i);
-                                            tree.push(build_tree_entry(&mem, &ot, i));
+    let end: usize = match path_param(&req, "end") { Ok(v) => v, Err(e) => return error_response(e) };
+    let start = std::cmp::max(1, query.start.unwrap_or(1));
+    let depth = query.depth.unwrap_or(DEFAULT_TREE_DEPTH);
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return error_response(e)
+    };
+    match Session::try_from((pool.get_ref(), id)) {
+        Ok(mut session) => {
+            match session.load(&pool, name) {
+                Ok(mut mem) => {
+                    // Find all children of the root
+                    let mut placed = HashSet::new();
+                    let mut tree = Vec::new();
+                    let mut warnings = Vec::new();
+                    match ObjectTable::new(&mut mem) {
+                        Ok(ot) => {
+                            for i in start..(end + 1) {
+                                if let Ok(o) = ot.get_object(&mem, i) {
+                                    if !placed.contains(&i) && o.get_parent() == 0 {
+                                        placed.insert(i);
+                                        match build_tree_entry(&mem, &ot, i, depth, &mut placed, &mut warnings) {
+                                            Ok(entry) => tree.push(entry),
+                                            Err(e) => return error_response(e)
                                         }
-                                    } else {
-                                        // Premature end of object table?
-                                        break;
                                     }
+                                } else {
+                                    // Premature end of object table?
+                                    break;
                                 }
-                                HttpResponse::Ok().json(tree)
-                            },
-                            Err(e) => HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string())
-                        }
+                            }
+                            HttpResponse::Ok().json(ObjectTree { tree, warnings })
+                        },
+                        Err(e) => error_response(e)
+                    }
+                },
+                Err(e) => error_response(e)
+            }
+        },
+        Err(e) => error_response(e)
+    }
+}
+
+#[derive(Serialize)]
+struct HeaderInfo {
+    version: u8,
+    release: u16,
+    serial: String,
+    dynamic_size: usize,
+    static_start: usize,
+    high_start: usize,
+    dictionary: usize,
+    object_table: usize,
+    globals: usize,
+    abbreviations: usize,
+    checksum: u16
+}
+
+impl From<&Header> for HeaderInfo {
+    fn from(header: &Header) -> HeaderInfo {
+        let Version::V(version) = header.version();
+        HeaderInfo { version,
+                     release: header.release(),
+                     serial: String::from(header.serial()),
+                     dynamic_size: header.static_memory_base(),
+                     static_start: header.static_memory_base(),
+                     high_start: header.high_memory_base(),
+                     dictionary: header.dictionary(),
+                     object_table: header.object_table(),
+                     globals: header.global_variables(),
+                     abbreviations: header.abbreviations(),
+                     checksum: header.checksum() }
+    }
+}
+
+async fn get_header(req: HttpRequest, pool: web::Data<RedisPool>) -> HttpResponse {
+    let name = req.match_info().get("name").unwrap();
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return error_response(e)
+    };
+    match load_memory(&pool, id, name) {
+        Ok(mem) => match Header::new(&mem) {
+            Ok(header) => HttpResponse::Ok().json(HeaderInfo::from(&header)),
+            Err(e) => error_response(e)
+        },
+        Err(e) => error_response(e)
+    }
+}
+
+#[derive(Deserialize)]
+struct SaveFileQuery {
+    pc: usize
+}
+
+#[derive(Serialize)]
+struct RestoreResponse {
+    pc: usize
+}
+
+/// Download the session's dynamic memory as a Quetzal save resuming at `pc`.  The save
+/// holds only the dummy outer frame, so it can only resume in another interpreter from
+/// code running in the main routine.
+async fn export_savefile(req: HttpRequest, pool: web::Data<RedisPool>, query: web::Query<SaveFileQuery>) -> HttpResponse {
+    let name = req.match_info().get("name").unwrap();
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return error_response(e)
+    };
+    match load_memory(&pool, id, name) {
+        Ok(mem) => match quetzal::export(&mem, query.pc) {
+            Ok(data) => HttpResponse::Ok()
+                            .content_type("application/octet-stream")
+                            .header("Content-Disposition", format!("attachment; filename=\"{}.qzl\"", name))
+                            .body(data),
+            Err(e) => error_response(e)
+        },
+        Err(e) => error_response(e)
+    }
+}
+
+/// Restore the session's dynamic memory from an uploaded Quetzal save, returning the pc
+/// to resume from.  Saved call frames are ignored and the call stack left by the last run
+/// is discarded.
+async fn import_savefile(req: HttpRequest, pool: web::Data<RedisPool>, data: web::Bytes) -> HttpResponse {
+    let name = req.match_info().get("name").unwrap();
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return error_response(e)
+    };
+    match Session::try_from((pool.get_ref(), id)) {
+        Ok(mut session) => {
+            match session.load(&pool, name) {
+                Ok(mut mem) => match quetzal::import(&mut mem, &data) {
+                    Ok(pc) => match session.save_state(&pool, name, mem, None) {
+                        Ok(_) => HttpResponse::Ok().json(RestoreResponse { pc }),
+                        Err(e) => error_response(e)
                     },
-                    Err(e) => HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string())
-                }
-            },
-            Err(e) => HttpResponse::build(StatusCode::NOT_FOUND).body(e.to_string())
-        }
-    } else {
-        HttpResponse::build(StatusCode::NOT_FOUND).finish()
+                    Err(e) => error_response(e)
+                },
+                Err(e) => error_response(e)
+            }
+        },
+        Err(e) => error_response(e)
+    }
+}
+
+#[derive(Serialize)]
+struct DictionaryListing {
+    separators: Vec<char>,
+    entries: Vec<String>
+}
+
+async fn get_dictionary(req: HttpRequest, pool: web::Data<RedisPool>) -> HttpResponse {
+    let name = req.match_info().get("name").unwrap();
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return error_response(e)
+    };
+    match load_memory(&pool, id, name) {
+        Ok(mem) => {
+            match Dictionary::new(&mem) {
+                Ok(d) => match d.entries(&mem) {
+                    Ok(words) => {
+                        let mut separators:Vec<char> = d.separators().iter().cloned().collect();
+                        separators.sort();
+                        HttpResponse::Ok().json(DictionaryListing { separators, entries: words.into_iter().map(|w| w.text).collect() })
+                    },
+                    Err(e) => error_response(e)
+                },
+                Err(e) => error_response(e)
+            }
+        },
+        Err(e) => error_response(e)
+    }
+}
+
+#[derive(Serialize)]
+struct Abbreviation {
+    table: usize,
+    index: usize,
+    text: String
+}
+
+async fn get_abbreviations(req: HttpRequest, pool: web::Data<RedisPool>) -> HttpResponse {
+    let name = req.match_info().get("name").unwrap();
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return error_response(e)
+    };
+    match load_memory(&pool, id, name) {
+        Ok(mem) => {
+            match Decoder::new(&mem).and_then(|d| d.abbreviations()) {
+                Ok(a) => HttpResponse::Ok().json(a.into_iter().map(|(table, index, text)| Abbreviation { table, index, text }).collect::<Vec<Abbreviation>>()),
+                Err(e) => error_response(e)
+            }
+        },
+        Err(e) => error_response(e)
+    }
+}
+
+#[derive(Serialize)]
+struct StateView<'a> {
+    pc: usize,
+    depth: usize,
+    frames: Vec<&'a Frame>
+}
+
+/// The call stack left by the last run, or the state a new run starts from if the story
+/// hasn't been run
+async fn get_state(req: HttpRequest, pool: web::Data<RedisPool>) -> HttpResponse {
+    let name = req.match_info().get("name").unwrap();
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return error_response(e)
+    };
+    match Session::try_from((pool.get_ref(), id)) {
+        Ok(mut session) => {
+            match session.load(&pool, name) {
+                Ok(mut mem) => {
+                    match FrameStack::new(&mut mem) {
+                        Ok(mut f) => {
+                            if let Some(frames) = session.state(name) {
+                                if let Err(e) = f.set_frames(frames.clone()) {
+                                    return error_response(e)
+                                }
+                            }
+                            HttpResponse::Ok().json(StateView { pc: f.pc(), depth: f.frame_depth(), frames: f.frames() })
+                        },
+                        Err(e) => error_response(e)
+                    }
+                },
+                Err(e) => error_response(e)
+            }
+        },
+        Err(e) => error_response(e)
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct GlobalVariable {
+    index: u8,
+    value: u16
+}
+
+async fn get_globals(req: HttpRequest, pool: web::Data<RedisPool>) -> HttpResponse {
+    let name = req.match_info().get("name").unwrap();
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return error_response(e)
+    };
+    match load_memory(&pool, id, name) {
+        Ok(mut mem) => {
+            match FrameStack::new(&mut mem) {
+                Ok(mut f) => {
+                    let mut globals = Vec::new();
+                    for i in 0..240 {
+                        match f.get_variable(i as u8 + 16, false) {
+                            Ok(value) => globals.push(GlobalVariable { index: i as u8, value }),
+                            Err(e) => return error_response(e)
+                        }
+                    }
+                    HttpResponse::Ok().json(globals)
+                },
+                Err(e) => error_response(e)
+            }
+        },
+        Err(e) => error_response(e)
+    }
+}
+
+async fn set_global(req: HttpRequest, pool: web::Data<RedisPool>) -> HttpResponse {
+    let name = req.match_info().get("name").unwrap();
+    let (index, value):(u8, u16) = match (req.match_info().get("index").unwrap().parse(), req.match_info().get("value").unwrap().parse()) {
+        (Ok(i), Ok(v)) => (i, v),
+        _ => return error_response(InfocomError::API(format!("Invalid global variable index or value")))
+    };
+    if index > 239 {
+        return error_response(InfocomError::API(format!("Invalid global variable index: {}", index)))
+    }
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return error_response(e)
+    };
+    match Session::try_from((pool.get_ref(), id)) {
+        Ok(mut session) => {
+            match session.load(&pool, name) {
+                Ok(mut mem) => {
+                    match FrameStack::new(&mut mem) {
+                        Ok(mut f) => {
+                            if let Err(e) = f.set_variable(index + 16, value, false) {
+                                return error_response(e)
+                            }
+                        },
+                        Err(e) => return error_response(e)
+                    }
+                    match session.save(&pool, name, mem) {
+                        Ok(_) => HttpResponse::Ok().json(GlobalVariable { index, value }),
+                        Err(e) => error_response(e)
+                    }
+                },
+                Err(e) => error_response(e)
+            }
+        },
+        Err(e) => error_response(e)
     }
 }
 
@@ -545,26 +988,26 @@ async fn object_tree(req: HttpRequest) -> HttpResponse {
 //     }
 // }
 
-async fn get_routine(req: HttpRequest) -> HttpResponse {
+async fn get_routine(req: HttpRequest, pool: web::Data<RedisPool>) -> HttpResponse {
     let name = req.match_info().get("name").unwrap();
-    let address:usize = req.match_info().get("address").unwrap().parse().unwrap();
-    if let Some(id) = req.headers().get("X-Session") {
-        match Session::try_from(id.to_str().unwrap()) {
-            Ok(mut session) => {
-                match session.load(name) {
-                    Ok(mut mem) => {
-                        match Routine::new(&mut mem, address) {
-                            Ok(r) => HttpResponse::Ok().json(r),
-                            Err(e) => HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string())
-                        }
-                    },
-                    Err(e) => HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string())
-                }
-            },
-            Err(e) => HttpResponse::build(StatusCode::NOT_FOUND).body(e.to_string())
-        }
-    } else {
-        HttpResponse::build(StatusCode::NOT_FOUND).finish()
+    let address: usize = match path_param(&req, "address") { Ok(v) => v, Err(e) => return error_response(e) };
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return error_response(e)
+    };
+    match Session::try_from((pool.get_ref(), id)) {
+        Ok(mut session) => {
+            match session.load(&pool, name) {
+                Ok(mut mem) => {
+                    match Routine::new(&mut mem, address) {
+                        Ok(r) => HttpResponse::Ok().json(r),
+                        Err(e) => error_response(e)
+                    }
+                },
+                Err(e) => error_response(e)
+            }
+        },
+        Err(e) => error_response(e)
     }
 }
 
@@ -604,121 +1047,754 @@ async fn get_routine(req: HttpRequest) -> HttpResponse {
 //     }
 // }
 
-async fn run(req: HttpRequest) -> HttpResponse {
+#[derive(Deserialize)]
+struct RunQuery {
+    max: Option<usize>
+}
+
+const DEFAULT_RUN_INSTRUCTIONS: usize = 10000;
+
+#[derive(Serialize)]
+struct RunResponse {
+    pc: usize,
+    instructions: usize,
+    awaiting_input: bool,
+    output: String
+}
+
+/// Each line of the request body is queued as a line of input
+async fn run(req: HttpRequest, pool: web::Data<RedisPool>, query: web::Query<RunQuery>, body: String) -> HttpResponse {
     let name = req.match_info().get("name").unwrap();
-    let mut address:usize = req.match_info().get("address").unwrap().parse().unwrap();
-    let mut interface = Curses::new();
-    if let Some(id) = req.headers().get("X-Session") {
-        match Session::try_from(id.to_str().unwrap()) {
-            Ok(mut session) => {
-                match session.load(name) {
-                    Ok(mut mem) => {
-                        match FrameStack::new(&mut mem) {
-                            Ok(mut f) => {
-                                loop {            
-                                    match instruction::decode_instruction(&f, address) {
-                                        Ok(mut i) => {
-                                            match i.execute(&mut f, &mut interface) {
-                                                Ok(r) => address = r,
-                                                Err(e) => {
-                                                    //interface.end();
-                                                    match session.save(name, mem) {
-                                                        Ok(_) => return HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string()),
-                                                        Err(e2) => return HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(format!("{}\n{}", e.to_string(), e2.to_string()))
-                                                    }
-                                                }
-                                            }
-                                        },
-                                        Err(e) => return HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string())                            
-                                    }
+    let address: usize = match path_param(&req, "address") { Ok(v) => v, Err(e) => return error_response(e) };
+    let max = query.max.unwrap_or(DEFAULT_RUN_INSTRUCTIONS);
+    let mut interface = Buffered::new(body.lines().map(|l| String::from(l)).collect());
+    let id = match session_id(&req) {
+        Ok(id) => id,
+        Err(e) => return error_response(e)
+    };
+    match Session::try_from((pool.get_ref(), id)) {
+        Ok(mut session) => {
+            match session.load(&pool, name) {
+                Ok(mut mem) => {
+                    let (result, frames) = match FrameStack::new(&mut mem) {
+                        Ok(mut f) => {
+                            // Continue from the call stack left by the last run
+                            if let Some(frames) = session.state(name) {
+                                if let Err(e) = f.set_frames(frames.clone()) {
+                                    return error_response(e)
                                 }
-                            },
-                            Err(e) => return HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string())
-                        } 
-                    },
-                    Err(e) => HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(e.to_string())
-                }
-            },
-            Err(e) => HttpResponse::build(StatusCode::NOT_FOUND).body(e.to_string())
-        }
-    } else {
-        HttpResponse::build(StatusCode::NOT_FOUND).finish()
+                            }
+                            let result = instruction::run_n(&mut f, &mut interface, address, max);
+                            (result, f.frames().into_iter().cloned().collect())
+                        },
+                        Err(e) => return error_response(e)
+                    };
+
+                    // Persist memory and the call stack whether or not the run ended in an error
+                    if let Err(e) = session.save_state(&pool, name, mem, Some(frames)) {
+                        return error_response(e)
+                    }
+
+                    match result {
+                        Ok(r) => HttpResponse::Ok().json(RunResponse { pc: r.pc,
+                                                                       instructions: r.instructions,
+                                                                       awaiting_input: r.awaiting_input,
+                                                                       output: interface.take_output() }),
+                        Err(e) => error_response(e)
+                    }
+                },
+                Err(e) => error_response(e)
+            }
+        },
+        Err(e) => error_response(e)
     }
 }
 
 use std::env;
 use std::fs;
-fn main() {
-    simple_logger::init_with_level(log::Level::Debug).unwrap();
-    
-    let args: Vec<String> = env::args().collect();
-    let filename = &args[1];
+use std::path::PathBuf;
+/// Read a command script.  Transcripts can be replayed: only the '>' command lines are read
+fn read_script(path: &str) -> std::io::Result<VecDeque<String>> {
+    let text = fs::read_to_string(path)?;
+    let lines:Vec<&str> = text.lines().collect();
+    if lines.iter().any(|l| l.starts_with('>')) {
+        Ok(lines.iter().filter(|l| l.starts_with('>')).map(|l| String::from(l.trim_start_matches('>'))).collect())
+    } else {
+        Ok(lines.iter().map(|l| String::from(*l)).collect())
+    }
+}
 
-    let bytes = fs::read(filename).unwrap();
-    let mut mem = MemoryMap::try_from(bytes).unwrap();
-    let mut interface = Curses::new();
-    let mut framestack = FrameStack::new(&mut mem).unwrap();
-    let mut pc = framestack.pc();
+/// Report a file that can't be read or written and exit
+fn exit_on_io_error<T>(path: &str, result: std::io::Result<T>) -> T {
+    match result {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1)
+        }
+    }
+}
 
+/// Execute until the story quits, the interface runs out of input, or an error occurs
+fn run_terminal<T: Interface>(framestack: &mut FrameStack, interface: &mut T) -> std::result::Result<(), InfocomError> {
     loop {
-        let mut i = instruction::decode_instruction(&framestack, pc).unwrap();
-        match i.execute(&mut framestack, &mut interface) {
-            Ok(v) => pc = v,
+        match framestack.step(interface) {
+            Ok(StepOutcome::Running) => {},
+            Ok(_) => {
+                interface.flush();
+                return Ok(())
+            },
             Err(e) => {
                 interface.print(&e.to_string());
+                interface.flush();
+                return Err(e)
+            }
+        }
+    }
+}
+
+/// Commands recorded on output stream 4 can be replayed with --script
+fn write_record(record: Option<&String>, interface: &dyn Interface) {
+    if let Some(r) = record {
+        let mut commands = interface.recorded_commands().join("\n");
+        commands.push('\n');
+        exit_on_io_error(r, fs::write(r, commands));
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut log_level = config::log_level();
+    let mut filename = None;
+    let mut script = None;
+    let mut transcript = None;
+    let mut record = None;
+    let mut blorb = None;
+    let mut ui = "curses";
+    let mut server = false;
+    let mut arg = args.iter().skip(1);
+    while let Some(a) = arg.next() {
+        match a.as_str() {
+            "--script" => script = arg.next(),
+            "--transcript" => transcript = arg.next(),
+            "--record" => record = arg.next(),
+            "--blorb" => blorb = arg.next(),
+            "--log" => if let Some(l) = arg.next().and_then(|l| config::parse_log_level(l)) {
+                log_level = l;
+            },
+            "--ui" => if let Some(u) = arg.next() {
+                ui = u.as_str();
+            },
+            "--serve" => server = true,
+            _ => filename = Some(a)
+        }
+    }
+    simple_logger::init_with_level(log_level).unwrap();
+
+    if server {
+        if let Err(e) = serve() {
+            eprintln!("Server error: {}", e);
+            std::process::exit(1)
+        }
+        return
+    }
+
+    let path = filename.expect("Usage: infocom <story> [--ui curses|dumb|test] [--script <file>] [--transcript <file>] [--record <file>] [--blorb <file>] [--log <level>] | infocom --serve");
+    let mut mem = Story::open(StorySource::File(PathBuf::from(path))).unwrap();
+    let mut framestack = FrameStack::new(&mut mem).unwrap();
+    framestack.set_instruction_cache(true);
+    framestack.set_loop_guard(Config::from_env().loop_guard);
+    if let Some(b) = blorb {
+        match Blorb::from_bytes(exit_on_io_error(b, fs::read(b))) {
+            Ok(resources) => framestack.set_resources(resources),
+            Err(e) => {
+                eprintln!("{}: {}", b, e);
+                std::process::exit(1)
+            }
+        }
+    }
+
+    let script = script.map(|s| exit_on_io_error(s, read_script(s)));
+    let transcript = transcript.map(|t| exit_on_io_error(t, fs::File::create(t)));
+    match ui {
+        // Plain line I/O, reading commands from stdin
+        "dumb" => {
+            let mut interface = StdioInterface::new();
+            if let Some(t) = transcript {
+                interface.set_transcript(t);
+            }
+            let _ = run_terminal(&mut framestack, &mut interface);
+            write_record(record, &interface);
+        },
+        // Run the script without a screen and print all of the output at the end
+        "test" => {
+            let mut interface = Buffered::new(script.unwrap_or_default());
+            if let Some(t) = transcript {
+                interface.set_transcript(t);
+            }
+            let _ = run_terminal(&mut framestack, &mut interface);
+            print!("{}", interface.take_output());
+            write_record(record, &interface);
+        },
+        _ => {
+            let mut interface = Curses::new();
+            if let Some(s) = script {
+                interface.set_script(s);
+                interface.input_stream(1);
+            }
+            if let Some(t) = transcript {
+                interface.set_transcript(t);
+            }
+            if run_terminal(&mut framestack, &mut interface).is_err() {
+                // Leave the error on screen until a key is pressed
                 interface.window.get_input();
-                break;
             }
-        }        
-    }
-}
-// #[actix_rt::main]
-// async fn main() -> std::io::Result<()> {
-//     simple_logger::init_with_level(log::Level::Debug).unwrap();
-
-//     let mut listenfd = ListenFd::from_env();
-//     let mut server = HttpServer::new(|| {
-//         App::new()
-//             .service(web::scope("/session")
-//                 .route("/new", web::post().to(new_session))
-//                 .route("", web::get().to(get_session)))
-//             .service(web::scope("/story")
-//                 .route("/{name}/new", web::post().to(new_story)))
-//             .service(web::scope("/memory/{name}")
-//                 .service(web::scope("/byte")
-//                     .route("/{address}", web::get().to(read_byte))
-//                     .route("/{address}/{value}", web::put().to(write_byte)))
-//                 .service(web::scope("/word")
-//                     .route("/{address}", web::get().to(read_word))
-//                     .route("/{address}/{value}", web::put().to(write_word))))
-//             .service(web::scope("/text/{name}")
-//                 .route("/{address}/decode", web::get().to(read_text))
-//                 .route("/encode/{string}", web::get().to(encode_text)))
-//             .route("/object/{name}/tree/{end}", web::get().to(object_tree))
-//             .service(web::scope("/object/{name}/{number}")
-//                 .route("", web::get().to(get_object))
-//                 .route("", web::delete().to(remove_object))
-//                 .route("/{parent}", web::put().to(insert_object))
-//                 .route("/attribute/{attribute}", web::get().to(has_object_attribute))
-//                 .route("/attribute/{attribute}", web::put().to(set_object_attribute))
-//                 .route("/attribute/{attribute}", web::delete().to(clear_object_attribute)) 
-//                 .route("/property/{property}", web::get().to(get_object_property))
-//                 .route("/property/{property}/{value}", web::put().to(put_object_property)))
-//             .service(web::scope("/instruction/{name}/{address}")
-//                 // .route("/decode", web::get().to(instruction))
-//                 // .route("/execute", web::get().to(execute_instruction))
-//                 .route("/run", web::get().to(run)))
-//             .route("routine/{name}/{address}/decode", web::get().to(get_routine))
-//             .wrap(middleware::Performance)
-
-//     });
-
-
-//     server = if let Some(l) = listenfd.take_tcp_listener(0).unwrap() {
-//         server.listen(l)?
-//     } else {
-//         server.bind("127.0.0.1:3000")?
-//     };
+            write_record(record, &interface);
+        }
+    }
+}
 
-//     server.run().await
-// }
+/// The REST API routes, mounted by `serve` and by the API tests so both see the same table
+fn routes(cfg: &mut web::ServiceConfig, max_story_size: usize) {
+    cfg.service(web::scope("/session")
+            .route("/new", web::post().to(new_session))
+            .route("", web::get().to(get_session)))
+        .service(web::scope("/story")
+            .service(web::resource("/{name}/new")
+                // Raise the Bytes extractor limit so oversize uploads are rejected with a 413 here
+                .app_data(web::PayloadConfig::new(max_story_size))
+                .route(web::post().to(new_story)))
+            .route("/{name}/header", web::get().to(get_header))
+            .route("/{name}/abbreviations", web::get().to(get_abbreviations))
+            .route("/{name}/savefile", web::get().to(export_savefile))
+            .route("/{name}/savefile", web::post().to(import_savefile)))
+        .service(web::scope("/memory/{name}")
+            .route("/range/{start}/{len}", web::get().to(read_range))
+            .service(web::scope("/byte")
+                .route("/{address}", web::get().to(read_byte))
+                .route("/{address}/{value}", web::put().to(write_byte)))
+            .service(web::scope("/word")
+                .route("/{address}", web::get().to(read_word))
+                .route("/{address}/{value}", web::put().to(write_word))))
+        .service(web::scope("/text/{name}")
+            .route("/{address}/decode", web::get().to(read_text))
+            .route("/encode/{string}", web::get().to(encode_text)))
+        .route("/dictionary/{name}", web::get().to(get_dictionary))
+        .route("/state/{name}", web::get().to(get_state))
+        .service(web::scope("/globals/{name}")
+            .route("", web::get().to(get_globals))
+            .route("/{index}/{value}", web::put().to(set_global)))
+        .route("/object/{name}/tree/{end}", web::get().to(object_tree))
+        .service(web::scope("/object/{name}/{number}")
+            .route("", web::get().to(get_object))
+            .route("", web::delete().to(remove_object))
+            .route("/{parent}", web::put().to(insert_object))
+            .route("/attribute/{attribute}", web::get().to(has_object_attribute))
+            .route("/attribute/{attribute}", web::put().to(set_object_attribute))
+            .route("/attribute/{attribute}", web::delete().to(clear_object_attribute))
+            .route("/property/{property}", web::get().to(get_object_property))
+            .route("/property/{property}/{value}", web::put().to(put_object_property)))
+        .service(web::scope("/instruction/{name}/{address}")
+            .route("/run", web::post().to(run)))
+        .route("routine/{name}/{address}/decode", web::get().to(get_routine));
+}
+
+/// Serve the REST API on port 3000, or a socket passed in by listenfd.  `main` only runs
+/// this with --serve; the server used to be commented out, leaving the endpoints unreachable.
+fn serve() -> std::io::Result<()> {
+    let mut system = actix_rt::System::new("infocom");
+    system.block_on(async move {
+        // One connection pool is shared by all workers
+        let config = Config::from_env();
+        let pool = match RedisPool::new(&config) {
+            Ok(p) => p,
+            Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::Other, e))
+        };
+
+        let mut listenfd = ListenFd::from_env();
+        let mut server = HttpServer::new(move || {
+            let max_story_size = config.max_story_size;
+            App::new()
+                .data(pool.clone())
+                .data(config.clone())
+                .configure(|cfg| routes(cfg, max_story_size))
+                .wrap(middleware::Performance)
+        });
+
+        server = if let Some(l) = listenfd.take_tcp_listener(0)? {
+            server.listen(l)?
+        } else {
+            server.bind("127.0.0.1:3000")?
+        };
+
+        server.run().await
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+    use components::test_util::{pack_zchars, zstring, StoryBuilder, TestInterface, CODE};
+    use serde_json::{json, Value};
+
+    /// A pool for the redis server named by `REDIS_URL`.  Tests that use it are ignored by
+    /// default and run with `cargo test -- --ignored` when a server is available.
+    fn redis_pool() -> RedisPool {
+        RedisPool::new(&Config::from_env()).unwrap()
+    }
+
+    /// A pool that is never connected to, for requests rejected before redis is used
+    fn unconnected_pool() -> RedisPool {
+        RedisPool::new(&Config::from_env()).unwrap()
+    }
+
+    /// Start a session holding `mem` as the story "test", returning the session id
+    fn session_with_story(pool: &RedisPool, mem: MemoryMap) -> String {
+        let mut session = Session::new(pool).unwrap();
+        session.add_story(pool, String::from("test"), mem).unwrap();
+        session.id.clone()
+    }
+
+    /// Send `request` to the API, returning the response status and JSON body
+    async fn call(pool: &RedisPool, request: test::TestRequest) -> (StatusCode, Value) {
+        let config = Config::from_env();
+        let max_story_size = config.max_story_size;
+        let mut app = test::init_service(App::new().data(pool.clone()).data(config).configure(|cfg| routes(cfg, max_story_size))).await;
+        let response = test::call_service(&mut app, request.to_request()).await;
+        let status = response.status();
+        let body = test::read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    fn get(uri: &str, id: &str) -> test::TestRequest {
+        test::TestRequest::get().uri(uri).header("X-Session", id)
+    }
+
+    fn put(uri: &str, id: &str) -> test::TestRequest {
+        test::TestRequest::put().uri(uri).header("X-Session", id)
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("infocom-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn transcript_records_scripted_turns() {
+        // Print a line, read a command into $0700, storing the terminator in G00, and loop
+        let mut code = vec![0xB2];
+        code.extend_from_slice(&zstring(3, "You see a mailbox."));
+        code.extend_from_slice(&[0xBB, 0xE4, 0x0F, 0x07, 0x00, 0x07, 0x80, 0x10]);
+        let offset = (2 - (code.len() as i16 + 3)) as u16;
+        code.extend_from_slice(&[0x8C, (offset >> 8) as u8, offset as u8]);
+        let mut mem = StoryBuilder::new(5).byte(0x700, 20).byte(0x780, 5).code(&code).build();
+        let mut framestack = FrameStack::new(&mut mem).unwrap();
+        assert_eq!(framestack.pc(), CODE);
+
+        let path = temp_path("transcript.txt");
+        let mut interface = Buffered::new(VecDeque::from(vec![String::from("open mailbox"), String::from("read leaflet")]));
+        interface.set_transcript(fs::File::create(&path).unwrap());
+        run_terminal(&mut framestack, &mut interface).unwrap();
+        drop(interface);
+
+        let transcript = fs::read_to_string(&path).unwrap();
+        assert_eq!(transcript, "You see a mailbox.\n>open mailbox\nYou see a mailbox.\n>read leaflet\nYou see a mailbox.\n");
+
+        // The transcript replays as a script
+        let script = read_script(path.to_str().unwrap()).unwrap();
+        assert_eq!(script, vec![String::from("open mailbox"), String::from("read leaflet")]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_print_burst_is_flushed_once() {
+        // Print two lines a word at a time, then quit
+        let mut code = Vec::new();
+        for word in &["You ", "see ", "a ", "mailbox."] {
+            code.push(0xB2);
+            code.extend_from_slice(&zstring(3, word));
+        }
+        code.extend_from_slice(&[0xBB, 0xB2]);
+        code.extend_from_slice(&zstring(3, "It is closed."));
+        code.extend_from_slice(&[0xBB, 0xBA]);
+        let mut mem = StoryBuilder::new(3).code(&code).build();
+        let mut framestack = FrameStack::new(&mut mem).unwrap();
+        let mut interface = TestInterface::new();
+        run_terminal(&mut framestack, &mut interface).unwrap();
+        assert_eq!(interface.output, "You see a mailbox.\nIt is closed.\n");
+        assert_eq!(interface.flushes, 1);
+    }
+
+    #[test]
+    fn object_views_list_the_set_attributes() {
+        let mem = StoryBuilder::new(3).object(1, &[0, 31], (0, 0, 0), "box", &[]).build();
+        let object = ObjectTable::new(&mem).unwrap().get_object(&mem, 1).unwrap();
+        let view = serde_json::to_value(ObjectView { object: &object, attribute_list: object.attributes_as_vec() }).unwrap();
+        assert_eq!(view["attribute_list"], json!([0, 31]));
+        assert_eq!(view["attributes"], 0x80000001u64);
+        assert_eq!(view["number"], 1);
+    }
+
+    #[test]
+    fn read_script_reports_a_missing_file() {
+        assert!(read_script(temp_path("missing.txt").to_str().unwrap()).is_err());
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs a redis server"]
+    async fn set_global_is_read_back_by_get_globals() {
+        let pool = redis_pool();
+        let id = session_with_story(&pool, StoryBuilder::new(3).global(5, 0x1234).build());
+
+        let (status, globals) = call(&pool, get("/globals/test", &id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(globals.as_array().unwrap().len(), 240);
+        assert_eq!(globals[5], json!({ "index": 5, "value": 0x1234 }));
+
+        let (status, global) = call(&pool, put("/globals/test/239/65535", &id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(global, json!({ "index": 239, "value": 65535 }));
+
+        let (_, globals) = call(&pool, get("/globals/test", &id)).await;
+        assert_eq!(globals[239], json!({ "index": 239, "value": 65535 }));
+        assert_eq!(globals[5], json!({ "index": 5, "value": 0x1234 }));
+    }
+
+    #[actix_rt::test]
+    async fn set_global_rejects_invalid_indexes_and_values() {
+        let pool = unconnected_pool();
+        for uri in &["/globals/test/240/1", "/globals/test/256/1", "/globals/test/1/65536", "/globals/test/x/1"] {
+            let (status, error) = call(&pool, put(uri, "id")).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+            assert_eq!(error["kind"], "API");
+        }
+    }
+
+    #[actix_rt::test]
+    async fn requests_without_a_session_header_are_rejected() {
+        let pool = unconnected_pool();
+        let requests = vec![test::TestRequest::get().uri("/session"),
+                            test::TestRequest::get().uri("/memory/test/byte/64"),
+                            test::TestRequest::put().uri("/memory/test/word/64/1"),
+                            test::TestRequest::get().uri("/memory/test/range/0/16"),
+                            test::TestRequest::get().uri("/text/test/64/decode"),
+                            test::TestRequest::get().uri("/object/test/1"),
+                            test::TestRequest::put().uri("/object/test/1/attribute/2"),
+                            test::TestRequest::get().uri("/object/test/tree/10"),
+                            test::TestRequest::get().uri("/story/test/header"),
+                            test::TestRequest::get().uri("/state/test"),
+                            test::TestRequest::put().uri("/globals/test/1/2"),
+                            test::TestRequest::post().uri("/instruction/test/4096/run")];
+        for request in requests {
+            let (status, error) = call(&pool, request).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(error, json!({ "kind": "API", "message": "Missing session id" }));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn malformed_path_parameters_are_rejected() {
+        let pool = unconnected_pool();
+        let requests = vec![(get("/memory/test/byte/x", "id"), "Invalid address"),
+                            (put("/memory/test/byte/64/256", "id"), "Invalid value"),
+                            (get("/object/test/one", "id"), "Invalid number"),
+                            (put("/object/test/1/property/-1/2", "id"), "Invalid property")];
+        for (request, message) in requests {
+            let (status, error) = call(&pool, request).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", message);
+            assert_eq!(error["message"], message);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn oversize_uploads_are_rejected() {
+        let pool = unconnected_pool();
+        let upload = vec![0; Config::from_env().max_story_size + 1];
+        let (status, _) = call(&pool, test::TestRequest::post().uri("/story/test/new").header("X-Session", "id").set_payload(upload)).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Stories over a lower configured limit are rejected before they are parsed
+        let story = StoryBuilder::new(3).byte(0x1000, 0).data();
+        let config = Config { max_story_size: 0x800, ..Config::default() };
+        assert_eq!(validate_story(&config, story.clone()).err().unwrap().status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(validate_story(&Config::default(), story.clone()).is_ok());
+
+        // A story shorter than its header's declared length is truncated
+        let mut truncated = story;
+        truncated.truncate(0xC00);
+        assert_eq!(validate_story(&Config::default(), truncated).err().unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs a redis server"]
+    async fn get_state_returns_the_call_stack_left_by_run() {
+        let pool = redis_pool();
+        // call_vs $1100 1 2 -> sp; quit, to a routine with 3 locals that reads a line and returns 7
+        let story = StoryBuilder::new(5)
+            .code(&[0xE0, 0x17, 0x04, 0x40, 0x01, 0x02, 0x00, 0xBA])
+            .routine(0x1100, &[0, 0, 0], &[0xE4, 0x0F, 0x02, 0x00, 0x02, 0x80, 0x00, 0x9B, 0x07])
+            .byte(0x200, 20)
+            .byte(0x280, 4)
+            .build();
+        let id = session_with_story(&pool, story);
+
+        let (status, state) = call(&pool, get("/state/test", &id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(state["depth"], 0);
+        assert_eq!(state["pc"], CODE);
+
+        let (_, result) = call(&pool, test::TestRequest::post().uri("/instruction/test/4096/run").header("X-Session", id.as_str())).await;
+        assert_eq!(result["awaiting_input"], true);
+        assert_eq!(result["pc"], 0x1101);
+
+        let (status, state) = call(&pool, get("/state/test", &id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(state["pc"], 0x1101);
+        assert_eq!(state["depth"], 1);
+        let frames = state["frames"].as_array().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1]["local_variables"], json!([1, 2, 0]));
+        assert_eq!(frames[1]["return_address"], CODE + 7);
+
+        // The next run resumes inside the routine and returns to the main routine
+        let (_, result) = call(&pool, test::TestRequest::post().uri("/instruction/test/4353/run").header("X-Session", id.as_str()).set_payload("look")).await;
+        assert_eq!(result["pc"], CODE + 7);
+        let (_, state) = call(&pool, get("/state/test", &id)).await;
+        assert_eq!(state["depth"], 0);
+        assert_eq!(state["frames"][0]["stack"], json!([7]));
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs a redis server"]
+    async fn run_returns_the_output_and_reads_the_request_body() {
+        let pool = redis_pool();
+        // print "hi"; new_line; sread $700 $780; print "ok"; jump to the start
+        let mut code = vec![0xB2];
+        code.extend(zstring(3, "hi"));
+        code.push(0xBB);
+        let sread = CODE + code.len();
+        code.extend_from_slice(&[0xE4, 0x0F, 0x07, 0x00, 0x07, 0x80, 0xB2]);
+        code.extend(zstring(3, "ok"));
+        let offset = (CODE as i32 - (CODE + code.len() + 3) as i32 + 2) as u16;
+        code.extend_from_slice(&[0x8C, (offset >> 8) as u8, offset as u8]);
+        let id = session_with_story(&pool, StoryBuilder::new(3).byte(0x700, 20).byte(0x780, 5).code(&code).build());
+
+        let (status, result) = call(&pool, test::TestRequest::post().uri("/instruction/test/4096/run").header("X-Session", id.as_str())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(result["output"], "hi\n");
+        assert_eq!(result["awaiting_input"], true);
+        assert_eq!(result["pc"], sread);
+
+        let uri = format!("/instruction/test/{}/run", sread);
+        let (_, result) = call(&pool, test::TestRequest::post().uri(&uri).header("X-Session", id.as_str()).set_payload("look")).await;
+        // The command is echoed as it is read
+        assert_eq!(result["output"], "look\nokhi\n");
+        assert_eq!(result["awaiting_input"], true);
+
+        let (_, text) = call(&pool, get("/memory/test/range/1793/4", &id)).await;
+        assert_eq!(text["data"], "6c6f6f6b");
+    }
+
+    #[test]
+    fn build_tree_entry_stops_at_cycles() {
+        // Objects 2 and 3 are each other's siblings, and 3 lists the root as its child
+        let mem = StoryBuilder::new(3)
+            .object(1, &[], (0, 0, 2), "root", &[])
+            .object(2, &[], (1, 3, 0), "two", &[])
+            .object(3, &[], (1, 2, 1), "three", &[])
+            .build();
+        let ot = ObjectTable::new(&mem).unwrap();
+        let mut placed = HashSet::new();
+        placed.insert(1);
+        let mut warnings = Vec::new();
+        let entry = build_tree_entry(&mem, &ot, 1, DEFAULT_TREE_DEPTH, &mut placed, &mut warnings).unwrap();
+
+        assert_eq!(entry.children.iter().map(|c| c.number).collect::<Vec<u16>>(), vec![2, 3]);
+        assert!(entry.children[1].children.is_empty());
+        assert_eq!(warnings, vec!["Cycle detected at object 1 under object 3", "Cycle detected at object 2 under object 1"]);
+    }
+
+    #[test]
+    fn build_tree_entry_stops_at_the_depth_limit() {
+        let mem = StoryBuilder::new(3)
+            .object(1, &[], (0, 0, 2), "root", &[])
+            .object(2, &[], (1, 0, 3), "two", &[])
+            .object(3, &[], (2, 0, 0), "three", &[])
+            .build();
+        let ot = ObjectTable::new(&mem).unwrap();
+        let mut warnings = Vec::new();
+        let entry = build_tree_entry(&mem, &ot, 1, 1, &mut HashSet::new(), &mut warnings).unwrap();
+
+        assert_eq!(entry.children.len(), 1);
+        assert!(entry.children[0].children.is_empty());
+        assert_eq!(warnings, vec!["Depth limit reached at object 2"]);
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs a redis server"]
+    async fn get_header_describes_an_uploaded_story() {
+        let pool = redis_pool();
+        let (status, session) = call(&pool, test::TestRequest::post().uri("/session/new")).await;
+        assert_eq!(status, StatusCode::OK);
+        let id = session["id"].as_str().unwrap();
+
+        let story = StoryBuilder::new(3).word(0x02, 88).word(0x1C, 0x1234).data();
+        let (status, _) = call(&pool, test::TestRequest::post().uri("/story/zork/new").header("X-Session", id).set_payload(story)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, header) = call(&pool, get("/story/zork/header", id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(header["version"], 3);
+        assert_eq!(header["release"], 88);
+        assert_eq!(header["serial"], "200101");
+        assert_eq!(header["checksum"], 0x1234);
+        assert_eq!(header["static_start"], 0x800);
+        assert_eq!(header["dictionary"], 0x600);
+        assert_eq!(header["object_table"], 0x300);
+        assert_eq!(header["globals"], 0x100);
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs a redis server"]
+    async fn missing_sessions_and_stories_are_not_found() {
+        let pool = redis_pool();
+        let id = session_with_story(&pool, StoryBuilder::new(3).build());
+
+        let (status, error) = call(&pool, get("/story/zork/header", &id)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error["message"], "No story named 'zork'");
+        let (status, _) = call(&pool, get("/object/test/1", "no-such-session")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = call(&pool, get("/text/test/encode/hello", "no-such-session")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn an_unreachable_redis_is_unavailable_rather_than_not_found() {
+        let pool = RedisPool::new(&Config { redis_url: String::from("redis://127.0.0.1:1/"), ..Config::from_env() }).unwrap();
+        for uri in &["/story/test/header", "/text/test/1000/decode", "/object/test/1", "/memory/test/range/0/4"] {
+            let (status, error) = call(&pool, get(uri, "1")).await;
+            assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", uri);
+            assert_eq!(error["kind"], "Redis");
+        }
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs a redis server"]
+    async fn get_abbreviations_decodes_all_96_entries() {
+        let pool = redis_pool();
+        // Abbreviations 0 and 33 are defined, the rest are empty strings
+        let mut story = StoryBuilder::new(3)
+            .word(0x18, 0x700)
+            .bytes(0x900, &zstring(3, "the "))
+            .bytes(0x910, &zstring(3, "lamp"))
+            .word(0x920, 0x94A5);
+        for i in 0..96 {
+            let address = match i { 0 => 0x900, 33 => 0x910, _ => 0x920 };
+            story = story.word(0x700 + (i * 2), address / 2);
+        }
+        let id = session_with_story(&pool, story.build());
+
+        let (status, abbreviations) = call(&pool, get("/story/test/abbreviations", &id)).await;
+        assert_eq!(status, StatusCode::OK);
+        let abbreviations = abbreviations.as_array().unwrap();
+        assert_eq!(abbreviations.len(), 96);
+        assert_eq!(abbreviations[0], json!({ "table": 1, "index": 0, "text": "the " }));
+        assert_eq!(abbreviations[33], json!({ "table": 2, "index": 1, "text": "lamp" }));
+        assert!(abbreviations.iter().enumerate().all(|(i, a)| i == 0 || i == 33 || a["text"] == ""));
+        assert_eq!(abbreviations[95]["table"], 3);
+        assert_eq!(abbreviations[95]["index"], 31);
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs a redis server"]
+    async fn decoded_length_counts_the_encoded_bytes() {
+        let pool = redis_pool();
+        // Abbreviation 0 followed by "lamp", in 4 bytes
+        let story = StoryBuilder::new(3)
+            .word(0x18, 0x700)
+            .word(0x700, 0x900 / 2)
+            .bytes(0x900, &zstring(3, "the "))
+            .bytes(0xA00, &pack_zchars(&[1, 0, 17, 6, 18, 21]));
+        let id = session_with_story(&pool, story.build());
+
+        let (status, text) = call(&pool, get("/text/test/2560/decode?detail=true", &id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(text["text"], "the lamp");
+        assert_eq!(text["byte_length"], 4);
+        assert_eq!(text["zchars"], json!([1, 0, 17, 6, 18, 21]));
+
+        let (_, text) = call(&pool, get("/text/test/2560/decode", &id)).await;
+        assert_eq!(text, "the lamp");
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs a redis server"]
+    async fn read_range_matches_single_byte_reads() {
+        let pool = redis_pool();
+        let data:Vec<u8> = (0..64).map(|i| (i * 7) as u8).collect();
+        let id = session_with_story(&pool, StoryBuilder::new(3).bytes(0x1040, &data).byte(0x10020, 0xAB).build());
+
+        let (status, range) = call(&pool, get("/memory/test/range/4160/64", &id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(range["start"], 0x1040);
+        assert_eq!(range["len"], 64);
+        let hex = range["data"].as_str().unwrap();
+        for i in 0..64 {
+            let (_, byte) = call(&pool, get(&format!("/memory/test/byte/{}", 0x1040 + i), &id)).await;
+            assert_eq!(byte["value"].as_u64().unwrap(), u64::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap());
+        }
+
+        // High memory above 64k is reachable, and long reads are capped
+        let (_, range) = call(&pool, get("/memory/test/range/65568/1", &id)).await;
+        assert_eq!(range["data"], "ab");
+        let (_, range) = call(&pool, get("/memory/test/range/0/5000", &id)).await;
+        assert_eq!(range["len"], MAX_MEMORY_RANGE);
+
+        let (status, error) = call(&pool, get("/memory/test/range/65568/64", &id)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["kind"], "ReadViolation");
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs a redis server"]
+    async fn reads_past_the_end_of_memory_are_read_violations() {
+        let pool = redis_pool();
+        let id = session_with_story(&pool, StoryBuilder::new(3).build());
+
+        let (status, error) = call(&pool, get("/memory/test/word/8191", &id)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["kind"], "ReadViolation");
+        assert_eq!(error["address"], 8192);
+
+        // A range ending beyond usize::MAX is a violation rather than an overflow
+        let (status, error) = call(&pool, get(&format!("/memory/test/range/{}/16", usize::MAX - 7), &id)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["kind"], "ReadViolation");
+        let (status, error) = call(&pool, get("/memory/test/range/8190/4", &id)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["address"], 8194);
+    }
+
+    #[test]
+    fn error_responses_carry_the_error_kind_and_address() {
+        let e = serde_json::to_value(ErrorResponse::from(InfocomError::WriteViolation(0x900, 0x800))).unwrap();
+        assert_eq!(e, json!({ "kind": "WriteViolation", "message": "Invalid write to $000900 beyond end of dynamic memory $000800", "address": 0x900 }));
+
+        // Errors without an address leave it out
+        let e = serde_json::to_value(ErrorResponse::from(InfocomError::Text(String::from("Bad text")))).unwrap();
+        assert_eq!(e, json!({ "kind": "Text", "message": "Bad text" }));
+
+        assert_eq!(error_response(InfocomError::ReadViolation(0, 0)).status(), StatusCode::BAD_REQUEST);
+        assert_eq!(error_response(InfocomError::API(String::new())).status(), StatusCode::BAD_REQUEST);
+        assert_eq!(error_response(InfocomError::Version(Version::V(9))).status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error_response(InfocomError::Memory(String::new())).status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(error_response(InfocomError::Session(String::new())).status(), StatusCode::NOT_FOUND);
+
+        // Opcodes the interpreter doesn't support are reported with their address
+        let unimplemented = || InfocomError::Unimplemented { opcode: String::from("$00 ($00)"), address: 0x1000 };
+        assert_eq!(error_response(unimplemented()).status(), StatusCode::NOT_IMPLEMENTED);
+        let e = serde_json::to_value(ErrorResponse::from(unimplemented())).unwrap();
+        assert_eq!(e, json!({ "kind": "Unimplemented", "message": "Unimplemented instruction $00 ($00) at $001000", "address": 0x1000 }));
+    }
+}